@@ -0,0 +1,13 @@
+-- Create auth audit table
+CREATE TABLE IF NOT EXISTS auth_audit (
+    id UUID PRIMARY KEY,
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    event VARCHAR(50) NOT NULL,
+    ip VARCHAR(64) NOT NULL,
+    success BOOLEAN NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create indexes
+CREATE INDEX idx_auth_audit_created ON auth_audit(created_at);
+CREATE INDEX idx_auth_audit_user_created ON auth_audit(user_id, created_at);
@@ -0,0 +1,11 @@
+-- Create outbox for messages pushed to users while they had no live connection
+CREATE TABLE IF NOT EXISTS outbox_messages (
+    id BIGSERIAL PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    payload JSONB NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Create indexes
+CREATE INDEX idx_outbox_messages_user_id ON outbox_messages(user_id, id);
+CREATE INDEX idx_outbox_messages_created ON outbox_messages(created_at);
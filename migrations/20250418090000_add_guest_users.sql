@@ -0,0 +1,4 @@
+-- Transient users behind anonymous guest sessions
+ALTER TABLE users ADD COLUMN IF NOT EXISTS is_guest BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS idx_users_is_guest ON users(is_guest) WHERE is_guest;
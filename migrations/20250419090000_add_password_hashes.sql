@@ -0,0 +1,3 @@
+-- Argon2 PHC strings for user passwords. Guests, and users created before
+-- passwords were stored, have none and cannot log in with a password.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS password_hash TEXT;
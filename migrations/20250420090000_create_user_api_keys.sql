@@ -0,0 +1,9 @@
+-- Provider API keys stored by users, encrypted with proxy.encryption_key
+CREATE TABLE IF NOT EXISTS user_api_keys (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    encrypted_data TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
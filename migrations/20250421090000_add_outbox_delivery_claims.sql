@@ -0,0 +1,3 @@
+-- Messages are claimed while being replayed and only deleted once delivered,
+-- so a connection that dies mid-replay leaves them to be delivered again
+ALTER TABLE outbox_messages ADD COLUMN claimed_at TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,7 @@
+-- Lifetime usage counters per user, written in batches by the server
+CREATE TABLE IF NOT EXISTS user_stats (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    query_count BIGINT NOT NULL DEFAULT 0,
+    last_query_at TIMESTAMP WITH TIME ZONE,
+    updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
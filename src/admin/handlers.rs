@@ -0,0 +1,213 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use tracing::info;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::auth::handlers::audit_ip;
+use crate::auth::middleware::require_admin;
+use crate::auth::password::validate_password_strength;
+use crate::error::Error;
+use crate::middleware::ValidatedJson;
+use crate::websocket::ServerMessage;
+use crate::AppState;
+
+/// Headline counts for dashboards
+pub async fn admin_stats(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &state.config.admin)?;
+
+    let (active_users, active_sessions) =
+        futures::try_join!(state.db.count_active_users(), state.db.count_active_sessions())?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "active_users": active_users,
+        "active_sessions": active_sessions,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    })))
+}
+
+/// A user's lifetime query count, including queries not yet flushed to the
+/// database, and the connections they have open to this instance
+pub async fn user_stats(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &state.config.admin)?;
+
+    let user_id = path.into_inner();
+    if state.db.get_user_by_id(user_id).await?.is_none() {
+        return Err(Error::NotFound(format!("User {} not found", user_id)));
+    }
+
+    let stored = state.db.get_user_stats(user_id).await?;
+    let pending = state.ws_server.query_stats().pending(user_id).await;
+    let query_count = stored.as_ref().map_or(0, |s| s.query_count) + pending.map_or(0, |p| p.count);
+    let last_query_at = pending.map(|p| p.last_at).or(stored.and_then(|s| s.last_query_at));
+    let connections = state.ws_server.pool().user_connection_count(&user_id).await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "user_id": user_id,
+        "query_count": query_count,
+        "last_query_at": last_query_at,
+        "connections": connections,
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceRequest {
+    pub enabled: bool,
+}
+
+/// Turn read-only maintenance mode on or off for this instance
+pub async fn set_maintenance(
+    req: HttpRequest,
+    body: web::Json<MaintenanceRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &state.config.admin)?;
+
+    let previous = state.maintenance.set(body.enabled);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "maintenance_mode": body.enabled,
+        "previous": previous,
+    })))
+}
+
+/// Disable a user's account: they can no longer log in, and their sessions and
+/// live WebSocket connections are ended
+pub async fn deactivate_user(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    set_user_active(req, path.into_inner(), false, state).await
+}
+
+/// Re-enable an account disabled by [`deactivate_user`]
+pub async fn activate_user(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    set_user_active(req, path.into_inner(), true, state).await
+}
+
+async fn set_user_active(
+    req: HttpRequest,
+    user_id: Uuid,
+    active: bool,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &state.config.admin)?;
+
+    let sessions_ended = state.db.set_user_active(user_id, active).await?
+        .ok_or_else(|| Error::NotFound(format!("User {} not found", user_id)))?;
+    let connections_closed = if active { 0 } else { state.ws_server.pool().disconnect_user(&user_id).await };
+
+    info!(
+        "Admin {} user {} ({} sessions, {} connections ended)",
+        if active { "activated" } else { "deactivated" },
+        user_id,
+        sessions_ended,
+        connections_closed
+    );
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "id": user_id,
+        "is_active": active,
+        "sessions_ended": sessions_ended,
+        "connections_closed": connections_closed,
+    })))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ResetPasswordRequest {
+    #[validate(custom(function = "validate_password_strength"))]
+    pub new_password: String,
+}
+
+/// Set a user's password and log out all of their sessions, e.g. for accounts
+/// created before passwords were stored, which cannot otherwise log in
+pub async fn reset_user_password(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    body: ValidatedJson<ResetPasswordRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &state.config.admin)?;
+    state.maintenance.ensure_writable()?;
+
+    let user_id = path.into_inner();
+    let sessions_ended = state.auth_service.reset_password(user_id, &body.new_password, &audit_ip(&req, &state)).await?
+        .ok_or_else(|| Error::NotFound(format!("User {} not found", user_id)))?;
+
+    info!("Admin reset the password of user {} ({} sessions ended)", user_id, sessions_ended);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "id": user_id,
+        "sessions_ended": sessions_ended,
+    })))
+}
+
+/// Severities a notification may carry, least to most urgent
+pub const NOTIFICATION_LEVELS: [&str; 3] = ["info", "warning", "critical"];
+
+fn validate_notification_level(level: &str) -> Result<(), ValidationError> {
+    if NOTIFICATION_LEVELS.contains(&level) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("level").with_message("Level must be info, warning or critical".into()))
+    }
+}
+
+fn default_notification_level() -> String {
+    "info".to_string()
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct NotificationRequest {
+    /// Recipient; every connected client when omitted
+    pub user_id: Option<Uuid>,
+    #[serde(default = "default_notification_level")]
+    #[validate(custom(function = "validate_notification_level"))]
+    pub level: String,
+    #[validate(length(min = 1, max = 200, message = "Title must be 1-200 characters"))]
+    pub title: String,
+    #[validate(length(min = 1, max = 4000, message = "Body must be 1-4000 characters"))]
+    pub body: String,
+}
+
+/// Send a notification to one user, queued until they reconnect if they
+/// are offline, or broadcast it to every live connection
+pub async fn send_notification(
+    req: HttpRequest,
+    body: ValidatedJson<NotificationRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &state.config.admin)?;
+
+    let NotificationRequest { user_id, level, title, body } = body.into_inner();
+    let notification = ServerMessage::Notification { level, title, body };
+
+    let Some(user_id) = user_id else {
+        let payload = serde_json::to_string(&notification)
+            .map_err(|e| Error::External(format!("Failed to serialize message: {}", e)))?;
+        let result = state.ws_server.pool().broadcast(&payload, None, false).await;
+        info!("Admin broadcast a notification to {} connections", result.delivered);
+        return Ok(HttpResponse::Ok().json(result));
+    };
+
+    if state.db.get_user_by_id(user_id).await?.is_none() {
+        return Err(Error::NotFound(format!("User {} not found", user_id)));
+    }
+    let delivered = state.ws_server.send_to_user(user_id, &notification).await?;
+    info!("Admin sent a notification to user {} ({})", user_id, if delivered { "delivered" } else { "queued" });
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "user_id": user_id,
+        "delivered": delivered,
+        "queued": !delivered,
+    })))
+}
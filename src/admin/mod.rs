@@ -0,0 +1,7 @@
+//! Operator endpoints for BuddyBot server
+//!
+//! Every handler here requires the configured admin token.
+
+pub mod handlers;
+
+pub use handlers::{activate_user, admin_stats, deactivate_user, reset_user_password, send_notification, set_maintenance, user_stats};
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+use tracing::{debug, instrument};
+
+use crate::db::DbOperations;
+use crate::error::Error;
+
+/// Where batched session activity is written
+#[async_trait]
+pub trait ActivityStore: Send + Sync {
+    /// Set `last_activity` for each token to the matching timestamp in one write
+    async fn touch_sessions(&self, tokens: &[String], seen_at: &[DateTime<Utc>]) -> Result<u64, Error>;
+}
+
+#[async_trait]
+impl ActivityStore for DbOperations {
+    async fn touch_sessions(&self, tokens: &[String], seen_at: &[DateTime<Utc>]) -> Result<u64, Error> {
+        self.update_sessions_activity(tokens, seen_at).await
+    }
+}
+
+/// Collects session activity in memory and writes it out in batches, so token
+/// validation costs no write per request. `last_activity` may lag by up to one
+/// flush interval, which anything reading it has to tolerate.
+pub struct SessionActivity {
+    store: Arc<dyn ActivityStore>,
+    pending: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl SessionActivity {
+    pub fn new(store: Arc<dyn ActivityStore>) -> Self {
+        Self {
+            store,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Note that `token` was just used; only the latest use is kept
+    pub async fn touch(&self, token: &str) {
+        self.pending.lock().await.insert(token.to_string(), Utc::now());
+    }
+
+    /// Number of sessions waiting to be written
+    pub async fn pending(&self) -> usize {
+        self.pending.lock().await.len()
+    }
+
+    /// Write all pending activity in one statement, returning how many sessions
+    /// were flushed. On failure the activity is kept for the next flush.
+    #[instrument(skip_all)]
+    pub async fn flush(&self) -> Result<usize, Error> {
+        let batch = std::mem::take(&mut *self.pending.lock().await);
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        let (tokens, seen_at): (Vec<String>, Vec<DateTime<Utc>>) = batch.iter()
+            .map(|(token, at)| (token.clone(), *at))
+            .unzip();
+
+        if let Err(e) = self.store.touch_sessions(&tokens, &seen_at).await {
+            // Keep anything touched again since the batch was taken
+            let mut pending = self.pending.lock().await;
+            for (token, at) in batch {
+                pending.entry(token).or_insert(at);
+            }
+            return Err(e);
+        }
+
+        debug!("Flushed activity for {} sessions", tokens.len());
+        Ok(tokens.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct CountingStore {
+        writes: AtomicUsize,
+        fail: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait]
+    impl ActivityStore for CountingStore {
+        async fn touch_sessions(&self, tokens: &[String], _seen_at: &[DateTime<Utc>]) -> Result<u64, Error> {
+            self.writes.fetch_add(1, Ordering::SeqCst);
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(Error::External("store unavailable".into()));
+            }
+            Ok(tokens.len() as u64)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_touches_coalesce_into_one_write() {
+        let store = Arc::new(CountingStore::default());
+        let activity = SessionActivity::new(store.clone());
+
+        for n in 0..100 {
+            activity.touch(&format!("token-{}", n % 3)).await;
+        }
+        assert_eq!(activity.pending().await, 3);
+
+        assert_eq!(activity.flush().await.unwrap(), 3);
+        assert_eq!(store.writes.load(Ordering::SeqCst), 1);
+
+        // Nothing pending means no write at all
+        assert_eq!(activity.flush().await.unwrap(), 0);
+        assert_eq!(store.writes.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_is_retried() {
+        let store = Arc::new(CountingStore::default());
+        let activity = SessionActivity::new(store.clone());
+        activity.touch("token").await;
+
+        store.fail.store(true, Ordering::SeqCst);
+        assert!(activity.flush().await.is_err());
+        assert_eq!(activity.pending().await, 1);
+
+        store.fail.store(false, Ordering::SeqCst);
+        assert_eq!(activity.flush().await.unwrap(), 1);
+    }
+}
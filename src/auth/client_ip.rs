@@ -0,0 +1,157 @@
+use actix_web::HttpRequest;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::config::ForwardedHeader;
+
+/// Resolve the address of the client behind this request. The forwarding
+/// header is only consulted when `trust_proxy` is set, since any client can
+/// send it; enable it only when a proxy in front of us appends to `header`.
+/// The other forwarding header is ignored, as a proxy that doesn't write it
+/// passes through whatever the client sent.
+///
+/// Only the last hop is used: it was added by our proxy, while anything before
+/// it came from the client and may be forged.
+pub fn client_ip(req: &HttpRequest, trust_proxy: bool, header: ForwardedHeader) -> Option<IpAddr> {
+    if trust_proxy {
+        let (name, parse): (&str, fn(&str) -> Option<IpAddr>) = match header {
+            ForwardedHeader::XForwardedFor => ("X-Forwarded-For", parse_x_forwarded_for),
+            ForwardedHeader::Forwarded => ("Forwarded", parse_forwarded),
+        };
+        let forwarded = req.headers()
+            .get_all(name)
+            .last()
+            .and_then(|h| h.to_str().ok())
+            .and_then(parse);
+        if forwarded.is_some() {
+            return forwarded;
+        }
+    }
+
+    req.peer_addr().map(|addr| addr.ip())
+}
+
+/// Address our proxy saw, the last hop of `X-Forwarded-For: client, proxy1, proxy2`
+pub fn parse_x_forwarded_for(value: &str) -> Option<IpAddr> {
+    value.rsplit(',').next().and_then(parse_node)
+}
+
+/// Address our proxy saw, from the last element's `for=` in an RFC 7239 `Forwarded` header
+pub fn parse_forwarded(value: &str) -> Option<IpAddr> {
+    value.rsplit(',')
+        .next()?
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+        .and_then(|(_, node)| parse_node(node))
+}
+
+/// Parse a single node: a bare IP, `ip:port`, or `"[v6]:port"`, optionally quoted
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    node.parse::<IpAddr>().ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|rest| rest.split(']').next())
+                .and_then(|ip| ip.parse().ok())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn peer() -> SocketAddr {
+        "10.0.0.1:40000".parse().unwrap()
+    }
+
+    #[test]
+    fn test_x_forwarded_for_multiple_hops() {
+        assert_eq!(
+            parse_x_forwarded_for("10.0.0.2, 10.0.0.3, 203.0.113.7"),
+            Some("203.0.113.7".parse().unwrap())
+        );
+        assert_eq!(
+            parse_x_forwarded_for("10.0.0.2, 2001:db8::1"),
+            Some("2001:db8::1".parse().unwrap())
+        );
+        assert_eq!(parse_x_forwarded_for("unknown"), None);
+    }
+
+    #[test]
+    fn test_forwarded_header() {
+        assert_eq!(
+            parse_forwarded("for=198.51.100.17, for=192.0.2.60;proto=http;by=203.0.113.43"),
+            Some("192.0.2.60".parse().unwrap())
+        );
+        assert_eq!(
+            parse_forwarded(r#"For="[2001:db8:cafe::17]:4711""#),
+            Some("2001:db8:cafe::17".parse().unwrap())
+        );
+        assert_eq!(parse_forwarded("proto=https"), None);
+    }
+
+    const XFF: ForwardedHeader = ForwardedHeader::XForwardedFor;
+
+    #[test]
+    fn test_untrusted_headers_ignored() {
+        let req = TestRequest::default()
+            .peer_addr(peer())
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .insert_header(("Forwarded", "for=192.0.2.60"))
+            .to_http_request();
+
+        assert_eq!(client_ip(&req, false, XFF), Some(peer().ip()));
+        assert_eq!(client_ip(&req, false, ForwardedHeader::Forwarded), Some(peer().ip()));
+    }
+
+    #[test]
+    fn test_trusted_headers() {
+        let req = TestRequest::default()
+            .peer_addr(peer())
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .to_http_request();
+        assert_eq!(client_ip(&req, true, XFF), Some("203.0.113.7".parse().unwrap()));
+
+        // A hop the client put before the proxy's own is ignored
+        let req = TestRequest::default()
+            .peer_addr(peer())
+            .insert_header(("X-Forwarded-For", "198.51.100.1, 203.0.113.7"))
+            .to_http_request();
+        assert_eq!(client_ip(&req, true, XFF), Some("203.0.113.7".parse().unwrap()));
+
+        // Falls back to the peer when the header is missing or garbled
+        let req = TestRequest::default()
+            .peer_addr(peer())
+            .insert_header(("X-Forwarded-For", "garbage"))
+            .to_http_request();
+        assert_eq!(client_ip(&req, true, XFF), Some(peer().ip()));
+    }
+
+    #[test]
+    fn test_only_configured_header_read() {
+        // The proxy appended to X-Forwarded-For and passed the client's forged
+        // Forwarded header through
+        let req = TestRequest::default()
+            .peer_addr(peer())
+            .insert_header(("Forwarded", "for=192.0.2.60"))
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .to_http_request();
+        assert_eq!(client_ip(&req, true, XFF), Some("203.0.113.7".parse().unwrap()));
+
+        // And the other way round for a proxy that writes Forwarded
+        let req = TestRequest::default()
+            .peer_addr(peer())
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .to_http_request();
+        assert_eq!(client_ip(&req, true, ForwardedHeader::Forwarded), Some(peer().ip()));
+        let req = TestRequest::default()
+            .peer_addr(peer())
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .insert_header(("Forwarded", "for=192.0.2.60"))
+            .to_http_request();
+        assert_eq!(client_ip(&req, true, ForwardedHeader::Forwarded), Some("192.0.2.60".parse().unwrap()));
+    }
+}
@@ -1,12 +1,71 @@
 use actix_web::{web, HttpResponse, HttpRequest};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
 use crate::AppState;
-use crate::error::Error;
-use tracing::{info, error};
+use crate::auth::client_ip::client_ip;
+use crate::auth::middleware::{authenticated_user, locked_response, request_token, require_admin, require_user};
+use crate::auth::password::validate_password_strength;
+use crate::error::{Error, FieldError};
+use crate::middleware::ValidatedJson;
+use tracing::{info, error, warn};
 
-#[derive(Debug, Deserialize)]
+/// Page size for the audit listing when the caller doesn't ask for one
+const DEFAULT_AUDIT_PAGE_SIZE: i64 = 50;
+const MAX_AUDIT_PAGE_SIZE: i64 = 500;
+
+/// Client address as recorded in the audit log
+pub(crate) fn audit_ip(req: &HttpRequest, state: &AppState) -> String {
+    client_ip(req, state.config.server.trust_proxy, state.config.server.forwarded_header)
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Minimal structural email check: one `@` with a non-empty local part and a dotted domain
+fn validate_email(email: &str) -> Result<(), ValidationError> {
+    let valid = match email.trim().split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !domain.contains('@')
+                && domain.split('.').count() > 1
+                && domain.split('.').all(|part| !part.is_empty())
+        }
+        None => false,
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(ValidationError::new("email").with_message("Invalid email address".into()))
+    }
+}
+
+/// Longest display name accepted, in characters
+const MAX_DISPLAY_NAME_CHARS: usize = 100;
+
+/// Trim a display name and reject over-long or non-printable values.
+/// Blank names are treated as absent.
+fn normalize_display_name(display_name: Option<&str>) -> Result<Option<String>, Error> {
+    let Some(name) = display_name.map(str::trim).filter(|n| !n.is_empty()) else {
+        return Ok(None);
+    };
+
+    let message = if name.chars().count() > MAX_DISPLAY_NAME_CHARS {
+        format!("Display name must be at most {} characters", MAX_DISPLAY_NAME_CHARS)
+    } else if name.chars().any(char::is_control) {
+        "Display name contains non-printable characters".to_string()
+    } else {
+        return Ok(Some(name.to_string()));
+    };
+
+    Err(Error::Validation(vec![FieldError::new("display_name", &message)]))
+}
+
+#[derive(Debug, Deserialize, Validate)]
 pub struct LoginRequest {
+    #[validate(custom(function = "validate_email"))]
     pub email: String,
+    #[validate(length(min = 1, message = "Password cannot be empty"))]
     pub password: String,
 }
 
@@ -16,40 +75,63 @@ pub struct AuthResponse {
 }
 
 pub async fn login(
-    req: web::Json<LoginRequest>,
+    http_req: HttpRequest,
+    req: ValidatedJson<LoginRequest>,
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
     info!("Received login request for email: {}", req.email);
-    match state.auth_service.authenticate(&req.email, &req.password).await {
+
+    if let Some(remaining) = state.login_lockout.locked_for(&req.email).await {
+        warn!("Login rejected for locked account: {}", req.email);
+        return Ok(locked_response(remaining));
+    }
+
+    match state.auth_service.authenticate(&req.email, &req.password, &audit_ip(&http_req, &state)).await {
         Ok(token) => {
             info!("Login successful for email: {}", req.email);
+            state.login_lockout.record_success(&req.email).await;
             Ok(HttpResponse::Ok().json(AuthResponse { token }))
         }
         Err(e) => {
             error!("Login failed for email: {}: {}", req.email, e);
+            if matches!(e, Error::Unauthorized(_)) {
+                if let Some(duration) = state.login_lockout.record_failure(&req.email).await {
+                    warn!("Locking {} for {}s after repeated failed logins", req.email, duration.num_seconds());
+                }
+            }
             Err(e)
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct RegisterRequest {
+    #[validate(custom(function = "validate_email"))]
     pub email: String,
+    #[validate(custom(function = "validate_password_strength"))]
     pub password: String,
     pub display_name: Option<String>,
 }
 
 pub async fn register(
-    req: web::Json<RegisterRequest>,
+    http_req: HttpRequest,
+    req: ValidatedJson<RegisterRequest>,
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
     info!("Received registration request for email: {}", req.email);
-    
+    state.maintenance.ensure_writable()?;
+    if !state.config.features.registration_enabled {
+        return Err(Error::Forbidden("Registration is disabled".into()));
+    }
+    let display_name = normalize_display_name(req.display_name.as_deref())?;
+    let ip = audit_ip(&http_req, &state);
+
     // Attempt registration
     match state.auth_service.register(
         &req.email,
         &req.password,
-        req.display_name.as_deref(),
+        display_name.as_deref(),
+        &ip,
     ).await {
         Ok(_) => {
              info!("Registration successful for email: {}", req.email);
@@ -61,7 +143,7 @@ pub async fn register(
     }
     
     // Attempt login immediately after successful registration
-    match state.auth_service.authenticate(&req.email, &req.password).await {
+    match state.auth_service.authenticate(&req.email, &req.password, &ip).await {
         Ok(token) => {
             info!("Post-registration login successful for email: {}", req.email);
             Ok(HttpResponse::Created().json(AuthResponse { token }))
@@ -74,21 +156,197 @@ pub async fn register(
     }
 }
 
+/// Start an anonymous, chat-only session
+pub async fn guest(state: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    state.maintenance.ensure_writable()?;
+    if !state.config.features.guest_sessions_enabled {
+        return Err(Error::Forbidden("Guest sessions are disabled".into()));
+    }
+
+    let token = state.auth_service.create_guest_session().await.map_err(|e| {
+        error!("Failed to create guest session: {}", e);
+        e
+    })?;
+    info!("Guest session created");
+    Ok(HttpResponse::Created().json(AuthResponse { token }))
+}
+
 pub async fn logout(
     req: HttpRequest,
     state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
-    // Get token from Authorization header
-    let token = req.headers()
-        .get("Authorization")
-        .and_then(|h| h.to_str().ok())
-        .and_then(|h| h.strip_prefix("Bearer "))
-        .ok_or_else(|| Error::Unauthorized("No authorization token provided".into()))?;
+    let token = request_token(&req, &state.config.auth)?;
 
     // Invalidate the token
-    state.auth_service.invalidate_token(token).await?;
+    state.auth_service.invalidate_token(token, &audit_ip(&req, &state)).await?;
     
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "message": "Successfully logged out"
     })))
-} 
\ No newline at end of file
+}
+
+/// Headers `GET /auth/validate` describes the token's user with
+pub const USER_ID_HEADER: &str = "X-User-Id";
+pub const USER_EMAIL_HEADER: &str = "X-User-Email";
+/// "user", or "guest" for anonymous chat-only sessions
+pub const USER_ROLE_HEADER: &str = "X-User-Role";
+
+/// Check the bearer token for a gateway doing auth offload (nginx
+/// `auth_request`, Envoy `ext_authz`): 200 with the user in `X-User-*`
+/// headers and an empty body, or 401.
+pub async fn validate(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let user = authenticated_user(&req, &state).await?;
+    let role = if user.is_guest { "guest" } else { "user" };
+
+    Ok(HttpResponse::Ok()
+        .insert_header((USER_ID_HEADER, user.id.to_string()))
+        .insert_header((USER_EMAIL_HEADER, user.email))
+        .insert_header((USER_ROLE_HEADER, role))
+        .finish())
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ChangePasswordRequest {
+    #[validate(length(min = 1, message = "Current password cannot be empty"))]
+    pub current_password: String,
+    #[validate(custom(function = "validate_password_strength"))]
+    pub new_password: String,
+}
+
+/// Change the caller's password. Every other session is logged out; the one
+/// making the request stays valid.
+pub async fn change_password(
+    http_req: HttpRequest,
+    req: ValidatedJson<ChangePasswordRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    state.maintenance.ensure_writable()?;
+    let user = require_user(&http_req, &state).await?;
+    let token = request_token(&http_req, &state.config.auth)?;
+
+    let ended = state.auth_service.change_password(
+        &user,
+        token,
+        &req.current_password,
+        &req.new_password,
+        &audit_ip(&http_req, &state),
+    ).await.map_err(|e| {
+        warn!("Password change failed for user {}: {}", user.id, e);
+        e
+    })?;
+    info!("Password changed for user {}, ended {} other sessions", user.id, ended);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Password changed",
+        "sessions_ended": ended
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub user_id: Option<Uuid>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Admin endpoint to page through the auth audit log, newest first
+pub async fn list_audit_events(
+    req: HttpRequest,
+    query: web::Query<AuditQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &state.config.admin)?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_AUDIT_PAGE_SIZE).clamp(1, MAX_AUDIT_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let events = state.db.list_auth_events(query.user_id, limit, offset).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "events": events,
+        "limit": limit,
+        "offset": offset
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fields named in the validation error for `request`, or none if it passed
+    fn failing_fields(request: &impl Validate) -> Vec<String> {
+        match request.validate().map_err(Error::from) {
+            Ok(()) => Vec::new(),
+            Err(Error::Validation(fields)) => fields.into_iter().map(|f| f.field).collect(),
+            Err(e) => panic!("Unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn test_login_request_constraints() {
+        let login = |email: &str, password: &str| LoginRequest {
+            email: email.to_string(),
+            password: password.to_string(),
+        };
+
+        assert!(failing_fields(&login("ada@example.com", "x")).is_empty());
+        assert!(failing_fields(&login(" ada@example.com ", "x")).is_empty());
+        for email in ["", "ada", "@example.com", "ada@localhost", "ada@example..com", "a@b@example.com"] {
+            assert_eq!(failing_fields(&login(email, "x")), vec!["email"], "Accepted {:?}", email);
+        }
+        assert_eq!(failing_fields(&login("ada@example.com", "")), vec!["password"]);
+        assert_eq!(failing_fields(&login("ada", "")), vec!["email", "password"]);
+    }
+
+    #[test]
+    fn test_register_request_constraints() {
+        let register = |email: &str, password: &str| RegisterRequest {
+            email: email.to_string(),
+            password: password.to_string(),
+            display_name: None,
+        };
+
+        assert!(failing_fields(&register("ada@example.com", "password123")).is_empty());
+        assert_eq!(failing_fields(&register("not-an-email", "password123")), vec!["email"]);
+
+        let too_long = format!("a1{}", "b".repeat(127));
+        for weak in ["short1", "onlyletters", "12345678", too_long.as_str()] {
+            assert_eq!(failing_fields(&register("ada@example.com", weak)), vec!["password"], "Accepted {:?}", weak);
+        }
+    }
+
+    #[test]
+    fn test_change_password_request_constraints() {
+        let change = |current: &str, new: &str| ChangePasswordRequest {
+            current_password: current.to_string(),
+            new_password: new.to_string(),
+        };
+
+        assert!(failing_fields(&change("old", "n3w-passphrase")).is_empty());
+        assert_eq!(failing_fields(&change("", "n3w-passphrase")), vec!["current_password"]);
+        assert_eq!(failing_fields(&change("old", "short1")), vec!["new_password"]);
+    }
+
+    #[test]
+    fn test_display_name_trimmed() {
+        assert_eq!(normalize_display_name(Some("  Ada Lovelace \t")).unwrap(), Some("Ada Lovelace".to_string()));
+        assert_eq!(normalize_display_name(Some("   ")).unwrap(), None);
+        assert_eq!(normalize_display_name(None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_display_name_rejected() {
+        let too_long = "a".repeat(MAX_DISPLAY_NAME_CHARS + 1);
+        assert!(matches!(normalize_display_name(Some(&too_long)), Err(Error::Validation(_))));
+
+        // Length is counted in characters, not bytes
+        let multibyte = "é".repeat(MAX_DISPLAY_NAME_CHARS);
+        assert!(normalize_display_name(Some(&multibyte)).is_ok());
+
+        assert!(matches!(normalize_display_name(Some("Ada\u{0}Lovelace")), Err(Error::Validation(_))));
+        assert!(matches!(normalize_display_name(Some("Ada\nLovelace")), Err(Error::Validation(_))));
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use chrono::{DateTime, Utc, Duration};
+
+use crate::config::LoginLockoutConfig;
+
+#[derive(Debug, Clone)]
+pub struct LockoutConfig {
+    /// Failed logins allowed within `failure_window` before the account locks
+    pub max_failures: u32,
+    pub failure_window: Duration,
+    pub lock_duration: Duration,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            max_failures: 5,
+            failure_window: Duration::minutes(15),
+            lock_duration: Duration::minutes(15),
+        }
+    }
+}
+
+impl LockoutConfig {
+    pub fn from_config(config: &LoginLockoutConfig) -> Self {
+        Self {
+            max_failures: config.max_failures,
+            failure_window: Duration::seconds(config.failure_window_secs as i64),
+            lock_duration: Duration::seconds(config.lock_secs as i64),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct FailureRecord {
+    failures: Vec<DateTime<Utc>>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+/// Brute-force protection for password login. Failures are tracked per email,
+/// whether or not an account exists, so lockouts don't reveal which emails are registered.
+pub struct LoginLockout {
+    records: Arc<RwLock<HashMap<String, FailureRecord>>>,
+    config: LockoutConfig,
+}
+
+impl LoginLockout {
+    pub fn new(config: LockoutConfig) -> Self {
+        Self {
+            records: Arc::new(RwLock::new(HashMap::new())),
+            config,
+        }
+    }
+
+    fn key(email: &str) -> String {
+        email.trim().to_lowercase()
+    }
+
+    /// Time left on the lock for `email`, or `None` if logins are allowed
+    pub async fn locked_for(&self, email: &str) -> Option<Duration> {
+        let records = self.records.read().await;
+        let locked_until = records.get(&Self::key(email))?.locked_until?;
+        let remaining = locked_until - Utc::now();
+        (remaining > Duration::zero()).then_some(remaining)
+    }
+
+    /// Count a failed login, locking the account once the threshold is reached.
+    /// Returns the lock duration if this failure triggered a lock.
+    pub async fn record_failure(&self, email: &str) -> Option<Duration> {
+        let mut records = self.records.write().await;
+        let record = records.entry(Self::key(email)).or_default();
+        let now = Utc::now();
+
+        let cutoff = now - self.config.failure_window;
+        record.failures.retain(|ts| *ts > cutoff);
+        record.failures.push(now);
+
+        if record.failures.len() >= self.config.max_failures as usize {
+            record.failures.clear();
+            record.locked_until = Some(now + self.config.lock_duration);
+            Some(self.config.lock_duration)
+        } else {
+            None
+        }
+    }
+
+    /// Forget past failures after a successful login
+    pub async fn record_success(&self, email: &str) {
+        self.records.write().await.remove(&Self::key(email));
+    }
+
+    pub async fn cleanup(&self) {
+        let now = Utc::now();
+        let cutoff = now - self.config.failure_window;
+
+        self.records.write().await.retain(|_, record| {
+            record.failures.retain(|ts| *ts > cutoff);
+            !record.failures.is_empty() || record.locked_until.is_some_and(|until| until > now)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> LockoutConfig {
+        LockoutConfig {
+            max_failures: 3,
+            ..LockoutConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lock_after_failures() {
+        let lockout = LoginLockout::new(test_config());
+
+        assert!(lockout.record_failure("user@example.com").await.is_none());
+        assert!(lockout.record_failure("user@example.com").await.is_none());
+        assert!(lockout.locked_for("user@example.com").await.is_none());
+
+        assert_eq!(lockout.record_failure("USER@example.com ").await, Some(Duration::minutes(15)));
+
+        let remaining = lockout.locked_for("user@example.com").await.unwrap();
+        assert!(remaining > Duration::minutes(14) && remaining <= Duration::minutes(15));
+        assert!(lockout.locked_for("other@example.com").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_success_resets_failures() {
+        let lockout = LoginLockout::new(test_config());
+
+        lockout.record_failure("user@example.com").await;
+        lockout.record_failure("user@example.com").await;
+        lockout.record_success("user@example.com").await;
+
+        assert!(lockout.record_failure("user@example.com").await.is_none());
+        assert!(lockout.locked_for("user@example.com").await.is_none());
+    }
+}
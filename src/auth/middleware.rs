@@ -0,0 +1,208 @@
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER},
+    http::StatusCode,
+    middleware::Next,
+    web, HttpMessage, HttpRequest, HttpResponse, ResponseError,
+};
+use tracing::warn;
+
+use crate::auth::rate_limit::RateLimitStatus;
+use crate::config::{AdminConfig, AuthConfig};
+use crate::db::User;
+use crate::error::{AppError, AuthError, Error, ErrorResponse};
+use crate::AppState;
+
+/// Reject the request unless it carries the configured admin token
+pub fn require_admin(req: &HttpRequest, config: &AdminConfig) -> Result<(), Error> {
+    let expected = config.token.as_deref()
+        .filter(|t| !t.is_empty())
+        .ok_or_else(|| Error::Unauthorized("Admin access is not configured".into()))?;
+
+    let provided = req.headers()
+        .get("X-Admin-Token")
+        .and_then(|h| h.to_str().ok())
+        .ok_or_else(|| Error::Unauthorized("No admin token provided".into()))?;
+
+    // Compare without short-circuiting on the first differing byte
+    let matches = provided.len() == expected.len()
+        && provided.bytes().zip(expected.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0;
+
+    if matches {
+        Ok(())
+    } else {
+        warn!("Rejected admin request with invalid token");
+        Err(Error::Unauthorized("Invalid admin token".into()))
+    }
+}
+
+/// The user [`rate_limit`] resolved from a request's token, kept in the
+/// request extensions so handlers don't look the session up a second time
+#[derive(Clone)]
+struct AuthenticatedUser {
+    token: String,
+    user: User,
+}
+
+/// Resolve the user behind the request's bearer token, reusing the lookup
+/// [`rate_limit`] already made for it when there was one
+pub async fn authenticated_user(req: &HttpRequest, state: &AppState) -> Result<User, Error> {
+    let token = request_token(req, &state.config.auth)?;
+    let cached = req.extensions().get::<AuthenticatedUser>()
+        .filter(|cached| cached.token == token)
+        .map(|cached| cached.user.clone());
+    match cached {
+        Some(user) => Ok(user),
+        None => state.auth_service.validate_token(token).await,
+    }
+}
+
+/// Resolve the registered user behind the request's bearer token.
+/// Guest sessions are limited to chat, so they are refused here.
+pub async fn require_user(req: &HttpRequest, state: &AppState) -> Result<User, Error> {
+    let user = authenticated_user(req, state).await?;
+    if user.is_guest {
+        return Err(Error::Forbidden("Guest sessions can only be used for chat".into()));
+    }
+    Ok(user)
+}
+
+/// The session token in `auth.header_name`, after `auth.token_prefix`.
+/// The scheme matches case-insensitively and surrounding whitespace is
+/// ignored; a header with nothing after the scheme holds no token.
+pub fn token_from_headers<'a>(headers: &'a HeaderMap, config: &AuthConfig) -> Option<&'a str> {
+    let value = headers.get(config.header_name.as_str())?.to_str().ok()?.trim_start();
+    let prefix = config.token_prefix.as_str();
+    let scheme = value.get(..prefix.len())?;
+    if !scheme.eq_ignore_ascii_case(prefix) {
+        return None;
+    }
+
+    Some(value[prefix.len()..].trim()).filter(|token| !token.is_empty())
+}
+
+/// The bearer token a request was made with
+pub fn request_token<'a>(req: &'a HttpRequest, config: &AuthConfig) -> Result<&'a str, Error> {
+    token_from_headers(req.headers(), config)
+        .ok_or_else(|| Error::Unauthorized("No authorization token provided".into()))
+}
+
+/// Extract the bearer token from the request, if any
+pub fn bearer_token(req: &ServiceRequest, config: &AuthConfig) -> Option<String> {
+    token_from_headers(req.headers(), config).map(str::to_string)
+}
+
+/// Build the 429 response for a request that exceeded its window
+pub fn rate_limited_response(status: &RateLimitStatus) -> HttpResponse {
+    let mut response = AppError::AuthError(AuthError::RateLimited).error_response();
+    let headers = response.headers_mut();
+
+    let values = [
+        ("x-ratelimit-limit", status.limit.to_string()),
+        ("x-ratelimit-remaining", status.remaining.to_string()),
+        ("x-ratelimit-reset", status.reset_at.timestamp().to_string()),
+    ];
+    for (name, value) in values {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+
+    response
+}
+
+/// Build the 429 response for a login attempt against a locked account
+pub fn locked_response(remaining: chrono::Duration) -> HttpResponse {
+    // Round up so clients never retry a moment too early
+    let retry_after = (remaining.num_milliseconds() + 999) / 1000;
+
+    let mut response = ErrorResponse::new(
+        StatusCode::TOO_MANY_REQUESTS,
+        "Account temporarily locked after repeated failed logins",
+    )
+    .with_code("account_locked")
+    .with_retry_after(retry_after)
+    .into_response();
+    response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(retry_after));
+    response
+}
+
+/// Middleware that applies the per-user rate limit to authenticated HTTP requests.
+/// Requests without a valid bearer token pass through untouched. The user is
+/// left in the request extensions for [`authenticated_user`].
+pub async fn rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let state = req.app_data::<web::Data<AppState>>().cloned();
+    let token = state.as_ref().and_then(|state| bearer_token(&req, &state.config.auth));
+
+    if let (Some(state), Some(token)) = (state, token) {
+        if let Ok(user) = state.auth_service.validate_token(&token).await {
+            let status = state.rate_limiter
+                .check_rate_limit_detailed(user.id, &user.rate_limit_tier)
+                .await;
+
+            if !status.allowed {
+                warn!("Rate limit exceeded for user {}", user.id);
+                return Ok(req.into_response(rate_limited_response(&status)));
+            }
+            req.extensions_mut().insert(AuthenticatedUser { token, user });
+        }
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_boxed_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+    use actix_web::test::TestRequest;
+
+    fn auth_config(header_name: &str, token_prefix: &str) -> AuthConfig {
+        let mut config = Settings::new_for_test().unwrap().auth;
+        config.header_name = header_name.to_string();
+        config.token_prefix = token_prefix.to_string();
+        config
+    }
+
+    fn token<'a>(req: &'a HttpRequest, config: &AuthConfig) -> Option<&'a str> {
+        token_from_headers(req.headers(), config)
+    }
+
+    #[test]
+    fn test_default_bearer_token() {
+        let config = auth_config("Authorization", "Bearer ");
+        let req = TestRequest::default().insert_header(("Authorization", "Bearer abc.def")).to_http_request();
+        assert_eq!(token(&req, &config), Some("abc.def"));
+
+        let req = TestRequest::default().insert_header(("Authorization", "  bearer   abc.def ")).to_http_request();
+        assert_eq!(token(&req, &config), Some("abc.def"));
+
+        for value in ["Token abc.def", "Bearerabc.def", "Bearer ", "abc.def"] {
+            let req = TestRequest::default().insert_header(("Authorization", value)).to_http_request();
+            assert_eq!(token(&req, &config), None, "{:?}", value);
+        }
+        assert!(request_token(&TestRequest::default().to_http_request(), &config).is_err());
+    }
+
+    #[test]
+    fn test_custom_header_and_prefix() {
+        let config = auth_config("X-Api-Token", "Token ");
+        let req = TestRequest::default()
+            .insert_header(("X-Api-Token", "Token abc.def"))
+            .insert_header(("Authorization", "Bearer other"))
+            .to_http_request();
+        assert_eq!(token(&req, &config), Some("abc.def"));
+
+        let req = TestRequest::default().insert_header(("Authorization", "Token abc.def")).to_http_request();
+        assert_eq!(token(&req, &config), None);
+
+        // An empty prefix takes the whole header value
+        let config = auth_config("X-Session", "");
+        let req = TestRequest::default().insert_header(("X-Session", " abc.def ")).to_http_request();
+        assert_eq!(token(&req, &config), Some("abc.def"));
+    }
+}
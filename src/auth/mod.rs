@@ -6,10 +6,17 @@
 // Re-export public interfaces
 // Will be implemented in Phase 2
 
+mod activity;
 mod service;
 mod rate_limit;
+mod lockout;
+pub mod password;
+pub mod client_ip;
 pub mod handlers;
+pub mod middleware;
 
-pub use service::{AuthService, Claims};
-pub use rate_limit::{RateLimiter, RateLimitConfig};
+pub use activity::{ActivityStore, SessionActivity};
+pub use service::{AuthService, Claims, SessionLimitPolicy, SessionTokenType, AUDIT_LOGIN, DEFAULT_JWT_AUDIENCE, DEFAULT_JWT_ISSUER, AUDIT_LOGOUT, AUDIT_PASSWORD_CHANGE, AUDIT_PASSWORD_RESET, AUDIT_REGISTER};
+pub use rate_limit::{RateLimiter, RateLimitConfig, RateLimitStatus};
+pub use lockout::{LoginLockout, LockoutConfig};
 pub use handlers::{login, register};
@@ -0,0 +1,166 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use validator::ValidationError;
+
+use crate::config::AuthConfig;
+use crate::error::{AppError, Error, FieldError};
+
+/// Shortest password accepted when one is set or changed
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+/// Longest password accepted, so hashing cost stays bounded
+pub const MAX_PASSWORD_LENGTH: usize = 128;
+
+/// Argon2id cost parameters for new password hashes. Existing hashes keep the
+/// parameters they were made with until [`needs_rehash`] flags them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasswordParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for PasswordParams {
+    /// The argon2 crate's defaults (19 MiB, 2 passes, 1 lane), per OWASP's minimum
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl PasswordParams {
+    /// Read `auth.password_memory_kib`, `password_iterations` and `password_parallelism`
+    pub fn from_config(config: &AuthConfig) -> Result<Self, AppError> {
+        let params = Self {
+            memory_kib: config.password_memory_kib,
+            iterations: config.password_iterations,
+            parallelism: config.password_parallelism,
+        };
+        params.argon2().map_err(|e| AppError::ConfigError(format!("Invalid password hashing parameters: {}", e)))?;
+        Ok(params)
+    }
+
+    fn argon2(&self) -> Result<Argon2<'static>, argon2::Error> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+/// Hash `password` with Argon2id into a PHC string. Hashing is deliberately
+/// slow, so it runs on the blocking pool rather than an async worker.
+pub async fn hash_password(password: &str, params: &PasswordParams) -> Result<String, Error> {
+    let password = password.to_string();
+    let params = *params;
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut rand::rngs::OsRng);
+        params
+            .argon2()
+            .map_err(|e| Error::External(format!("Invalid password hashing parameters: {}", e)))?
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| Error::External(format!("Failed to hash password: {}", e)))
+    })
+    .await
+    .map_err(|e| Error::External(format!("Password hashing task failed: {}", e)))?
+}
+
+/// Check `password` against a hash produced by [`hash_password`]
+pub async fn verify_password(password: &str, hash: &str) -> Result<bool, Error> {
+    let password = password.to_string();
+    let hash = hash.to_string();
+    tokio::task::spawn_blocking(move || {
+        let parsed = PasswordHash::new(&hash)
+            .map_err(|e| Error::External(format!("Stored password hash is invalid: {}", e)))?;
+        Ok(Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+    })
+    .await
+    .map_err(|e| Error::External(format!("Password verification task failed: {}", e)))?
+}
+
+/// Whether `hash` was made with anything other than Argon2id under `params`,
+/// so it should be replaced next time the password is at hand. Unparseable
+/// hashes are left alone; they can't be verified to be replaced anyway.
+pub fn needs_rehash(hash: &str, params: &PasswordParams) -> bool {
+    let Ok(parsed) = PasswordHash::new(hash) else {
+        return false;
+    };
+    if parsed.algorithm != Algorithm::Argon2id.ident() || parsed.version != Some(Version::V0x13.into()) {
+        return true;
+    }
+    match Params::try_from(&parsed) {
+        Ok(current) => {
+            current.m_cost() != params.memory_kib
+                || current.t_cost() != params.iterations
+                || current.p_cost() != params.parallelism
+        }
+        Err(_) => true,
+    }
+}
+
+/// Reject passwords that are too short, too long, or made of a single kind of
+/// character. Usable as a `#[validate(custom(...))]` rule on request fields.
+pub fn validate_password_strength(password: &str) -> Result<(), ValidationError> {
+    let length = password.chars().count();
+    let message = if length < MIN_PASSWORD_LENGTH {
+        format!("Password must be at least {} characters", MIN_PASSWORD_LENGTH)
+    } else if length > MAX_PASSWORD_LENGTH {
+        format!("Password must be at most {} characters", MAX_PASSWORD_LENGTH)
+    } else if !password.chars().any(char::is_alphabetic) || password.chars().all(char::is_alphabetic) {
+        "Password must mix letters with numbers or symbols".to_string()
+    } else {
+        return Ok(());
+    };
+
+    Err(ValidationError::new("password_strength").with_message(message.into()))
+}
+
+/// [`validate_password_strength`] for callers outside request validation.
+/// `field` names the request field in the validation error.
+pub fn check_password_strength(field: &str, password: &str) -> Result<(), Error> {
+    validate_password_strength(password).map_err(|e| {
+        let message = e.message.unwrap_or_default();
+        Error::Validation(vec![FieldError::new(field, &message)])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hash_and_verify() {
+        let params = PasswordParams::default();
+        let hash = hash_password("password123", &params).await.unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("password123", &hash).await.unwrap());
+        assert!(!verify_password("password124", &hash).await.unwrap());
+
+        // Salted, so the same password never hashes the same way twice
+        assert_ne!(hash, hash_password("password123", &params).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_needs_rehash() {
+        let weak = PasswordParams { memory_kib: 1024, iterations: 1, parallelism: 1 };
+        let hash = hash_password("password123", &weak).await.unwrap();
+        assert!(!needs_rehash(&hash, &weak));
+        assert!(needs_rehash(&hash, &PasswordParams::default()));
+        assert!(needs_rehash(&hash, &PasswordParams { iterations: 3, ..weak }));
+        assert!(!needs_rehash("not a hash", &weak));
+    }
+
+    #[test]
+    fn test_password_strength() {
+        assert!(check_password_strength("password", "password123").is_ok());
+        assert!(check_password_strength("password", "correct horse battery").is_ok());
+
+        for weak in ["short1", "onlyletters", "12345678", &"a1".repeat(65)] {
+            match check_password_strength("new_password", weak) {
+                Err(Error::Validation(fields)) => assert_eq!(fields[0].field, "new_password"),
+                other => panic!("Expected validation error for {:?}, got {:?}", weak, other),
+            }
+        }
+    }
+}
@@ -4,6 +4,10 @@ use tokio::sync::RwLock;
 use chrono::{DateTime, Utc, Duration};
 use uuid::Uuid;
 
+use crate::config::RequestRateLimitConfig;
+use crate::db::models::GUEST_RATE_LIMIT_TIER;
+use crate::error::Error;
+
 #[derive(Debug, Clone)]
 pub struct RateLimitConfig {
     pub window_size: Duration,
@@ -15,6 +19,7 @@ impl Default for RateLimitConfig {
         let mut limits = HashMap::new();
         limits.insert("standard".to_string(), 100);  // 100 requests per window
         limits.insert("premium".to_string(), 500);   // 500 requests per window
+        limits.insert(GUEST_RATE_LIMIT_TIER.to_string(), 20);  // 20 requests per window
         
         Self {
             window_size: Duration::minutes(1),
@@ -23,6 +28,27 @@ impl Default for RateLimitConfig {
     }
 }
 
+impl RateLimitConfig {
+    /// The built-in limits with those set in `auth.rate_limit` applied on top
+    pub fn from_config(config: &RequestRateLimitConfig) -> Self {
+        let mut limits = Self::default().limits;
+        limits.extend(config.limits.clone());
+        Self {
+            window_size: Duration::seconds(config.window_secs as i64),
+            limits,
+        }
+    }
+}
+
+/// Outcome of a rate limit check, with enough detail to build `X-RateLimit-*` headers
+#[derive(Debug, Clone)]
+pub struct RateLimitStatus {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    pub reset_at: DateTime<Utc>,
+}
+
 #[derive(Debug)]
 struct RequestWindow {
     timestamps: Vec<DateTime<Utc>>,
@@ -47,6 +73,13 @@ impl RequestWindow {
     fn request_count(&self) -> usize {
         self.timestamps.len()
     }
+
+    fn reset_at(&self, window_size: Duration) -> DateTime<Utc> {
+        // The window frees up a slot once its oldest request ages out
+        self.timestamps.first()
+            .map(|oldest| *oldest + window_size)
+            .unwrap_or_else(|| Utc::now() + window_size)
+    }
 }
 
 pub struct RateLimiter {
@@ -63,6 +96,10 @@ impl RateLimiter {
     }
 
     pub async fn check_rate_limit(&self, user_id: Uuid, tier: &str) -> bool {
+        self.check_rate_limit_detailed(user_id, tier).await.allowed
+    }
+
+    pub async fn check_rate_limit_detailed(&self, user_id: Uuid, tier: &str) -> RateLimitStatus {
         let mut windows = self.windows.write().await;
         
         // Get or create window for user
@@ -72,15 +109,30 @@ impl RateLimiter {
         window.cleanup_old_requests(self.config.window_size);
         
         // Get limit for user's tier
-        let limit = self.config.limits.get(tier)
+        let limit = *self.config.limits.get(tier)
             .unwrap_or_else(|| self.config.limits.get("standard").unwrap());
         
         // Check if under limit
-        if window.request_count() < *limit as usize {
+        let allowed = window.request_count() < limit as usize;
+        if allowed {
             window.add_request();
-            true
+        }
+
+        RateLimitStatus {
+            allowed,
+            limit,
+            remaining: limit.saturating_sub(window.request_count() as u32),
+            reset_at: window.reset_at(self.config.window_size),
+        }
+    }
+
+    /// Count a request against `user_id`'s window, failing once their tier's limit is reached
+    pub async fn enforce(&self, user_id: Uuid, tier: &str) -> Result<(), Error> {
+        let status = self.check_rate_limit_detailed(user_id, tier).await;
+        if status.allowed {
+            Ok(())
         } else {
-            false
+            Err(Error::RateLimited(status.limit))
         }
     }
 
@@ -102,9 +154,11 @@ mod tests {
 
     #[tokio::test]
     async fn test_rate_limiter() {
-        let mut config = RateLimitConfig::default();
         // Use a shorter window for testing
-        config.window_size = Duration::seconds(1);
+        let config = RateLimitConfig {
+            window_size: Duration::seconds(1),
+            ..RateLimitConfig::default()
+        };
         let limiter = RateLimiter::new(config);
         let user_id = Uuid::new_v4();
 
@@ -122,4 +176,54 @@ mod tests {
         // Should allow requests again
         assert!(limiter.check_rate_limit(user_id, "standard").await);
     }
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_rate_limit_detailed() {
+        let limiter = RateLimiter::new(RateLimitConfig::default());
+        let user_id = Uuid::new_v4();
+
+        let status = limiter.check_rate_limit_detailed(user_id, "standard").await;
+        assert!(status.allowed);
+        assert_eq!(status.limit, 100);
+        assert_eq!(status.remaining, 99);
+        assert!(status.reset_at > Utc::now());
+
+        for _ in 0..99 {
+            limiter.check_rate_limit(user_id, "standard").await;
+        }
+
+        let status = limiter.check_rate_limit_detailed(user_id, "standard").await;
+        assert!(!status.allowed);
+        assert_eq!(status.remaining, 0);
+    }
+
+    #[tokio::test]
+    async fn test_guest_tier_is_tighter() {
+        let limiter = RateLimiter::new(RateLimitConfig::default());
+        let guest = Uuid::new_v4();
+
+        for _ in 0..20 {
+            assert!(limiter.check_rate_limit(guest, GUEST_RATE_LIMIT_TIER).await);
+        }
+        let status = limiter.check_rate_limit_detailed(guest, GUEST_RATE_LIMIT_TIER).await;
+        assert!(!status.allowed);
+        assert_eq!(status.limit, 20);
+
+        // A standard user is nowhere near their limit after the same number of requests
+        let standard = Uuid::new_v4();
+        for _ in 0..21 {
+            assert!(limiter.check_rate_limit(standard, "standard").await);
+        }
+    }
+
+    #[test]
+    fn test_config_overrides_builtin_limits() {
+        let config = RateLimitConfig::from_config(&RequestRateLimitConfig {
+            window_secs: 30,
+            limits: HashMap::from([("premium".to_string(), 1000)]),
+        });
+        assert_eq!(config.window_size, Duration::seconds(30));
+        assert_eq!(config.limits["premium"], 1000);
+        assert_eq!(config.limits["standard"], 100);
+    }
+}
@@ -0,0 +1,84 @@
+use config::ConfigError;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use super::TlsConfig;
+
+fn open(path: &str, kind: &str) -> Result<BufReader<File>, ConfigError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| ConfigError::Message(format!("TLS {} file {}: {}", kind, path, e)))
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, ConfigError> {
+    let certs = rustls_pemfile::certs(&mut open(path, "certificate")?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| ConfigError::Message(format!("TLS certificate file {} is malformed: {}", path, e)))?;
+
+    if certs.is_empty() {
+        return Err(ConfigError::Message(format!("TLS certificate file {} contains no certificates", path)));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &str) -> Result<PrivateKeyDer<'static>, ConfigError> {
+    rustls_pemfile::private_key(&mut open(path, "key")?)
+        .map_err(|e| ConfigError::Message(format!("TLS key file {} is malformed: {}", path, e)))?
+        .ok_or_else(|| ConfigError::Message(format!("TLS key file {} contains no private key", path)))
+}
+
+/// Build the rustls server configuration from the configured certificate chain and key
+pub fn load_rustls_config(tls: &TlsConfig) -> Result<rustls::ServerConfig, ConfigError> {
+    let cert_path = tls.cert_path.as_deref()
+        .ok_or_else(|| ConfigError::Message("tls.cert_path is required when TLS is enabled".into()))?;
+    let key_path = tls.key_path.as_deref()
+        .ok_or_else(|| ConfigError::Message("tls.key_path is required when TLS is enabled".into()))?;
+
+    let certs = load_certs(cert_path)?;
+    let key = load_key(key_path)?;
+
+    rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| ConfigError::Message(format!("TLS setup failed: {}", e)))?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| ConfigError::Message(format!("TLS certificate and key are invalid: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tls_config(cert_path: &str, key_path: &str) -> TlsConfig {
+        TlsConfig {
+            enabled: true,
+            cert_path: Some(cert_path.to_string()),
+            key_path: Some(key_path.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_load_rustls_config() {
+        let config = tls_config("tests/certs/cert.pem", "tests/certs/key.pem");
+        assert!(load_rustls_config(&config).is_ok());
+    }
+
+    #[test]
+    fn test_missing_tls_files() {
+        let config = tls_config("tests/certs/missing.pem", "tests/certs/key.pem");
+        let err = load_rustls_config(&config).unwrap_err();
+        assert!(err.to_string().contains("missing.pem"));
+
+        let config = TlsConfig { enabled: true, cert_path: None, key_path: None };
+        assert!(load_rustls_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_malformed_tls_files() {
+        // A key file holds no certificates, and vice versa
+        let config = tls_config("tests/certs/key.pem", "tests/certs/cert.pem");
+        assert!(load_rustls_config(&config).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use crate::auth::password::PasswordParams;
+use crate::auth::{SessionLimitPolicy, SessionTokenType};
+use crate::error::AppError;
+use crate::proxy::{content_filter_from_config, provider_by_name, ApiKeyManager, ConcurrencyLimit};
+use crate::scaling::executor_from_config;
+
+use super::{BindAddress, Settings};
+
+/// JWT secrets shipped in example configs, refused outside development
+const PLACEHOLDER_JWT_SECRETS: [&str; 3] = ["development_secret", "test_secret", "your-secret-key-here"];
+/// Shortest JWT secret accepted outside development, in bytes
+const MIN_JWT_SECRET_BYTES: usize = 32;
+
+/// Message of a config error without the `Configuration error:` prefix
+fn message(error: AppError) -> String {
+    match error {
+        AppError::ConfigError(message) => message,
+        other => other.to_string(),
+    }
+}
+
+fn is_percentage(value: f32) -> bool {
+    value > 0.0 && value <= 100.0
+}
+
+impl Settings {
+    /// Check every setting and report all problems at once, so a broken
+    /// deployment can be fixed in one pass rather than one error per restart
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+        let development = matches!(self.environment.as_str(), "development" | "test");
+
+        // Server
+        self.server.check(&mut problems);
+        if let Some(bind) = &self.server.bind {
+            if let Err(e) = BindAddress::parse(bind) {
+                problems.push(e.to_string());
+            }
+        }
+
+        // Database
+        if self.database.url.trim().is_empty() {
+            problems.push("database.url must be set".into());
+        }
+        if self.database.max_connections == 0 {
+            problems.push("database.max_connections must be at least 1".into());
+        }
+        if self.database.min_connections > self.database.max_connections {
+            problems.push(format!(
+                "database.min_connections ({}) exceeds database.max_connections ({})",
+                self.database.min_connections, self.database.max_connections
+            ));
+        }
+
+        // Auth
+        let secret = self.auth.jwt_secret.trim();
+        if secret.is_empty() {
+            problems.push("auth.jwt_secret must be set".into());
+        } else if !development {
+            if PLACEHOLDER_JWT_SECRETS.contains(&secret) {
+                problems.push(format!(
+                    "auth.jwt_secret is a placeholder value, which is not allowed in {}",
+                    self.environment
+                ));
+            } else if secret.len() < MIN_JWT_SECRET_BYTES {
+                problems.push(format!("auth.jwt_secret must be at least {} bytes", MIN_JWT_SECRET_BYTES));
+            }
+        }
+        if self.auth.token_expiry_hours <= 0 {
+            problems.push("auth.token_expiry_hours must be positive".into());
+        }
+        if self.auth.guest_session_minutes <= 0 {
+            problems.push("auth.guest_session_minutes must be positive".into());
+        }
+        if self.auth.jwt_issuer.trim().is_empty() {
+            problems.push("auth.jwt_issuer must not be empty".into());
+        }
+        if self.auth.jwt_audience.trim().is_empty() {
+            problems.push("auth.jwt_audience must not be empty".into());
+        }
+        if let Err(e) = SessionLimitPolicy::from_config(&self.auth.session_limit_policy) {
+            problems.push(message(e));
+        }
+        if let Err(e) = SessionTokenType::from_config(&self.auth.session_token_type) {
+            problems.push(message(e));
+        }
+        if let Err(e) = PasswordParams::from_config(&self.auth) {
+            problems.push(message(e));
+        }
+        if self.auth.lockout.max_failures == 0 {
+            problems.push("auth.lockout.max_failures must be at least 1".into());
+        }
+
+        // Scaling
+        let scaling = &self.scaling;
+        if let Err(e) = executor_from_config(scaling) {
+            problems.push(message(e));
+        }
+        if !is_percentage(scaling.cpu_threshold) {
+            problems.push(format!("scaling.cpu_threshold must be in (0, 100], got {}", scaling.cpu_threshold));
+        }
+        if !is_percentage(scaling.memory_threshold) {
+            problems.push(format!("scaling.memory_threshold must be in (0, 100], got {}", scaling.memory_threshold));
+        }
+        if scaling.connection_threshold == 0 {
+            problems.push("scaling.connection_threshold must be at least 1".into());
+        }
+        if scaling.scale_up_factor <= 1.0 {
+            problems.push(format!("scaling.scale_up_factor must be greater than 1, got {}", scaling.scale_up_factor));
+        }
+        if scaling.scale_down_factor <= 0.0 || scaling.scale_down_factor >= 1.0 {
+            problems.push(format!(
+                "scaling.scale_down_factor must be between 0 and 1, got {}",
+                scaling.scale_down_factor
+            ));
+        }
+        let cooldowns = [
+            ("cooldown_period", Some(scaling.cooldown_period)),
+            ("scale_up_cooldown", scaling.scale_up_cooldown),
+            ("scale_down_cooldown", scaling.scale_down_cooldown),
+        ];
+        for (name, value) in cooldowns {
+            if value.is_some_and(|v| v < 0) {
+                problems.push(format!("scaling.{} must not be negative", name));
+            }
+        }
+
+        // CORS
+        if self.cors.enabled && !self.cors.allow_any_origin && self.cors.allowed_origins.is_empty() {
+            problems.push("cors.allowed_origins is empty; list origins or set cors.allow_any_origin".into());
+        }
+
+        // Proxy
+        if self.proxy.request_timeout_ms == 0 {
+            problems.push("proxy.request_timeout_ms must be positive".into());
+        }
+        if self.proxy.providers.is_empty() {
+            problems.push("proxy.providers must name at least one provider".into());
+        }
+        for name in &self.proxy.providers {
+            if provider_by_name(name).is_none() {
+                problems.push(format!("proxy.providers: unknown provider '{}'", name));
+            }
+        }
+        if let Err(e) = content_filter_from_config(&self.proxy) {
+            problems.push(message(e));
+        }
+        if let Err(e) = ConcurrencyLimit::from_config(&self.proxy) {
+            problems.push(message(e));
+        }
+        if let Err(e) = ApiKeyManager::from_config(&self.proxy, &self.environment) {
+            problems.push(message(e));
+        }
+
+        // TLS
+        if self.tls.enabled {
+            if self.tls.cert_path.as_deref().is_none_or(str::is_empty) {
+                problems.push("tls.cert_path is required when tls.enabled is set".into());
+            }
+            if self.tls.key_path.as_deref().is_none_or(str::is_empty) {
+                problems.push("tls.key_path is required when tls.enabled is set".into());
+            }
+        }
+
+        // WebSocket
+        if !is_percentage(self.websocket.busy_cpu_threshold) {
+            problems.push(format!(
+                "websocket.busy_cpu_threshold must be in (0, 100], got {}",
+                self.websocket.busy_cpu_threshold
+            ));
+        }
+
+        // Webhooks
+        if let Some(url) = &self.webhooks.url {
+            if !url::Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https")) {
+                problems.push(format!("webhooks.url must be an http(s) URL, got '{}'", url));
+            }
+            if self.webhooks.secret.as_deref().is_none_or(str::is_empty) {
+                problems.push("webhooks.secret is required when webhooks.url is set".into());
+            }
+            if self.webhooks.max_attempts == 0 {
+                problems.push("webhooks.max_attempts must be at least 1".into());
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Settings {
+        let _env = crate::config::tests::ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        Settings::new_for_test().expect("Failed to load settings")
+    }
+
+    #[test]
+    fn test_defaults_are_valid() {
+        assert_eq!(settings().validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_reports_every_problem() {
+        let mut settings = settings();
+        settings.environment = "production".to_string();
+        settings.auth.jwt_secret = "development_secret".to_string();
+        settings.server.workers = 0;
+        settings.cors.allowed_origins.clear();
+        settings.scaling.cpu_threshold = 150.0;
+        settings.scaling.scale_down_factor = 2.0;
+        settings.proxy.providers = vec!["nonexistent".to_string()];
+        settings.auth.session_limit_policy = "random".to_string();
+        settings.webhooks.url = Some("ftp://hooks.example.com".to_string());
+
+        let problems = settings.validate().unwrap_err();
+        let expected = [
+            "server.workers",
+            "auth.jwt_secret",
+            "cors.allowed_origins",
+            "scaling.cpu_threshold",
+            "scaling.scale_down_factor",
+            "unknown provider 'nonexistent'",
+            "session limit policy 'random'",
+            "webhooks.url",
+            "webhooks.secret",
+            // The test encryption key is the development one
+            "proxy.encryption_key",
+        ];
+        for needle in expected {
+            assert!(
+                problems.iter().any(|p| p.contains(needle)),
+                "Expected a problem mentioning {:?} in {:?}",
+                needle,
+                problems
+            );
+        }
+        assert_eq!(problems.len(), expected.len(), "Unexpected problems: {:?}", problems);
+    }
+
+    #[test]
+    fn test_min_connections_above_max() {
+        let mut settings = settings();
+        settings.database.max_connections = 5;
+        settings.database.min_connections = 5;
+        assert!(settings.validate().is_ok());
+
+        settings.database.min_connections = 6;
+        let problems = settings.validate().unwrap_err();
+        assert_eq!(problems, vec!["database.min_connections (6) exceeds database.max_connections (5)".to_string()]);
+    }
+
+    #[test]
+    fn test_short_secret_outside_development() {
+        let mut settings = settings();
+        settings.auth.jwt_secret = "short".to_string();
+        assert!(settings.validate().is_ok(), "Short secrets are fine in development");
+
+        settings.environment = "staging".to_string();
+        settings.proxy.encryption_key = None;
+        let problems = settings.validate().unwrap_err();
+        assert!(problems.iter().any(|p| p.contains("at least 32 bytes")));
+        assert!(problems.iter().any(|p| p.contains("proxy.encryption_key is not set")));
+    }
+}
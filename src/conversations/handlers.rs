@@ -0,0 +1,105 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::auth::middleware::require_user;
+use crate::error::{Error, FieldError};
+use crate::AppState;
+
+/// Page size when the caller doesn't ask for one
+const DEFAULT_MESSAGES_PAGE_SIZE: i64 = 50;
+/// Largest page a caller may request
+pub const MAX_MESSAGES_PAGE_SIZE: i64 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct MessagesQuery {
+    /// Only return turns older than this: an RFC 3339 timestamp or a message id
+    pub before: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Where a page of history ends
+#[derive(Debug, PartialEq)]
+enum Cursor {
+    Time(DateTime<Utc>),
+    Message(Uuid),
+}
+
+fn parse_cursor(before: &str) -> Result<Cursor, Error> {
+    if let Ok(id) = Uuid::parse_str(before) {
+        return Ok(Cursor::Message(id));
+    }
+    DateTime::parse_from_rfc3339(before)
+        .map(|t| Cursor::Time(t.with_timezone(&Utc)))
+        .map_err(|_| Error::Validation(vec![FieldError::new(
+            "before",
+            "Must be an RFC 3339 timestamp or a message id",
+        )]))
+}
+
+fn validate_limit(limit: Option<i64>) -> Result<i64, Error> {
+    match limit {
+        None => Ok(DEFAULT_MESSAGES_PAGE_SIZE),
+        Some(limit) if (1..=MAX_MESSAGES_PAGE_SIZE).contains(&limit) => Ok(limit),
+        Some(_) => Err(Error::Validation(vec![FieldError::new(
+            "limit",
+            &format!("Must be between 1 and {}", MAX_MESSAGES_PAGE_SIZE),
+        )])),
+    }
+}
+
+/// Page through one of the caller's conversations, newest turn first.
+/// `next_before` is the cursor for the following page, or null on the last one.
+pub async fn list_messages(
+    req: HttpRequest,
+    conversation_id: web::Path<Uuid>,
+    query: web::Query<MessagesQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let user = require_user(&req, &state).await?;
+    let limit = validate_limit(query.limit)?;
+    let (before, before_id) = match query.before.as_deref().map(parse_cursor).transpose()? {
+        Some(Cursor::Time(at)) => (Some(at), None),
+        Some(Cursor::Message(id)) => (None, Some(id)),
+        None => (None, None),
+    };
+
+    let messages = state.db
+        .get_messages_page(*conversation_id, user.id, before, before_id, limit)
+        .await?;
+    let next_before = if messages.len() as i64 == limit {
+        messages.last().map(|m| m.id)
+    } else {
+        None
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "messages": messages,
+        "next_before": next_before
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cursor() {
+        let id = Uuid::new_v4();
+        assert_eq!(parse_cursor(&id.to_string()).unwrap(), Cursor::Message(id));
+
+        let at = DateTime::parse_from_rfc3339("2025-04-18T09:00:00+02:00").unwrap().with_timezone(&Utc);
+        assert_eq!(parse_cursor("2025-04-18T09:00:00+02:00").unwrap(), Cursor::Time(at));
+
+        assert!(matches!(parse_cursor("yesterday"), Err(Error::Validation(_))));
+    }
+
+    #[test]
+    fn test_limit_validation() {
+        assert_eq!(validate_limit(None).unwrap(), DEFAULT_MESSAGES_PAGE_SIZE);
+        assert_eq!(validate_limit(Some(MAX_MESSAGES_PAGE_SIZE)).unwrap(), MAX_MESSAGES_PAGE_SIZE);
+        assert!(validate_limit(Some(0)).is_err());
+        assert!(validate_limit(Some(MAX_MESSAGES_PAGE_SIZE + 1)).is_err());
+    }
+}
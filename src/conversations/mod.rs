@@ -0,0 +1,8 @@
+//! Conversation history endpoints for BuddyBot server
+//!
+//! Conversations are created over the WebSocket; these routes let their
+//! owners read them back.
+
+pub mod handlers;
+
+pub use handlers::list_messages;
@@ -9,5 +9,5 @@
 pub mod models;
 pub mod operations;
 
-pub use models::{User, UserSession};
-pub use operations::DbOperations;
+pub use models::{AuthAuditEvent, Conversation, ConversationMessage, OutboxMessage, User, UserSession, UserSetting, UserStats};
+pub use operations::{connect_with_retry, DbOperations, PurgeSummary};
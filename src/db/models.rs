@@ -3,6 +3,9 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
+/// Rate limit tier assigned to guest users
+pub const GUEST_RATE_LIMIT_TIER: &str = "guest";
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct User {
     pub id: Uuid,
@@ -13,6 +16,8 @@ pub struct User {
     pub last_login: Option<DateTime<Utc>>,
     pub is_active: bool,
     pub rate_limit_tier: String,
+    /// Transient user behind an anonymous guest session
+    pub is_guest: bool,
 }
 
 impl User {
@@ -27,6 +32,21 @@ impl User {
             last_login: None,
             is_active: true,
             rate_limit_tier: "standard".to_string(),
+            is_guest: false,
+        }
+    }
+
+    /// A throwaway user for a guest session. The email only satisfies the unique
+    /// constraint; `.invalid` guarantees it can never be delivered to.
+    pub fn guest() -> Self {
+        let id = Uuid::new_v4();
+        Self {
+            id,
+            email: format!("guest-{}@guest.invalid", id),
+            display_name: Some("Guest".to_string()),
+            rate_limit_tier: GUEST_RATE_LIMIT_TIER.to_string(),
+            is_guest: true,
+            ..Self::new(String::new(), None)
         }
     }
 }
@@ -43,18 +63,86 @@ pub struct UserSession {
 
 impl UserSession {
     pub fn new(user_id: Uuid, token: String, expires_in_hours: i64) -> Self {
+        Self::expiring_in(user_id, token, chrono::Duration::hours(expires_in_hours))
+    }
+
+    pub fn expiring_in(user_id: Uuid, token: String, lifetime: chrono::Duration) -> Self {
         let now = Utc::now();
         Self {
             id: Uuid::new_v4(),
             user_id,
             token,
-            expires_at: now + chrono::Duration::hours(expires_in_hours),
+            expires_at: now + lifetime,
             created_at: now,
             last_activity: now,
         }
     }
 
-    pub fn is_expired(&self) -> bool {
-        Utc::now() > self.expires_at
+    /// Whether the session ended more than `leeway` ago. The leeway absorbs
+    /// clock skew between the node that issued it and the one checking.
+    pub fn is_expired(&self, leeway: chrono::Duration) -> bool {
+        Utc::now() > self.expires_at + leeway
     }
-} 
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Conversation {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ConversationMessage {
+    pub id: Uuid,
+    pub conversation_id: Uuid,
+    pub role: String,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ConversationMessage {
+    pub fn new(conversation_id: Uuid, role: &str, content: String) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            conversation_id,
+            role: role.to_string(),
+            content,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// A recorded authentication attempt (login, registration, logout)
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AuthAuditEvent {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub event: String,
+    pub ip: String,
+    pub success: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A single per-user preference, stored as arbitrary JSON
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UserSetting {
+    pub key: String,
+    pub value: serde_json::Value,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Lifetime usage counters for a user, as last flushed from memory
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UserStats {
+    pub query_count: i64,
+    pub last_query_at: Option<DateTime<Utc>>,
+}
+
+/// A message queued for an offline user, claimed for delivery by `drain_outbox_for_user`
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct OutboxMessage {
+    pub id: i64,
+    pub payload: serde_json::Value,
+}
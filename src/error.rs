@@ -1,6 +1,74 @@
 use thiserror::Error;
 use actix_web::{ResponseError, HttpResponse, http::StatusCode};
-use serde_json::json;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Body of every HTTP error response. Clients can depend on this shape: fields
+/// are only ever added, and optional ones are left out when they don't apply.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub error: ErrorBody,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    /// HTTP status, repeated for clients that only see the body
+    pub status: u16,
+    pub message: String,
+    /// Stable machine-readable code, as returned by [`Error::code`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+    /// Identifies the failed request in server logs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Per-field validation failures
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+    /// How long to wait before retrying, when the server knows
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_seconds: Option<i64>,
+}
+
+impl ErrorResponse {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            error: ErrorBody {
+                status: status.as_u16(),
+                message: message.into(),
+                code: None,
+                request_id: None,
+                fields: Vec::new(),
+                retry_after_seconds: None,
+            },
+        }
+    }
+
+    pub fn with_code(mut self, code: &str) -> Self {
+        self.error.code = Some(code.to_string());
+        self
+    }
+
+    pub fn with_request_id(mut self, request_id: impl Into<String>) -> Self {
+        self.error.request_id = Some(request_id.into());
+        self
+    }
+
+    pub fn with_fields(mut self, fields: Vec<FieldError>) -> Self {
+        self.error.fields = fields;
+        self
+    }
+
+    pub fn with_retry_after(mut self, seconds: i64) -> Self {
+        self.error.retry_after_seconds = Some(seconds);
+        self
+    }
+
+    /// Respond with this body and the status it carries
+    pub fn into_response(self) -> HttpResponse {
+        let status = StatusCode::from_u16(self.error.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        HttpResponse::build(status).json(self)
+    }
+}
 
 #[derive(Error, Debug)]
 pub enum AppError {
@@ -53,15 +121,7 @@ impl From<std::io::Error> for AppError {
 // Implement actix_web::ResponseError for AppError
 impl ResponseError for AppError {
     fn error_response(&self) -> HttpResponse {
-        let status = self.status_code();
-        let message = self.to_string();
-        let response = json!({
-            "error": {
-                "status": status.as_u16(),
-                "message": message
-            }
-        });
-        HttpResponse::build(status).json(response)
+        ErrorResponse::new(self.status_code(), self.to_string()).into_response()
     }
 
     fn status_code(&self) -> StatusCode {
@@ -77,6 +137,9 @@ impl ResponseError for AppError {
             AppError::ConfigError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::DatabaseError(DatabaseError::NotFound) => StatusCode::NOT_FOUND,
             AppError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::ProxyError(ProxyError::Timeout) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::ProxyError(ProxyError::Disabled | ProxyError::Busy { .. }) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::ProxyError(ProxyError::ContentRejected(_)) => StatusCode::UNPROCESSABLE_ENTITY,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -116,6 +179,9 @@ pub enum WebSocketError {
 pub enum ProxyError {
     #[error("API request failed: {0}")]
     RequestFailed(String),
+
+    #[error("upstream returned status {0}")]
+    UpstreamStatus(u16),
     
     #[error("Invalid API key")]
     InvalidApiKey,
@@ -125,6 +191,34 @@ pub enum ProxyError {
     
     #[error("API response error: {0}")]
     ResponseError(String),
+
+    #[error("request timed out")]
+    Timeout,
+
+    #[error("proxy is disabled")]
+    Disabled,
+
+    #[error("content rejected: {0}")]
+    ContentRejected(String),
+
+    #[error("no API key is available for this request")]
+    MissingApiKey,
+
+    #[error("too many requests in flight; retry in {retry_after_ms}ms")]
+    Busy { retry_after_ms: u64 },
+
+    #[error("model '{0}' is not allowed")]
+    ModelNotAllowed(String),
+}
+
+impl ProxyError {
+    /// Whether another provider might succeed where this one failed
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ProxyError::RequestFailed(_) | ProxyError::RateLimited | ProxyError::Timeout
+        ) || matches!(self, ProxyError::UpstreamStatus(status) if *status >= 500)
+    }
 }
 
 #[derive(Error, Debug)]
@@ -142,6 +236,53 @@ pub enum DatabaseError {
     Duplicate,
 }
 
+/// A single input validation failure tied to the request field that caused it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// One field error per failing field, in field order, taking the first
+/// message reported for each
+impl From<validator::ValidationErrors> for Error {
+    fn from(errors: validator::ValidationErrors) -> Self {
+        let mut fields: Vec<FieldError> = errors
+            .field_errors()
+            .into_iter()
+            .filter_map(|(field, errors)| {
+                let error = errors.first()?;
+                let message = error.message.as_deref().unwrap_or("Invalid value");
+                Some(FieldError::new(&field, message))
+            })
+            .collect();
+        fields.sort_by(|a, b| a.field.cmp(&b.field));
+        Error::Validation(fields)
+    }
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+fn join_field_errors(fields: &[FieldError]) -> String {
+    fields.iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Database error: {0}")]
@@ -155,32 +296,101 @@ pub enum Error {
     
     #[error("Unauthorized: {0}")]
     Unauthorized(String),
+
+    #[error("Authentication error: {0}")]
+    Auth(AuthError),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
     
     #[error("External error: {0}")]
     External(String),
     
     #[error("UUID parse error: {0}")]
     Uuid(#[from] uuid::Error),
+
+    #[error("Validation error: {}", join_field_errors(.0))]
+    Validation(Vec<FieldError>),
+
+    #[error("Request body exceeds the {0} byte limit")]
+    PayloadTooLarge(usize),
+
+    #[error("Service unavailable: {0}")]
+    Unavailable(String),
+
+    #[error("Rate limit of {0} requests per window exceeded")]
+    RateLimited(u32),
+
+    #[error(transparent)]
+    Proxy(#[from] ProxyError),
+}
+
+impl Error {
+    /// Stable machine-readable code for clients to branch on
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Unauthorized(_) | Error::Jwt(_) => "unauthorized",
+            Error::Auth(AuthError::TokenExpired) => "token_expired",
+            Error::Auth(AuthError::InvalidToken) => "invalid_token",
+            Error::Auth(AuthError::RateLimited) => "rate_limited",
+            Error::Auth(AuthError::Unauthorized) => "forbidden",
+            Error::Auth(AuthError::InvalidCredentials) => "unauthorized",
+            Error::Forbidden(_) => "forbidden",
+            Error::NotFound(_) => "not_found",
+            Error::Conflict(_) => "conflict",
+            Error::Validation(_) | Error::Uuid(_) => "invalid_request",
+            Error::PayloadTooLarge(_) => "payload_too_large",
+            Error::Unavailable(_) => "unavailable",
+            Error::RateLimited(_) => "rate_limited",
+            Error::Proxy(ProxyError::RateLimited) => "rate_limited",
+            Error::Proxy(ProxyError::Timeout) => "upstream_timeout",
+            Error::Proxy(ProxyError::Disabled) => "unavailable",
+            Error::Proxy(ProxyError::ContentRejected(_)) => "content_rejected",
+            Error::Proxy(ProxyError::MissingApiKey) => "missing_api_key",
+            Error::Proxy(ProxyError::Busy { .. }) => "busy",
+            Error::Proxy(ProxyError::ModelNotAllowed(_)) => "model_not_allowed",
+            Error::Proxy(_) | Error::Http(_) => "upstream_error",
+            Error::Database(_) | Error::External(_) => "internal_error",
+        }
+    }
 }
 
 impl actix_web::ResponseError for Error {
     fn error_response(&self) -> actix_web::HttpResponse {
-        let status = self.status_code();
-        let message = self.to_string();
-        actix_web::HttpResponse::build(status).json(serde_json::json!({
-            "error": {
-                "status": status.as_u16(),
-                "message": message
-            }
-        }))
+        let mut response = ErrorResponse::new(self.status_code(), self.to_string()).with_code(self.code());
+        if let Error::Validation(fields) = self {
+            response = response.with_fields(fields.clone());
+        }
+        response.into_response()
     }
 
     fn status_code(&self) -> actix_web::http::StatusCode {
         use actix_web::http::StatusCode;
         match self {
             Error::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Error::Forbidden(_) => StatusCode::FORBIDDEN,
+            Error::NotFound(_) => StatusCode::NOT_FOUND,
+            Error::Conflict(_) => StatusCode::CONFLICT,
             Error::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Error::Jwt(_) => StatusCode::UNAUTHORIZED,
+            Error::Auth(AuthError::Unauthorized) => StatusCode::FORBIDDEN,
+            Error::Auth(AuthError::RateLimited) => StatusCode::TOO_MANY_REQUESTS,
+            Error::Auth(_) => StatusCode::UNAUTHORIZED,
+            Error::Validation(_) => StatusCode::BAD_REQUEST,
+            Error::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Error::Proxy(ProxyError::Timeout) => StatusCode::GATEWAY_TIMEOUT,
+            Error::Proxy(ProxyError::Disabled | ProxyError::Busy { .. }) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::Proxy(ProxyError::ContentRejected(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::Proxy(ProxyError::MissingApiKey | ProxyError::ModelNotAllowed(_)) => StatusCode::BAD_REQUEST,
+            Error::Proxy(_) => StatusCode::BAD_GATEWAY,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -238,4 +448,78 @@ mod tests {
         let err = AppError::DatabaseError(DatabaseError::NotFound);
         assert_eq!(err.to_string(), "Database error: Record not found");
     }
-}
\ No newline at end of file
+
+    #[actix_web::test]
+    async fn test_validation_error_fields() {
+        let err = Error::Validation(vec![
+            FieldError::new("email", "Invalid email address"),
+            FieldError::new("password", "Password cannot be empty"),
+        ]);
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            err.to_string(),
+            "Validation error: email: Invalid email address; password: Password cannot be empty"
+        );
+
+        let body = actix_web::body::to_bytes(err.error_response().into_body()).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["error"]["status"], 400);
+        assert_eq!(json["error"]["fields"][0]["field"], "email");
+        assert_eq!(json["error"]["fields"][0]["message"], "Invalid email address");
+        assert_eq!(json["error"]["fields"][1]["field"], "password");
+    }
+
+    #[test]
+    fn test_error_response_shape() {
+        let minimal = ErrorResponse::new(StatusCode::NOT_FOUND, "Conversation not found");
+        assert_eq!(
+            serde_json::to_value(&minimal).unwrap(),
+            serde_json::json!({ "error": { "status": 404, "message": "Conversation not found" } })
+        );
+
+        let full = ErrorResponse::new(StatusCode::TOO_MANY_REQUESTS, "Slow down")
+            .with_code("rate_limited")
+            .with_request_id("req-1")
+            .with_fields(vec![FieldError::new("email", "Too many attempts")])
+            .with_retry_after(30);
+        let json = serde_json::to_value(&full).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "error": {
+                    "status": 429,
+                    "message": "Slow down",
+                    "code": "rate_limited",
+                    "request_id": "req-1",
+                    "fields": [{ "field": "email", "message": "Too many attempts" }],
+                    "retry_after_seconds": 30
+                }
+            })
+        );
+        assert_eq!(serde_json::from_value::<ErrorResponse>(json).unwrap(), full);
+    }
+
+    #[actix_web::test]
+    async fn test_error_response_includes_code() {
+        let err = Error::NotFound("Conversation not found".into());
+        let body = actix_web::body::to_bytes(err.error_response().into_body()).await.unwrap();
+        let response: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(response.error.status, 404);
+        assert_eq!(response.error.code.as_deref(), Some("not_found"));
+    }
+
+    #[actix_web::test]
+    async fn test_token_errors_are_distinct() {
+        for (err, code, message) in [
+            (AuthError::TokenExpired, "token_expired", "Authentication error: Token expired"),
+            (AuthError::InvalidToken, "invalid_token", "Authentication error: Invalid token"),
+        ] {
+            let response = Error::Auth(err).error_response();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+            let response: ErrorResponse = serde_json::from_slice(&body).unwrap();
+            assert_eq!(response.error.code.as_deref(), Some(code));
+            assert_eq!(response.error.message, message);
+        }
+    }
+}
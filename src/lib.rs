@@ -1,88 +1,288 @@
+pub mod admin;
 pub mod auth;
 pub mod config;
+pub mod conversations;
 pub mod db;
 pub mod error;
+pub mod maintenance;
+pub mod middleware;
 pub mod proxy;
 pub mod scaling;
+pub mod telemetry;
+pub mod users;
+pub mod webhooks;
 pub mod websocket;
 
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use sqlx::PgPool;
-use actix_web::HttpResponse;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use std::str::FromStr;
+use actix_web::{web, HttpResponse};
+use tracing::{info, warn};
+use crate::auth::password::PasswordParams;
+use crate::auth::{SessionLimitPolicy, SessionTokenType};
+use crate::maintenance::MaintenanceMode;
+use crate::webhooks::Webhooks;
+use crate::websocket::Backpressure;
+use crate::middleware::AcceptRateLimiter;
+use crate::scaling::executor_from_config;
 
 pub use error::AppError;
 pub type Result<T> = std::result::Result<T, AppError>;
 pub use config::Settings;
 
-pub use auth::{AuthService, RateLimiter, RateLimitConfig};
+pub use auth::{AuthService, LoginLockout, LockoutConfig, RateLimiter, RateLimitConfig};
 pub use auth::handlers::{login, register, logout};
 pub use db::{DbOperations, User, UserSession};
+pub use proxy::{ApiKeyManager, ProxyService, LlmProvider};
 pub use scaling::{ScalingManager, ScalingConfig, InstanceInfo};
 pub use websocket::WebSocketServer;
 
 /// Health check endpoint handler
-/// Returns a JSON response with server status and timestamp
-pub async fn health_check() -> HttpResponse {
+/// Returns a JSON response with server status, timestamp and local load
+pub async fn health_check(state: web::Data<AppState>) -> HttpResponse {
+    let instances = state.scaling.get_active_instances().await;
+    let active_connections = state.ws_server.pool().connection_count().await;
+    let database = state.db.get_pool_status().await.ok();
+
     HttpResponse::Ok().json(serde_json::json!({
         "status": "healthy",
-        "timestamp": chrono::Utc::now().to_rfc3339()
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "instances": instances,
+        "active_connections": active_connections,
+        "database": database,
+        "uptime_seconds": state.started_at.elapsed().as_secs(),
     }))
 }
 
+/// Readiness endpoint handler
+/// Returns 503 until the database and, unless configured otherwise, an LLM provider are reachable
+pub async fn readiness(state: web::Data<AppState>) -> HttpResponse {
+    let database_ok = state.db.ping().await.is_ok();
+
+    // A disabled proxy has nothing to check
+    let provider_ok = if state.config.features.proxy_enabled {
+        Some(state.ws_server.proxy().health().await.is_ok())
+    } else {
+        None
+    };
+    let provider_required = state.config.proxy.require_healthy_provider;
+
+    let ready = database_ok && (provider_ok != Some(false) || !provider_required);
+    let mut response = if ready {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+
+    response.json(serde_json::json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "database_ok": database_ok,
+        "provider_ok": provider_ok,
+    }))
+}
+
+/// Report which build is running: package version, git commit and build time
+pub async fn version() -> HttpResponse {
+    let built_at = env!("BUILD_TIMESTAMP").parse::<i64>().ok()
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .map(|t| t.to_rfc3339());
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "commit": env!("BUILD_GIT_COMMIT"),
+        "built_at": built_at,
+    }))
+}
+
+/// Prometheus scrape endpoint for upstream LLM call metrics
+pub async fn metrics(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(state.ws_server.proxy().metrics().render())
+}
+
 /// Application state shared across all components
 #[derive(Clone)]
 pub struct AppState {
     pub config: Arc<Settings>,
     pub db_pool: Arc<PgPool>,
+    /// Shared query handle; its pool status counts callers waiting for a connection
+    pub db: DbOperations,
     pub scaling: Arc<ScalingManager>,
     pub auth_service: Arc<AuthService>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub login_lockout: Arc<LoginLockout>,
     pub ws_server: Arc<WebSocketServer>,
+    pub api_keys: Arc<ApiKeyManager>,
+    /// Read-only switch flipped by `POST /admin/maintenance`
+    pub maintenance: Arc<MaintenanceMode>,
+    /// Caps how fast new connections are admitted; `None` when unlimited
+    pub accept_limiter: Option<Arc<AcceptRateLimiter>>,
+    pub started_at: Instant,
 }
 
 impl AppState {
     pub async fn new(config: Settings) -> Result<Self> {
+        // Validate the API key encryption key before touching anything else
+        let api_keys = Arc::new(ApiKeyManager::from_config(&config.proxy, &config.environment)?);
+
         // Initialize database connection pool
-        let db_pool = PgPool::connect(&config.database.url)
-            .await
-            .map_err(|e| AppError::DatabaseError(error::DatabaseError::ConnectionError(e.to_string())))?;
+        let connect_options = PgConnectOptions::from_str(&config.database.url)
+            .map_err(|e| AppError::DatabaseError(error::DatabaseError::ConnectionError(e.to_string())))?
+            .statement_cache_capacity(config.database.statement_cache_capacity);
+        let pool_options = PgPoolOptions::new()
+            .max_connections(config.database.max_connections)
+            .min_connections(config.database.min_connections)
+            .test_before_acquire(config.database.test_before_acquire);
+        // The database may still be starting, e.g. when deployed alongside us
+        let db_pool = db::connect_with_retry(
+            config.database.connect_attempts,
+            Duration::from_millis(config.database.connect_retry_delay_ms),
+            || pool_options.clone().connect_with(connect_options.clone()),
+        )
+        .await
+        .map_err(|e| AppError::DatabaseError(error::DatabaseError::ConnectionError(e.to_string())))?;
         
         let db_pool = Arc::new(db_pool);
+
+        // Warm up the pool before accepting traffic
+        DbOperations::new(db_pool.clone())
+            .warmup(config.database.min_connections)
+            .await
+            .map_err(|e| AppError::DatabaseError(error::DatabaseError::ConnectionError(e.to_string())))?;
+        info!("Database pool warmup complete ({} connections)", config.database.min_connections);
         
         // Initialize scaling manager
-        let scaling = Arc::new(ScalingManager::new(ScalingConfig::default()));
+        let scaling = Arc::new(
+            ScalingManager::new(ScalingConfig::from(&config.scaling))
+                .with_executor(executor_from_config(&config.scaling)?),
+        );
 
         // Initialize auth service
         let db_ops = DbOperations::new(db_pool.clone());
-        let auth_service = Arc::new(AuthService::new(
-            db_ops,
-            config.auth.jwt_secret.clone(),
-        ));
+        let auth_service = Arc::new(
+            AuthService::new(db_ops.clone(), config.auth.jwt_secret.clone())
+                .with_token_validation(config.auth.jwt_leeway_secs, config.auth.validate_exp)
+                .with_issuer_and_audience(&config.auth.jwt_issuer, &config.auth.jwt_audience)
+                .with_guest_session_minutes(config.auth.guest_session_minutes)
+                .with_session_limit(
+                    config.auth.max_sessions_per_user,
+                    SessionLimitPolicy::from_config(&config.auth.session_limit_policy)?,
+                )
+                .with_session_token_type(SessionTokenType::from_config(&config.auth.session_token_type)?)
+                .with_password_params(PasswordParams::from_config(&config.auth)?, config.auth.rehash_on_login)
+                .with_webhooks(Arc::new(Webhooks::from_config(&config.webhooks)?)),
+        );
+
+        // Initialize HTTP rate limiter
+        let rate_limiter = Arc::new(RateLimiter::new(RateLimitConfig::from_config(&config.auth.rate_limit)));
+        let login_lockout = Arc::new(LoginLockout::new(LockoutConfig::from_config(&config.auth.lockout)));
+
+        // Initialize LLM proxy
+        let proxy = Arc::new(
+            ProxyService::from_config(&config.proxy)?
+                .with_enabled(config.features.proxy_enabled)
+                .with_api_keys(api_keys.clone()),
+        );
+
+        // Start in maintenance mode if configured, e.g. to run a migration
+        let maintenance = Arc::new(MaintenanceMode::new(config.server.maintenance_mode));
+
+        // Shared by the HTTP middleware and raw WebSocket accepts
+        let accept_limiter = AcceptRateLimiter::from_config(&config.server).map(Arc::new);
 
         // Initialize WebSocket server
-        let ws_server = Arc::new(WebSocketServer::new(auth_service.clone()));
+        let ws_server = Arc::new(
+            WebSocketServer::new(auth_service.clone(), proxy, db_ops.clone())
+                .with_accept_limiter(accept_limiter.clone())
+                .with_backpressure(Backpressure::new(&config.websocket))
+                .with_maintenance(maintenance.clone())
+                .with_rate_limiter(rate_limiter.clone())
+                .with_idle_query_timeout(config.websocket.idle_query_timeout())
+                .with_max_connections_per_ip(config.websocket.max_connections_per_ip),
+        );
 
         Ok(Self {
             config: Arc::new(config),
             db_pool,
+            db: db_ops,
             scaling,
             auth_service,
+            rate_limiter,
+            login_lockout,
             ws_server,
+            api_keys,
+            maintenance,
+            accept_limiter,
+            started_at: Instant::now(),
         })
     }
 
-    pub async fn shutdown(&self) -> Result<()> {
-        // Close database connections
+    /// Release what this instance holds once the HTTP server has stopped:
+    /// write out pending session activity and query counts, close remaining
+    /// WebSocket connections, leave the instance registry and close the
+    /// database pool.
+    /// The summary is logged and returned.
+    pub async fn shutdown(&self) -> Result<ShutdownSummary> {
+        let started = Instant::now();
+        let queries_in_flight = self.ws_server.backpressure().in_flight();
+
+        // Don't lose activity recorded since the last periodic flush
+        let sessions_flushed = self.auth_service.flush_session_activity().await.unwrap_or_else(|e| {
+            warn!("Failed to flush session activity: {}", e);
+            0
+        });
+        let query_counts_flushed = self.ws_server.query_stats().flush().await.unwrap_or_else(|e| {
+            warn!("Failed to flush query counts: {}", e);
+            0
+        });
+        let connections_closed = self.ws_server.pool().disconnect_all().await;
+        let instance_deregistered = self.scaling.deregister_instance(self.config.server.instance_id).await;
+
         self.db_pool.close().await;
-        
-        // Additional cleanup can be added here
-        Ok(())
+
+        let summary = ShutdownSummary {
+            connections_closed,
+            queries_in_flight,
+            sessions_flushed,
+            query_counts_flushed,
+            instance_deregistered,
+            duration: started.elapsed(),
+        };
+        info!(
+            connections_closed = summary.connections_closed,
+            queries_in_flight = summary.queries_in_flight,
+            sessions_flushed = summary.sessions_flushed,
+            query_counts_flushed = summary.query_counts_flushed,
+            instance_deregistered = summary.instance_deregistered,
+            duration_ms = summary.duration.as_millis() as u64,
+            "Shutdown complete"
+        );
+        Ok(summary)
     }
 }
 
+/// What [`AppState::shutdown`] cleaned up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShutdownSummary {
+    /// WebSocket connections still open that were sent a close frame
+    pub connections_closed: usize,
+    /// Queries still running when shutdown began
+    pub queries_in_flight: usize,
+    /// Sessions whose buffered activity was written out
+    pub sessions_flushed: usize,
+    /// Users whose buffered query counts were written out
+    pub query_counts_flushed: usize,
+    /// Whether this instance was still in the scaling registry and was removed
+    pub instance_deregistered: bool,
+    pub duration: std::time::Duration,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tokio;
     use std::env;
 
     fn cleanup_env() {
@@ -116,17 +316,27 @@ mod tests {
         let pool_arc = Arc::new(pool);
         let db_ops = DbOperations::new(pool_arc.clone());
         let auth_service = Arc::new(AuthService::new(
-            db_ops,
+            db_ops.clone(),
             "test_secret".to_string(),
         ));
-        let ws_server = Arc::new(WebSocketServer::new(auth_service.clone()));
+        let rate_limiter = Arc::new(RateLimiter::new(RateLimitConfig::from_config(&config.auth.rate_limit)));
+        let login_lockout = Arc::new(LoginLockout::new(LockoutConfig::from_config(&config.auth.lockout)));
+        let proxy = Arc::new(ProxyService::new(Arc::new(proxy::EchoProvider), &config.proxy));
+        let ws_server = Arc::new(WebSocketServer::new(auth_service.clone(), proxy, db_ops.clone()));
 
         let state = AppState {
             config: Arc::new(config),
             db_pool: pool_arc,
+            db: db_ops,
             scaling,
             auth_service,
+            rate_limiter,
+            login_lockout,
             ws_server,
+            api_keys: Arc::new(ApiKeyManager::from_base64_key(proxy::DEVELOPMENT_ENCRYPTION_KEY).unwrap()),
+            maintenance: Arc::new(MaintenanceMode::default()),
+            accept_limiter: None,
+            started_at: Instant::now(),
         };
         
         let cloned = state.clone();
@@ -136,6 +346,7 @@ mod tests {
         assert!(Arc::ptr_eq(&state.db_pool, &cloned.db_pool));
         assert!(Arc::ptr_eq(&state.scaling, &cloned.scaling));
         assert!(Arc::ptr_eq(&state.auth_service, &cloned.auth_service));
+        assert!(Arc::ptr_eq(&state.rate_limiter, &cloned.rate_limiter));
         assert!(Arc::ptr_eq(&state.ws_server, &cloned.ws_server));
     }
 } 
\ No newline at end of file
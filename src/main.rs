@@ -1,212 +1,73 @@
-use actix_web::{web, App, HttpServer, HttpResponse, Error, HttpRequest};
-use actix_cors::Cors;
-use actix::prelude::*;
-use actix_web_actors::ws;
-use buddybot_server::{AppState, Settings, AppError};
-use buddybot_server::auth::handlers::{login, register, logout};
-use buddybot_server::websocket::{ClientMessage, ServerMessage};
+use actix_web::{web, App, HttpServer};
+use actix_web::http::KeepAlive;
+use actix_web::middleware::{from_fn, Compress, Condition};
+use buddybot_server::{health_check, metrics, readiness, version, AppState, Settings, AppError};
+use buddybot_server::config::{load_rustls_config, BindAddress};
+use buddybot_server::admin::handlers::{activate_user, admin_stats, deactivate_user, reset_user_password, send_notification, set_maintenance, user_stats};
+use buddybot_server::auth::handlers::{change_password, guest, list_audit_events, login, register, logout, validate};
+use buddybot_server::auth::middleware::rate_limit;
+use buddybot_server::middleware::{build_cors, json_config, limit_accept_rate, mark_connection, verify_signature};
+use buddybot_server::websocket::handlers::disconnect_connection;
+use buddybot_server::websocket::websocket_route;
+use buddybot_server::scaling::handlers::{heartbeat_batch, scaling_history, scaling_metrics, scaling_recommendation};
+use buddybot_server::users::handlers::{export_data, get_setting, purge_account, put_setting};
+use buddybot_server::proxy::handlers::{delete_api_key, put_api_key, query};
+use buddybot_server::conversations::handlers::list_messages;
+use buddybot_server::telemetry::{init_tracing, trace_request};
 use dotenv::dotenv;
-use std::net::TcpListener;
-use tracing::{info, error, warn, Level};
-use tracing_subscriber::FmtSubscriber;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use tokio::net::TcpSocket;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use tracing::{error, info, warn};
 use std::time::Duration;
-use std::sync::Arc;
-use uuid::Uuid;
 
-/// Health check endpoint handler
-/// Returns a JSON response with server status and timestamp
-async fn health_check(state: web::Data<AppState>) -> HttpResponse {
-    let instances = state.scaling.get_active_instances().await;
+/// Permissions applied to the Unix socket file (owner and group read/write)
+const UNIX_SOCKET_MODE: u32 = 0o660;
 
-    HttpResponse::Ok().json(serde_json::json!({
-        "status": "healthy",
-        "timestamp": chrono::Utc::now().to_rfc3339(),
-        "instances": instances,
-    }))
-}
-
-/// WebSocket connection handler
-/// This upgrades the HTTP connection to a WebSocket connection
-async fn websocket_route(
-    req: HttpRequest,
-    stream: web::Payload,
-    app_data: web::Data<AppState>,
-) -> std::result::Result<HttpResponse, Error> {
-    let peer_addr = req.peer_addr()
-        .map(|addr| addr.to_string())
-        .unwrap_or_else(|| "unknown".to_string());
-    
-    info!("New WebSocket connection request from: {}", peer_addr);
-    
-    // Create WebSocket actor and start it
-    ws::start(
-        WebSocketSession::new(app_data.ws_server.clone(), peer_addr),
-        &req,
-        stream,
-    )
-}
-
-/// WebSocket session actor that handles WebSocket connections
-struct WebSocketSession {
-    ws_server: Arc<buddybot_server::websocket::WebSocketServer>,
-    peer_addr: String,
-    id: Uuid,
-    authenticated: bool,
-}
-
-impl WebSocketSession {
-    fn new(ws_server: Arc<buddybot_server::websocket::WebSocketServer>, peer_addr: String) -> Self {
-        Self { 
-            ws_server,
-            peer_addr,
-            id: Uuid::new_v4(),
-            authenticated: false,
-        }
-    }
-
-    /// Process an incoming message and generate a response
-    fn handle_websocket_message(&mut self, text: String, ctx: &mut <Self as Actor>::Context) {
-        // Log the received message
-        info!("Received message from {}: {}", self.peer_addr, text);
-
-        // Parse the message as a ClientMessage
-        match serde_json::from_str::<ClientMessage>(&text) {
-            Ok(client_msg) => {
-                match client_msg {
-                    ClientMessage::Authenticate { token } => {
-                        info!("Authentication attempt from {}", self.peer_addr);
-                        // Forward to WebSocketServer for authentication
-                        Self::handle_auth_result(self, ctx, token);
-                    },
-                    ClientMessage::Query { text } => {
-                        if !self.authenticated {
-                            warn!("Unauthenticated query attempt from {}", self.peer_addr);
-                            self.send_error(ctx, "Not authenticated");
-                            return;
-                        }
-                        
-                        info!("Query from {}: {}", self.peer_addr, text);
-                        // Echo back the message for now
-                        // In a real implementation, this would process the query and generate a response
-                        self.send_response(ctx, &format!("Echo: {}", text));
-                    },
-                    ClientMessage::Ping => {
-                        // Respond with a pong message
-                        self.send_server_message(ctx, ServerMessage::Pong);
-                    },
-                    ClientMessage::Pong => {
-                        // Client responded to our ping, update heartbeat timestamp
-                        // This would typically update a last_heartbeat field
-                    },
-                }
-            },
-            Err(e) => {
-                error!("Failed to parse message from {}: {}", self.peer_addr, e);
-                self.send_error(ctx, &format!("Invalid message format: {}", e));
-            }
+/// Bind a Unix domain socket, replacing any stale socket file left by a previous run.
+/// Anything else at `path` is left alone and binding fails, so a mistyped path
+/// can't delete a regular file.
+fn bind_unix_socket(path: &Path) -> std::io::Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            warn!("Removing stale socket file at {}", path.display());
+            std::fs::remove_file(path)?;
         }
-    }
-
-    /// Handle authentication result
-    fn handle_auth_result(&mut self, ctx: &mut <Self as Actor>::Context, token: String) {
-        // In a real implementation, this would validate the token with your authentication service
-        // For the purpose of this example, we'll simply accept any token
-        if !token.is_empty() {
-            self.authenticated = true;
-            info!("Authentication successful for {}", self.peer_addr);
-            self.send_server_message(ctx, ServerMessage::AuthResult { 
-                success: true, 
-                error: None 
-            });
-        } else {
-            self.authenticated = false;
-            warn!("Authentication failed for {}", self.peer_addr);
-            self.send_server_message(ctx, ServerMessage::AuthResult { 
-                success: false, 
-                error: Some("Invalid token".to_string()) 
-            });
+        Ok(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
         }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
     }
 
-    /// Send a server message to the client
-    fn send_server_message(&self, ctx: &mut <Self as Actor>::Context, msg: ServerMessage) {
-        match serde_json::to_string(&msg) {
-            Ok(json_str) => {
-                ctx.text(json_str);
-            },
-            Err(e) => {
-                error!("Failed to serialize server message: {}", e);
-            }
-        }
-    }
-
-    /// Send an error message to the client
-    fn send_error(&self, ctx: &mut <Self as Actor>::Context, message: &str) {
-        self.send_server_message(ctx, ServerMessage::Error { 
-            message: message.to_string() 
-        });
-    }
-
-    /// Send a response message to the client
-    fn send_response(&self, ctx: &mut <Self as Actor>::Context, text: &str) {
-        self.send_server_message(ctx, ServerMessage::Response { 
-            text: text.to_string() 
-        });
-    }
-
-    /// Start the heartbeat process
-    fn start_heartbeat(&self, ctx: &mut <Self as Actor>::Context) {
-        ctx.run_interval(Duration::from_secs(30), |act, ctx| {
-            // Send a ping message to the client
-            act.send_server_message(ctx, ServerMessage::Ping);
-        });
-    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(UNIX_SOCKET_MODE))?;
+    Ok(listener)
 }
 
-impl Actor for WebSocketSession {
-    type Context = ws::WebsocketContext<Self>;
-
-    fn started(&mut self, ctx: &mut Self::Context) {
-        info!("WebSocket connection established with {} (id: {})", self.peer_addr, self.id);
-        
-        // Start heartbeat
-        self.start_heartbeat(ctx);
-    }
-
-    fn stopped(&mut self, _ctx: &mut Self::Context) {
-        info!("WebSocket connection closed with {} (id: {})", self.peer_addr, self.id);
-    }
-}
-
-/// Implement the StreamHandler trait to process WebSocket messages
-impl StreamHandler<std::result::Result<ws::Message, ws::ProtocolError>> for WebSocketSession {
-    fn handle(&mut self, msg: std::result::Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
-        match msg {
-            Ok(ws::Message::Ping(msg)) => {
-                info!("Received ping from {}", self.peer_addr);
-                ctx.pong(&msg);
-            }
-            Ok(ws::Message::Text(text)) => {
-                self.handle_websocket_message(text.to_string(), ctx);
-            }
-            Ok(ws::Message::Binary(bin)) => {
-                info!("Received binary message from {} of {} bytes", self.peer_addr, bin.len());
-                // Binary messages are not supported in this implementation
-                self.send_error(ctx, "Binary messages are not supported");
-            }
-            Ok(ws::Message::Close(reason)) => {
-                info!("WebSocket closed from {}: {:?}", self.peer_addr, reason);
-                ctx.close(reason);
-            }
-            Ok(_) => {
-                // Other message types can be handled here if needed
-            }
-            Err(e) => {
-                error!("Error handling WebSocket message from {}: {}", self.peer_addr, e);
-                ctx.stop();
-            }
+/// Bind a TCP listener with `backlog` pending connections. `std::net::TcpListener::bind`
+/// always asks for 128, so the socket is built by hand to honor `server.backlog`.
+fn bind_tcp_listener(addr: &str, backlog: u32) -> std::io::Result<TcpListener> {
+    let mut last_error = None;
+    for addr in addr.to_socket_addrs()? {
+        let socket = match addr {
+            SocketAddr::V4(_) => TcpSocket::new_v4()?,
+            SocketAddr::V6(_) => TcpSocket::new_v6()?,
+        };
+        socket.set_reuseaddr(true)?;
+        match socket.bind(addr).and_then(|()| socket.listen(backlog)) {
+            Ok(listener) => return listener.into_std(),
+            Err(e) => last_error = Some(e),
         }
     }
+    Err(last_error.unwrap_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{} did not resolve to an address", addr))
+    }))
 }
 
 #[actix_web::main]
@@ -214,21 +75,32 @@ async fn main() -> buddybot_server::Result<()> {
     // Load environment variables
     dotenv().ok();
     
-    // Initialize logging
-    FmtSubscriber::builder()
-        .with_max_level(Level::INFO)
-        .with_target(false)
-        .with_thread_ids(true)
-        .with_file(true)
-        .with_line_number(true)
-        .pretty()
-        .init();
-    
-    // Load configuration
+    // Load configuration, reporting every invalid setting before giving up
     let config = Settings::new()?;
+    if let Err(problems) = config.validate() {
+        eprintln!("Invalid configuration ({} problems):", problems.len());
+        for problem in &problems {
+            eprintln!("  - {}", problem);
+        }
+        std::process::exit(1);
+    }
+
+    // Initialize logging, exporting spans when telemetry is configured
+    let tracer_provider = init_tracing(&config.telemetry)?;
     info!("Configuration loaded successfully");
     
-    info!("Starting server at {}:{}", config.server.host, config.server.port);
+    let bind_address = config.server.bind_address()?;
+    info!("Starting server at {:?}", bind_address);
+
+    // Load TLS material up front so a bad certificate fails startup
+    let tls_config = if config.tls.enabled {
+        if matches!(bind_address, BindAddress::Unix(_)) {
+            return Err(AppError::ConfigError("TLS is not supported on unix socket listeners".into()));
+        }
+        Some(load_rustls_config(&config.tls)?)
+    } else {
+        None
+    };
     
     // Initialize application state
     let state = AppState::new(config.clone()).await?;
@@ -237,70 +109,162 @@ async fn main() -> buddybot_server::Result<()> {
     // Start instance management
     let scaling_state = state.clone();
     tokio::spawn(async move {
+        let server = &scaling_state.config.server;
         loop {
+            // Register this instance under its configured identity, which also
+            // keeps it from being reaped as inactive
+            scaling_state.scaling
+                .register_instance_with_id(server.instance_id, server.host.clone(), server.port)
+                .await;
+
+            // Shed WebSocket queries while the cluster is overloaded
+            let metrics = scaling_state.scaling.aggregate_metrics().await;
+            scaling_state.ws_server.backpressure().observe(metrics.as_ref());
+
             // Check scaling needs
-            if let Some(action) = scaling_state.scaling.check_scaling_needs().await {
-                info!("Scaling action required: {:?}", action);
-                // Implement scaling action here
-            }
+            scaling_state.scaling.apply_scaling().await;
 
-            // Cleanup inactive instances
+            // Cleanup inactive and fully drained instances
             scaling_state.scaling.cleanup_inactive_instances().await;
+            scaling_state.scaling.remove_drained_instances().await;
+
+            // Drop expired login lockouts
+            scaling_state.login_lockout.cleanup().await;
+
+            // Remove guest users whose session has run out
+            if let Err(e) = scaling_state.db.delete_expired_guests().await {
+                error!("Failed to delete expired guest users: {}", e);
+            }
+
+            // Drop queued messages for users who never reconnected
+            if let Err(e) = scaling_state.ws_server.cleanup_outbox().await {
+                error!("Failed to purge expired outbox messages: {}", e);
+            }
 
             tokio::time::sleep(Duration::from_secs(60)).await;
         }
     });
     
-    // Create and bind TCP listener
-    let listener = TcpListener::bind(format!("{}:{}", config.server.host, config.server.port))?;
-    
-    info!("WebSocket server initialized and ready to accept connections at ws://{}:{}/ws", config.server.host, config.server.port);
+    // Write batched session activity and per-user query counts
+    let activity_state = state.clone();
+    let flush_interval = Duration::from_secs(config.auth.activity_flush_secs.max(1));
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(flush_interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = activity_state.auth_service.flush_session_activity().await {
+                warn!("Failed to flush session activity: {}", e);
+            }
+            if let Err(e) = activity_state.ws_server.query_stats().flush().await {
+                warn!("Failed to flush query counts: {}", e);
+            }
+        }
+    });
+    let shutdown_state = state.clone();
     
     // Start HTTP server
-    HttpServer::new(move || {
-        let cors = if config.cors.enabled {
-            let cors_config = Cors::default();
-            
-            // Apply specific CORS rules based on configuration
-            let cors_config = if config.cors.allow_any_origin {
-                cors_config
-                    .allow_any_origin()
-                    .allow_any_method()
-                    .allow_any_header()
-                    .expose_any_header()
-                    .supports_credentials()
-            } else {
-                // More restrictive CORS for production use
-                cors_config
-                    .allowed_origin("https://your-production-frontend.com")
-                    .allowed_origin("http://localhost:8080")
-                    .allowed_origin("http://127.0.0.1:8080")
-                    .allowed_methods(vec!["GET", "POST"])
-                    .allowed_headers(vec!["Authorization", "Content-Type"])
-                    .supports_credentials()
-            };
-            
-            // Set max age
-            cors_config.max_age(config.cors.max_age as usize)
-        } else {
-            // CORS disabled - use most restrictive settings
-            Cors::default()
-        };
-
+    let workers = config.server.workers as usize;
+    let blocking_threads = config.server.blocking_threads;
+    let backlog = config.server.backlog;
+    let keep_alive = match config.server.keep_alive_secs {
+        0 => KeepAlive::Disabled,
+        secs => KeepAlive::Timeout(Duration::from_secs(secs)),
+    };
+    let server = HttpServer::new(move || {
+        // Compress skips 101 Switching Protocols responses, so the /ws upgrade is unaffected
         App::new()
-            .wrap(cors)
+            .wrap(from_fn(rate_limit))
+            .wrap(from_fn(limit_accept_rate))
+            .wrap(from_fn(trace_request))
+            .wrap(Condition::new(config.server.compression, Compress::default()))
+            .wrap(build_cors(&config.cors))
             .app_data(state.clone())
+            .app_data(json_config(config.server.max_json_bytes))
             .route("/health", web::get().to(health_check))
+            .route("/ready", web::get().to(readiness))
+            .route("/version", web::get().to(version))
+            .route("/metrics", web::get().to(metrics))
+            .route("/scaling/metrics", web::get().to(scaling_metrics))
+            .route("/scaling/history", web::get().to(scaling_history))
+            .route("/scaling/recommendation", web::get().to(scaling_recommendation))
+            .service(
+                web::resource("/scaling/heartbeat/batch")
+                    .wrap(from_fn(verify_signature))
+                    .route(web::post().to(heartbeat_batch)),
+            )
             .route("/auth/login", web::post().to(login))
             .route("/auth/register", web::post().to(register))
+            .route("/auth/guest", web::post().to(guest))
             .route("/auth/logout", web::post().to(logout))
+            .route("/auth/validate", web::get().to(validate))
+            .route("/auth/change-password", web::post().to(change_password))
+            .route("/admin/audit", web::get().to(list_audit_events))
+            .route("/admin/stats", web::get().to(admin_stats))
+            .route("/admin/maintenance", web::post().to(set_maintenance))
+            .route("/admin/users/{id}/deactivate", web::post().to(deactivate_user))
+            .route("/admin/users/{id}/activate", web::post().to(activate_user))
+            .route("/admin/users/{id}/password", web::post().to(reset_user_password))
+            .route("/admin/users/{id}/stats", web::get().to(user_stats))
+            .route("/admin/notifications", web::post().to(send_notification))
+            .route("/users/me/settings/{key}", web::get().to(get_setting))
+            .route("/users/me/settings/{key}", web::put().to(put_setting))
+            .route("/users/me/export", web::get().to(export_data))
+            .route("/users/me/purge", web::delete().to(purge_account))
+            .route("/proxy/api-key", web::put().to(put_api_key))
+            .route("/proxy/api-key", web::delete().to(delete_api_key))
+            .route("/proxy/query", web::post().to(query))
+            .route("/conversations/{id}/messages", web::get().to(list_messages))
             .route("/ws", web::get().to(websocket_route))  // Add WebSocket route
-    })
-    .listen(listener)?
-    .workers(config.server.workers as usize)
-    .run()
-    .await
-    .map_err(|e| AppError::InternalError(e.to_string()))?;
+            .route("/ws/connections/{id}/disconnect", web::post().to(disconnect_connection))
+    });
+
+    // Marks each connection so the accept-rate limit only counts its first request
+    let server = server.keep_alive(keep_alive).on_connect(mark_connection);
+
+    // Create and bind the listener
+    let server = match &bind_address {
+        BindAddress::Tcp(addr) => {
+            let listener = bind_tcp_listener(addr, backlog)?;
+            match tls_config {
+                Some(tls_config) => {
+                    let server = server.listen_rustls_0_23(listener, tls_config)?;
+                    info!("WebSocket server initialized and ready to accept connections at wss://{}/ws", addr);
+                    server
+                }
+                None => {
+                    let server = server.listen(listener)?;
+                    info!("WebSocket server initialized and ready to accept connections at ws://{}/ws", addr);
+                    server
+                }
+            }
+        }
+        BindAddress::Unix(path) => {
+            let server = server.listen_uds(bind_unix_socket(path)?)?;
+            info!("WebSocket server initialized and ready to accept connections on unix socket {}", path.display());
+            server
+        }
+    };
+
+    let server = server.workers(workers);
+    let server = match blocking_threads {
+        Some(threads) => server.worker_max_blocking_threads(threads),
+        None => server,
+    };
+
+    server
+        .run()
+        .await
+        .map_err(|e| AppError::InternalError(e.to_string()))?;
+
+    // `run` returns once SIGTERM/SIGINT has stopped the workers
+    shutdown_state.shutdown().await?;
+
+    // Flush spans still buffered for export
+    if let Some(provider) = tracer_provider {
+        if let Err(e) = provider.shutdown() {
+            warn!("Failed to flush telemetry: {}", e);
+        }
+    }
 
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,57 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::warn;
+
+use crate::error::Error;
+
+/// Read-only maintenance switch. While it is on, anything that writes (new
+/// accounts, settings changes, proxied queries) is refused with 503 so operators
+/// can migrate or investigate, while health checks and reads keep working.
+#[derive(Debug, Default)]
+pub struct MaintenanceMode {
+    enabled: AtomicBool,
+}
+
+impl MaintenanceMode {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled: AtomicBool::new(enabled) }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Turn maintenance mode on or off, returning the previous setting
+    pub fn set(&self, enabled: bool) -> bool {
+        let previous = self.enabled.swap(enabled, Ordering::Relaxed);
+        if previous != enabled {
+            warn!("Maintenance mode {}", if enabled { "enabled" } else { "disabled" });
+        }
+        previous
+    }
+
+    /// Refuse a write while maintenance mode is on
+    pub fn ensure_writable(&self) -> Result<(), Error> {
+        if self.is_enabled() {
+            Err(Error::Unavailable("Server is in read-only maintenance mode; try again later".into()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggle() {
+        let maintenance = MaintenanceMode::default();
+        assert!(maintenance.ensure_writable().is_ok());
+
+        assert!(!maintenance.set(true));
+        assert!(matches!(maintenance.ensure_writable(), Err(Error::Unavailable(_))));
+
+        assert!(maintenance.set(false));
+        assert!(maintenance.ensure_writable().is_ok());
+    }
+}
@@ -0,0 +1,143 @@
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{Extensions, ServiceRequest, ServiceResponse},
+    http::header::{HeaderValue, RETRY_AFTER},
+    http::{ConnectionType, StatusCode},
+    middleware::Next,
+    web,
+};
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::config::ServerConfig;
+use crate::error::ErrorResponse;
+use crate::AppState;
+
+/// Seconds a shed client is told to wait; the bucket refills within a second
+const RETRY_AFTER_SECS: i64 = 1;
+
+/// Token bucket capping how fast new connections are admitted, so a
+/// connection storm is shed at the door instead of piling onto the database
+/// pool and memory. Holds up to `burst` tokens and refills at `rate` per second.
+pub struct AcceptRateLimiter {
+    rate: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl AcceptRateLimiter {
+    pub fn new(rate: u32, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            rate: f64::from(rate),
+            burst,
+            bucket: Mutex::new(Bucket { tokens: burst, refilled_at: Instant::now() }),
+        }
+    }
+
+    /// Build the limiter from `server.max_connection_rate`; `None` when it is 0.
+    /// The burst defaults to one second's worth of connections.
+    pub fn from_config(config: &ServerConfig) -> Option<Self> {
+        let burst = match config.connection_burst {
+            0 => config.max_connection_rate,
+            burst => burst,
+        };
+        (config.max_connection_rate > 0).then(|| Self::new(config.max_connection_rate, burst))
+    }
+
+    /// Take a token for a new connection, or `false` if it should be shed
+    pub fn try_acquire(&self) -> bool {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.refilled_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Per-connection marker set once the connection has been admitted, so only
+/// its first request spends a token
+#[derive(Clone, Default)]
+pub struct ConnectionAdmission(Arc<AtomicBool>);
+
+/// `HttpServer::on_connect` hook attaching a [`ConnectionAdmission`] to each connection
+pub fn mark_connection(_connection: &dyn Any, data: &mut Extensions) {
+    data.insert(ConnectionAdmission::default());
+}
+
+/// Middleware applying the accept-rate limit: the first request on each new
+/// connection needs a token, and without one gets 503 and the connection is
+/// closed. Without the `on_connect` marker every request counts as new.
+pub async fn limit_accept_rate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let limiter = req.app_data::<web::Data<AppState>>().and_then(|state| state.accept_limiter.clone());
+
+    if let Some(limiter) = limiter {
+        let admission = req.conn_data::<ConnectionAdmission>().cloned();
+        let admitted = admission.as_ref().is_some_and(|a| a.0.load(Ordering::Relaxed));
+
+        if !admitted {
+            if !limiter.try_acquire() {
+                warn!("Shedding new connection: accept rate exceeded");
+                let mut response = ErrorResponse::new(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Server is accepting too many new connections; try again shortly",
+                )
+                .with_code("connection_rate_limited")
+                .with_retry_after(RETRY_AFTER_SECS)
+                .into_response();
+                response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECS));
+                response.head_mut().set_connection_type(ConnectionType::Close);
+                return Ok(req.into_response(response));
+            }
+            if let Some(admission) = admission {
+                admission.0.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    next.call(req).await.map(ServiceResponse::map_into_boxed_body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_burst_then_steady_rate() {
+        let limiter = AcceptRateLimiter::new(10, 5);
+
+        // The burst is admitted, the rest of the storm shed
+        let admitted = (0..20).filter(|_| limiter.try_acquire()).count();
+        assert_eq!(admitted, 5);
+
+        // Arrivals at the configured rate all get through
+        for _ in 0..30 {
+            tokio::time::advance(Duration::from_millis(100)).await;
+            assert!(limiter.try_acquire());
+        }
+
+        // Idle time refills no further than the burst
+        tokio::time::advance(Duration::from_secs(60)).await;
+        assert_eq!((0..20).filter(|_| limiter.try_acquire()).count(), 5);
+    }
+}
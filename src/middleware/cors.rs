@@ -0,0 +1,136 @@
+use actix_cors::Cors;
+
+use crate::config::CorsConfig;
+
+/// Build the CORS middleware described by `config`.
+///
+/// With CORS disabled no cross-origin request is allowed. `allow_any_origin`
+/// accepts every origin, method and header; otherwise only the listed ones are.
+/// Invalid entries surface as an error when the server starts.
+pub fn build_cors(config: &CorsConfig) -> Cors {
+    if !config.enabled {
+        return Cors::default();
+    }
+
+    let cors = if config.allow_any_origin {
+        Cors::default()
+            .allow_any_origin()
+            .allow_any_method()
+            .allow_any_header()
+            .expose_any_header()
+    } else {
+        config.allowed_origins.iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+            .allowed_methods(config.allowed_methods.iter().map(String::as_str))
+            .allowed_headers(config.allowed_headers.iter().map(String::as_str))
+    };
+
+    let cors = if config.supports_credentials {
+        cors.supports_credentials()
+    } else {
+        cors
+    };
+
+    cors.max_age(config.max_age as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::dev::ServiceResponse;
+    use actix_web::http::{header, Method, StatusCode};
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App, HttpResponse};
+
+    const ORIGIN: &str = "https://app.example";
+
+    /// Send a preflight from `origin` for `method` through a minimal app
+    async fn preflight(config: &CorsConfig, origin: &str, method: &str) -> ServiceResponse {
+        let app = init_service(
+            App::new()
+                .wrap(build_cors(config))
+                .route("/", web::to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let req = TestRequest::default()
+            .method(Method::OPTIONS)
+            .insert_header((header::ORIGIN, origin))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, method))
+            .to_request();
+        call_service(&app, req).await.map_into_boxed_body()
+    }
+
+    fn header_value(res: &ServiceResponse, name: header::HeaderName) -> Option<&str> {
+        res.headers().get(name).and_then(|v| v.to_str().ok())
+    }
+
+    fn listed(origins: &[&str]) -> CorsConfig {
+        CorsConfig {
+            allowed_origins: origins.iter().map(|o| o.to_string()).collect(),
+            ..CorsConfig::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_disabled_rejects_cross_origin() {
+        let config = CorsConfig { enabled: false, ..listed(&[ORIGIN]) };
+        let res = preflight(&config, ORIGIN, "GET").await;
+        assert!(header_value(&res, header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_explicit_origins() {
+        let config = listed(&[ORIGIN]);
+
+        let res = preflight(&config, ORIGIN, "GET").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(header_value(&res, header::ACCESS_CONTROL_ALLOW_ORIGIN), Some(ORIGIN));
+
+        let res = preflight(&config, "https://other.example", "GET").await;
+        assert!(header_value(&res, header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_allow_any_origin() {
+        let config = CorsConfig { allow_any_origin: true, ..listed(&[]) };
+        let res = preflight(&config, "https://anywhere.example", "DELETE").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            header_value(&res, header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some("https://anywhere.example")
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_methods_and_headers() {
+        let config = CorsConfig {
+            allowed_methods: vec!["PUT".to_string()],
+            allowed_headers: vec!["X-Custom".to_string()],
+            ..listed(&[ORIGIN])
+        };
+
+        let res = preflight(&config, ORIGIN, "PUT").await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(header_value(&res, header::ACCESS_CONTROL_ALLOW_METHODS), Some("PUT"));
+        assert_eq!(
+            header_value(&res, header::ACCESS_CONTROL_ALLOW_HEADERS).map(str::to_lowercase),
+            Some("x-custom".to_string())
+        );
+
+        let res = preflight(&config, ORIGIN, "POST").await;
+        assert!(!res.status().is_success());
+    }
+
+    #[actix_web::test]
+    async fn test_credentials_and_max_age() {
+        let res = preflight(&listed(&[ORIGIN]), ORIGIN, "GET").await;
+        assert_eq!(header_value(&res, header::ACCESS_CONTROL_ALLOW_CREDENTIALS), Some("true"));
+        assert_eq!(header_value(&res, header::ACCESS_CONTROL_MAX_AGE), Some("3600"));
+
+        let config = CorsConfig { supports_credentials: false, max_age: 60, ..listed(&[ORIGIN]) };
+        let res = preflight(&config, ORIGIN, "GET").await;
+        assert!(header_value(&res, header::ACCESS_CONTROL_ALLOW_CREDENTIALS).is_none());
+        assert_eq!(header_value(&res, header::ACCESS_CONTROL_MAX_AGE), Some("60"));
+    }
+}
@@ -0,0 +1,67 @@
+use actix_web::dev::Payload;
+use actix_web::error::JsonPayloadError;
+use actix_web::{web, FromRequest, HttpRequest};
+use futures::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use std::ops::Deref;
+use validator::Validate;
+
+use crate::error::{Error, FieldError};
+
+/// JSON extractor settings for every route: bodies over `max_bytes` are refused
+/// with 413 before they are buffered in full, and any other payload error
+/// becomes a 400 in the crate's error shape rather than actix's plain text.
+pub fn json_config(max_bytes: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(max_bytes)
+        .error_handler(move |err, _req| match err {
+            JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+                Error::PayloadTooLarge(max_bytes).into()
+            }
+            err => Error::Validation(vec![FieldError::new("body", &payload_error_message(&err))]).into(),
+        })
+}
+
+fn payload_error_message(err: &JsonPayloadError) -> String {
+    match err {
+        JsonPayloadError::ContentType => "Content-Type must be application/json".to_string(),
+        // Wrong or missing fields; serde's message names them
+        JsonPayloadError::Deserialize(e) if e.is_data() => format!("Invalid request body: {}", e),
+        JsonPayloadError::Deserialize(_) => "Request body is not valid JSON".to_string(),
+        _ => "Request body could not be read".to_string(),
+    }
+}
+
+/// `web::Json<T>` that also runs `T`'s `#[validate]` rules, so a handler only
+/// ever sees a body that passed them. Failures become a 400 listing each
+/// offending field, the same shape as hand-written validation errors.
+#[derive(Debug)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T> ValidatedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> Deref for ValidatedJson<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + Validate + 'static> FromRequest for ValidatedJson<T> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let json = web::Json::<T>::from_request(req, payload);
+        Box::pin(async move {
+            let value = json.await?.into_inner();
+            value.validate().map_err(Error::from)?;
+            Ok(Self(value))
+        })
+    }
+}
@@ -0,0 +1,14 @@
+//! HTTP middleware for BuddyBot server
+//!
+//! Builders here derive their behavior purely from configuration so it can be
+//! tested without starting the server.
+
+pub mod accept_rate;
+pub mod cors;
+pub mod json;
+pub mod signature;
+
+pub use accept_rate::{limit_accept_rate, mark_connection, AcceptRateLimiter};
+pub use cors::build_cors;
+pub use json::{json_config, ValidatedJson};
+pub use signature::{verify_signature, SIGNATURE_HEADER};
@@ -6,11 +6,16 @@ use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
-use crate::error::Error;
+use crate::config::ProxyConfig;
+use crate::error::{AppError, Error};
 
 const NONCE_SIZE: usize = 12;
 const KEY_SIZE: usize = 32;
 
+/// Key shipped in config/default.toml so development works out of the box.
+/// Refused in any other environment.
+pub const DEVELOPMENT_ENCRYPTION_KEY: &str = "ZGV2LW9ubHkta2V5LW5ldmVyLXVzZS1pbi1wcm9kISE=";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EncryptedApiKey {
     pub encrypted_data: String,
@@ -42,6 +47,26 @@ impl ApiKeyManager {
         Ok(Self { encryption_key })
     }
 
+    /// Build the manager from `proxy.encryption_key`, failing startup on a missing
+    /// or malformed key, or on the development key outside development.
+    pub fn from_config(config: &ProxyConfig, environment: &str) -> Result<Self, AppError> {
+        let key = config.encryption_key.as_deref()
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .ok_or_else(|| AppError::ConfigError("proxy.encryption_key is not set".into()))?;
+
+        let development = matches!(environment, "development" | "test");
+        if !development && key == DEVELOPMENT_ENCRYPTION_KEY {
+            return Err(AppError::ConfigError(format!(
+                "proxy.encryption_key is the development key, which is not allowed in {}",
+                environment
+            )));
+        }
+
+        Self::from_base64_key(key)
+            .map_err(|e| AppError::ConfigError(format!("proxy.encryption_key: {}", e)))
+    }
+
     pub fn encrypt_api_key(&self, api_key: &str, ttl_seconds: Option<u64>) -> Result<EncryptedApiKey, Error> {
         let cipher = Aes256Gcm::new_from_slice(&self.encryption_key)
             .map_err(|e| Error::External(format!("Encryption error: {}", e)))?;
@@ -123,8 +148,8 @@ mod tests {
         let api_key = "test-api-key-123";
         let encrypted = manager.encrypt_api_key(api_key, None).unwrap();
         
-        assert!(encrypted.encrypted_data.len() > 0);
-        assert!(encrypted.nonce.len() > 0);
+        assert!(!encrypted.encrypted_data.is_empty());
+        assert!(!encrypted.nonce.is_empty());
         assert!(encrypted.created_at > 0);
         assert_eq!(encrypted.expires_at, None);
         
@@ -173,4 +198,45 @@ mod tests {
         let decrypted = old_manager.decrypt_api_key(&encrypted).unwrap();
         assert_eq!(decrypted, api_key);
     }
-} 
\ No newline at end of file
+
+    fn proxy_config(encryption_key: Option<&str>) -> ProxyConfig {
+        ProxyConfig {
+            encryption_key: encryption_key.map(str::to_string),
+            ..ProxyConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_key_from_config() {
+        let key = BASE64.encode(generate_test_key());
+        assert!(ApiKeyManager::from_config(&proxy_config(Some(&key)), "production").is_ok());
+    }
+
+    #[test]
+    fn test_key_wrong_length() {
+        let key = BASE64.encode([7u8; 16]);
+        let result = ApiKeyManager::from_config(&proxy_config(Some(&key)), "production");
+        assert!(matches!(result, Err(AppError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_key_invalid_base64() {
+        let result = ApiKeyManager::from_config(&proxy_config(Some("not base64!")), "production");
+        assert!(matches!(result, Err(AppError::ConfigError(_))));
+    }
+
+    #[test]
+    fn test_key_missing_or_default() {
+        for key in [None, Some(""), Some("   ")] {
+            let result = ApiKeyManager::from_config(&proxy_config(key), "development");
+            assert!(matches!(result, Err(AppError::ConfigError(_))));
+        }
+
+        let dev_key = proxy_config(Some(DEVELOPMENT_ENCRYPTION_KEY));
+        assert!(ApiKeyManager::from_config(&dev_key, "development").is_ok());
+        assert!(matches!(
+            ApiKeyManager::from_config(&dev_key, "production"),
+            Err(AppError::ConfigError(_))
+        ));
+    }
+}
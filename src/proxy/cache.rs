@@ -0,0 +1,108 @@
+use hashlink::LruCache;
+use sha2::{Digest, Sha256};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::config::ProxyCacheConfig;
+
+type CacheKey = [u8; 32];
+
+struct CachedCompletion {
+    text: String,
+    expires_at: Instant,
+}
+
+/// In-memory LRU of completions for standalone prompts. Only prompts sent
+/// without conversation context are cacheable, since context changes the answer.
+pub struct ResponseCache {
+    entries: Mutex<LruCache<CacheKey, CachedCompletion>>,
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    pub fn new(max_entries: usize, ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(max_entries.max(1))),
+            ttl,
+        }
+    }
+
+    /// Build the cache described by `proxy.cache`, or `None` when it is off
+    pub fn from_config(config: &ProxyCacheConfig) -> Option<Self> {
+        config.enabled
+            .then(|| Self::new(config.max_entries, Duration::from_secs(config.ttl_secs)))
+    }
+
+    /// Key for `prompt` as answered by `model`
+    pub fn key(model: &str, prompt: &str) -> CacheKey {
+        let mut hasher = Sha256::new();
+        hasher.update(model.as_bytes());
+        hasher.update([0]);
+        hasher.update(prompt.as_bytes());
+        hasher.finalize().into()
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some(entry) if entry.expires_at > Instant::now() => Some(entry.text.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, key: CacheKey, text: String) {
+        let entry = CachedCompletion { text, expires_at: Instant::now() + self.ttl };
+        self.entries.lock().unwrap().insert(key, entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hit_and_miss() {
+        let cache = ResponseCache::new(2, Duration::from_secs(60));
+        let key = ResponseCache::key("echo", "hello");
+
+        assert_eq!(cache.get(&key), None);
+        cache.insert(key, "hi".to_string());
+        assert_eq!(cache.get(&key).as_deref(), Some("hi"));
+
+        // Same prompt for a different model is a different entry
+        assert_eq!(cache.get(&ResponseCache::key("other", "hello")), None);
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_evicted() {
+        let cache = ResponseCache::new(2, Duration::from_secs(60));
+        let (a, b, c) = (ResponseCache::key("m", "a"), ResponseCache::key("m", "b"), ResponseCache::key("m", "c"));
+
+        cache.insert(a, "a".to_string());
+        cache.insert(b, "b".to_string());
+        cache.get(&a);
+        cache.insert(c, "c".to_string());
+
+        assert!(cache.get(&a).is_some());
+        assert!(cache.get(&b).is_none());
+        assert!(cache.get(&c).is_some());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ttl_expiry() {
+        let cache = ResponseCache::new(10, Duration::from_secs(30));
+        let key = ResponseCache::key("echo", "hello");
+        cache.insert(key, "hi".to_string());
+
+        tokio::time::advance(Duration::from_secs(29)).await;
+        assert!(cache.get(&key).is_some());
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert!(cache.get(&key).is_none());
+    }
+}
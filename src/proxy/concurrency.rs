@@ -0,0 +1,87 @@
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::config::ProxyConfig;
+use crate::error::{AppError, ProxyError};
+
+/// Caps how many upstream LLM requests run at once. Requests over the cap
+/// wait for a free slot up to the queue timeout, or with the "busy" overflow
+/// policy are turned away at once; either way they end in `ProxyError::Busy`.
+pub struct ConcurrencyLimit {
+    semaphore: Semaphore,
+    /// How long to wait for a slot; `None` refuses immediately
+    queue_timeout: Option<Duration>,
+    retry_after_ms: u64,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(max_concurrency: usize, queue_timeout: Option<Duration>, retry_after_ms: u64) -> Self {
+        Self { semaphore: Semaphore::new(max_concurrency), queue_timeout, retry_after_ms }
+    }
+
+    /// Build the limit from `proxy.max_concurrency`; `None` when it is 0
+    pub fn from_config(config: &ProxyConfig) -> Result<Option<Self>, AppError> {
+        let queue_timeout = match config.concurrency_overflow.as_str() {
+            "queue" => Some(Duration::from_millis(config.queue_timeout_ms)),
+            "busy" => None,
+            other => {
+                return Err(AppError::ConfigError(format!("Unknown proxy concurrency overflow policy '{}'", other)))
+            }
+        };
+
+        Ok((config.max_concurrency > 0)
+            .then(|| Self::new(config.max_concurrency, queue_timeout, config.busy_retry_after_ms)))
+    }
+
+    /// Take a slot, held until the permit drops
+    pub async fn acquire(&self) -> Result<SemaphorePermit<'_>, ProxyError> {
+        let busy = ProxyError::Busy { retry_after_ms: self.retry_after_ms };
+        match self.queue_timeout {
+            None => self.semaphore.try_acquire().map_err(|_| busy),
+            Some(timeout) => match tokio::time::timeout(timeout, self.semaphore.acquire()).await {
+                Ok(Ok(permit)) => Ok(permit),
+                _ => Err(busy),
+            },
+        }
+    }
+
+    /// Slots not currently taken
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_queue_times_out() {
+        let limit = ConcurrencyLimit::new(1, Some(Duration::from_secs(2)), 500);
+        let held = limit.acquire().await.unwrap();
+        assert_eq!(limit.available(), 0);
+
+        assert!(matches!(limit.acquire().await, Err(ProxyError::Busy { retry_after_ms: 500 })));
+
+        drop(held);
+        assert!(limit.acquire().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_busy_policy_refuses_at_once() {
+        let limit = ConcurrencyLimit::new(1, None, 250);
+        let _held = limit.acquire().await.unwrap();
+        assert!(matches!(limit.acquire().await, Err(ProxyError::Busy { retry_after_ms: 250 })));
+    }
+
+    #[test]
+    fn test_from_config() {
+        assert!(ConcurrencyLimit::from_config(&ProxyConfig::default()).unwrap().is_none());
+
+        let config = ProxyConfig { max_concurrency: 4, ..ProxyConfig::default() };
+        assert_eq!(ConcurrencyLimit::from_config(&config).unwrap().unwrap().available(), 4);
+
+        let config = ProxyConfig { concurrency_overflow: "drop".to_string(), ..ProxyConfig::default() };
+        assert!(ConcurrencyLimit::from_config(&config).is_err());
+    }
+}
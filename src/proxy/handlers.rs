@@ -0,0 +1,114 @@
+use actix_web::web::Bytes;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures::stream;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::auth::middleware::require_user;
+use crate::error::{Error, FieldError, ProxyError};
+use crate::proxy::{ChatTurn, QueryOptions};
+use crate::websocket::{process_query, response_chunks};
+use crate::AppState;
+
+/// Longest provider API key accepted
+pub const MAX_API_KEY_CHARS: usize = 512;
+
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyRequest {
+    pub api_key: String,
+    /// Seconds until the stored key stops being used; never expires when absent
+    pub ttl_seconds: Option<u64>,
+}
+
+/// Store the caller's provider API key, replacing any previous one. The key is
+/// encrypted at rest and never returned.
+pub async fn put_api_key(
+    req: HttpRequest,
+    body: web::Json<ApiKeyRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    state.maintenance.ensure_writable()?;
+    let user = require_user(&req, &state).await?;
+
+    let api_key = body.api_key.trim();
+    if api_key.is_empty() || api_key.chars().count() > MAX_API_KEY_CHARS {
+        return Err(Error::Validation(vec![FieldError::new(
+            "api_key",
+            &format!("API keys must be 1-{} characters", MAX_API_KEY_CHARS),
+        )]));
+    }
+
+    let encrypted = state.api_keys.encrypt_api_key(api_key, body.ttl_seconds)?;
+    state.db.set_user_api_key(user.id, &encrypted).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "API key stored",
+        "created_at": encrypted.created_at,
+        "expires_at": encrypted.expires_at
+    })))
+}
+
+/// Remove the caller's provider API key; later queries use the server default
+pub async fn delete_api_key(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    state.maintenance.ensure_writable()?;
+    let user = require_user(&req, &state).await?;
+
+    if !state.db.delete_user_api_key(user.id).await? {
+        return Err(Error::NotFound("No API key stored".into()));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "API key removed" })))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct QueryRequest {
+    pub text: String,
+    #[serde(default)]
+    pub conversation_id: Option<Uuid>,
+    #[serde(default)]
+    pub history: Option<Vec<ChatTurn>>,
+    /// Unset or `true` streams the answer as a chunked `text/plain` body;
+    /// `false` returns it whole as `{"text": ...}`
+    #[serde(default)]
+    pub stream: Option<bool>,
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Answer a query over HTTP, with the same conversation handling and delivery
+/// modes as a WebSocket `query`
+pub async fn query(
+    req: HttpRequest,
+    body: web::Json<QueryRequest>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    state.maintenance.ensure_writable()?;
+    let user = require_user(&req, &state).await?;
+
+    let _permit = state.ws_server.backpressure()
+        .try_admit()
+        .map_err(|retry_after_ms| Error::Proxy(ProxyError::Busy { retry_after_ms }))?;
+
+    let QueryRequest { text, conversation_id, history, stream, model } = body.into_inner();
+    let stream = stream.unwrap_or(true);
+    let options = QueryOptions { model: model.as_deref(), tier: Some(&user.rate_limit_tier), stream, ..QueryOptions::default() };
+    let history = history.unwrap_or_default();
+    let proxy = state.ws_server.proxy();
+    let response = process_query(&proxy, &state.db, user.id, &text, conversation_id, &history, options).await?;
+    state.ws_server.query_stats().record_query(user.id).await;
+
+    if !stream {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({ "text": response })));
+    }
+
+    let chunks: Vec<Result<Bytes, Error>> = response_chunks(&response)
+        .into_iter()
+        .map(|chunk| Ok(Bytes::copy_from_slice(chunk.as_bytes())))
+        .collect();
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .streaming(stream::iter(chunks)))
+}
@@ -0,0 +1,113 @@
+use crate::error::ProxyError;
+use crate::proxy::ChatTurn;
+
+/// Reject a conversation whose combined message content exceeds `max_bytes`
+pub fn check_prompt_size(messages: &[ChatTurn], max_bytes: usize) -> Result<(), ProxyError> {
+    let size: usize = messages.iter().map(|m| m.content.len()).sum();
+    if size > max_bytes {
+        return Err(ProxyError::ResponseError(format!(
+            "prompt is {} bytes, limit is {}",
+            size, max_bytes
+        )));
+    }
+    Ok(())
+}
+
+/// Reject a completion of `len` bytes when that is over `max_bytes`
+pub fn check_response_size(len: u64, max_bytes: usize) -> Result<(), ProxyError> {
+    if len > max_bytes as u64 {
+        return Err(ProxyError::ResponseError(format!("response exceeds {} bytes", max_bytes)));
+    }
+    Ok(())
+}
+
+/// Read an upstream response body chunk by chunk, giving up as soon as it
+/// grows past `max_bytes` rather than buffering the whole thing first
+pub async fn read_body_limited(
+    mut response: reqwest::Response,
+    max_bytes: usize,
+) -> Result<Vec<u8>, ProxyError> {
+    if let Some(len) = response.content_length() {
+        check_response_size(len, max_bytes)?;
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| ProxyError::RequestFailed(e.to_string()))?
+    {
+        check_response_size((body.len() + chunk.len()) as u64, max_bytes)?;
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_prompt_over_limit() {
+        let messages = vec![ChatTurn::user("a".repeat(6)), ChatTurn::assistant("b".repeat(6))];
+        assert!(check_prompt_size(&messages, 12).is_ok());
+        assert!(matches!(check_prompt_size(&messages, 11), Err(ProxyError::ResponseError(_))));
+    }
+
+    /// Serve one chunked response of `chunks` x 1 KiB, with no Content-Length
+    async fn chunked_server(chunks: usize) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = socket.read(&mut request).await;
+
+            let _ = socket
+                .write_all(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n")
+                .await;
+            for _ in 0..chunks {
+                let chunk = format!("400\r\n{}\r\n", "x".repeat(1024));
+                if socket.write_all(chunk.as_bytes()).await.is_err() {
+                    return;
+                }
+            }
+            let _ = socket.write_all(b"0\r\n\r\n").await;
+        });
+
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_streamed_response_over_limit() {
+        let response = reqwest::get(chunked_server(4).await).await.unwrap();
+        assert_eq!(read_body_limited(response, 4 * 1024).await.unwrap().len(), 4 * 1024);
+
+        let response = reqwest::get(chunked_server(64).await).await.unwrap();
+        assert!(response.content_length().is_none());
+        assert!(matches!(
+            read_body_limited(response, 4 * 1024).await,
+            Err(ProxyError::ResponseError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_declared_length_over_limit() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![b'x'; 8 * 1024]))
+            .mount(&server)
+            .await;
+
+        let response = reqwest::get(server.uri()).await.unwrap();
+        assert!(matches!(
+            read_body_limited(response, 1024).await,
+            Err(ProxyError::ResponseError(_))
+        ));
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::error::ProxyError;
+
+/// Upper bounds of the upstream latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 10] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+#[derive(Default)]
+struct ProviderStats {
+    /// Calls by outcome: "ok" or an error class
+    requests: BTreeMap<&'static str, u64>,
+    /// Calls per latency bucket, not cumulative; slower calls only count in `latency_count`
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: f64,
+    latency_count: u64,
+}
+
+/// Per-provider upstream call counts and latencies, rendered in the Prometheus
+/// text format on `/metrics`
+#[derive(Default)]
+pub struct ProxyMetrics {
+    providers: Mutex<BTreeMap<String, ProviderStats>>,
+}
+
+/// Error class used as the status label: "4xx", "5xx", "timeout" or "other"
+pub fn error_class(error: &ProxyError) -> &'static str {
+    match error {
+        ProxyError::UpstreamStatus(status) if *status >= 500 => "5xx",
+        ProxyError::UpstreamStatus(_) | ProxyError::InvalidApiKey | ProxyError::RateLimited => "4xx",
+        ProxyError::Timeout => "timeout",
+        _ => "other",
+    }
+}
+
+impl ProxyMetrics {
+    /// Count one call to `provider` that finished with `result` after `latency`
+    pub fn record<T>(&self, provider: &str, result: &Result<T, ProxyError>, latency: Duration) {
+        let status = result.as_ref().map_or_else(error_class, |_| "ok");
+        let seconds = latency.as_secs_f64();
+
+        let mut providers = self.providers.lock().unwrap();
+        let stats = providers.entry(provider.to_string()).or_default();
+        *stats.requests.entry(status).or_default() += 1;
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            stats.buckets[bucket] += 1;
+        }
+        stats.latency_sum += seconds;
+        stats.latency_count += 1;
+    }
+
+    /// Calls to `provider` that finished with `status`
+    pub fn requests(&self, provider: &str, status: &str) -> u64 {
+        let providers = self.providers.lock().unwrap();
+        providers.get(provider).and_then(|s| s.requests.get(status)).copied().unwrap_or(0)
+    }
+
+    /// Failed calls to `provider`, of any class
+    pub fn errors(&self, provider: &str) -> u64 {
+        let providers = self.providers.lock().unwrap();
+        providers.get(provider)
+            .map(|s| s.requests.iter().filter(|(status, _)| **status != "ok").map(|(_, n)| n).sum())
+            .unwrap_or(0)
+    }
+
+    /// All series in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let providers = self.providers.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP buddybot_proxy_requests_total Upstream LLM calls by provider and outcome\n");
+        out.push_str("# TYPE buddybot_proxy_requests_total counter\n");
+        for (provider, stats) in providers.iter() {
+            for (status, count) in &stats.requests {
+                let _ = writeln!(out, "buddybot_proxy_requests_total{{provider=\"{}\",status=\"{}\"}} {}", provider, status, count);
+            }
+        }
+
+        out.push_str("# HELP buddybot_proxy_errors_total Failed upstream LLM calls by provider and error class\n");
+        out.push_str("# TYPE buddybot_proxy_errors_total counter\n");
+        for (provider, stats) in providers.iter() {
+            for (class, count) in stats.requests.iter().filter(|(status, _)| **status != "ok") {
+                let _ = writeln!(out, "buddybot_proxy_errors_total{{provider=\"{}\",class=\"{}\"}} {}", provider, class, count);
+            }
+        }
+
+        out.push_str("# HELP buddybot_proxy_request_duration_seconds Upstream LLM call latency\n");
+        out.push_str("# TYPE buddybot_proxy_request_duration_seconds histogram\n");
+        for (provider, stats) in providers.iter() {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(stats.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "buddybot_proxy_request_duration_seconds_bucket{{provider=\"{}\",le=\"{}\"}} {}",
+                    provider, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "buddybot_proxy_request_duration_seconds_bucket{{provider=\"{}\",le=\"+Inf\"}} {}",
+                provider, stats.latency_count
+            );
+            let _ = writeln!(out, "buddybot_proxy_request_duration_seconds_sum{{provider=\"{}\"}} {}", provider, stats.latency_sum);
+            let _ = writeln!(out, "buddybot_proxy_request_duration_seconds_count{{provider=\"{}\"}} {}", provider, stats.latency_count);
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_counts_and_histogram() {
+        let metrics = ProxyMetrics::default();
+        metrics.record("echo", &Ok::<_, ProxyError>(()), Duration::from_millis(30));
+        metrics.record("echo", &Ok::<_, ProxyError>(()), Duration::from_millis(700));
+        metrics.record::<()>("echo", &Err(ProxyError::UpstreamStatus(503)), Duration::from_secs(120));
+        metrics.record::<()>("echo", &Err(ProxyError::Timeout), Duration::from_secs(30));
+
+        assert_eq!(metrics.requests("echo", "ok"), 2);
+        assert_eq!(metrics.requests("echo", "5xx"), 1);
+        assert_eq!(metrics.errors("echo"), 2);
+        assert_eq!(metrics.errors("other"), 0);
+
+        let text = metrics.render();
+        for line in [
+            "buddybot_proxy_requests_total{provider=\"echo\",status=\"ok\"} 2",
+            "buddybot_proxy_requests_total{provider=\"echo\",status=\"5xx\"} 1",
+            "buddybot_proxy_errors_total{provider=\"echo\",class=\"timeout\"} 1",
+            "buddybot_proxy_request_duration_seconds_bucket{provider=\"echo\",le=\"0.05\"} 1",
+            "buddybot_proxy_request_duration_seconds_bucket{provider=\"echo\",le=\"1\"} 2",
+            "buddybot_proxy_request_duration_seconds_bucket{provider=\"echo\",le=\"60\"} 3",
+            "buddybot_proxy_request_duration_seconds_bucket{provider=\"echo\",le=\"+Inf\"} 4",
+            "buddybot_proxy_request_duration_seconds_count{provider=\"echo\"} 4",
+        ] {
+            assert!(text.lines().any(|l| l == line), "Missing {:?} in:\n{}", line, text);
+        }
+        assert!(!text.contains("errors_total{provider=\"echo\",class=\"ok\"}"));
+    }
+
+    #[test]
+    fn test_error_classes() {
+        assert_eq!(error_class(&ProxyError::UpstreamStatus(500)), "5xx");
+        assert_eq!(error_class(&ProxyError::UpstreamStatus(404)), "4xx");
+        assert_eq!(error_class(&ProxyError::RateLimited), "4xx");
+        assert_eq!(error_class(&ProxyError::Timeout), "timeout");
+        assert_eq!(error_class(&ProxyError::RequestFailed("reset".into())), "other");
+    }
+}
@@ -3,5 +3,21 @@
 //! This module handles proxying requests to the Claude API
 //! and manages rate limiting and request transformation.
 
-// Re-export public interfaces
-// Will be implemented in Phase 2
+mod api_key;
+mod cache;
+mod concurrency;
+pub mod handlers;
+mod limits;
+mod metrics;
+mod moderation;
+mod provider;
+mod service;
+
+pub use api_key::{ApiKeyManager, EncryptedApiKey, DEVELOPMENT_ENCRYPTION_KEY};
+pub use cache::ResponseCache;
+pub use concurrency::ConcurrencyLimit;
+pub use limits::read_body_limited;
+pub use metrics::{error_class, ProxyMetrics};
+pub use moderation::{content_filter_from_config, ContentFilter, ModerationEndpointFilter, NoopFilter};
+pub use provider::{provider_by_name, ChatRole, ChatTurn, HttpLlmProvider, LlmProvider, EchoProvider};
+pub use service::{ProxyService, QueryOptions};
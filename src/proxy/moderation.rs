@@ -0,0 +1,135 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::ProxyConfig;
+use crate::error::{AppError, ProxyError};
+
+/// Screens text on its way to and from the LLM
+#[async_trait]
+pub trait ContentFilter: Send + Sync {
+    /// Return `ProxyError::ContentRejected` if `text` must not pass
+    async fn check(&self, text: &str) -> Result<(), ProxyError>;
+}
+
+/// Filter that lets everything through
+#[derive(Debug, Default)]
+pub struct NoopFilter;
+
+#[async_trait]
+impl ContentFilter for NoopFilter {
+    async fn check(&self, _text: &str) -> Result<(), ProxyError> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ModerationVerdict {
+    flagged: bool,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+/// Filter that asks an external moderation service. The endpoint receives
+/// `{"input": text}` and answers `{"flagged": bool, "reason": string?}`.
+pub struct ModerationEndpointFilter {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl ModerationEndpointFilter {
+    pub fn new(url: impl Into<String>, timeout: Duration) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .unwrap_or_default();
+
+        Self { client, url: url.into() }
+    }
+}
+
+#[async_trait]
+impl ContentFilter for ModerationEndpointFilter {
+    async fn check(&self, text: &str) -> Result<(), ProxyError> {
+        let response = self.client
+            .post(&self.url)
+            .json(&serde_json::json!({ "input": text }))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| ProxyError::RequestFailed(format!("moderation request failed: {}", e)))?;
+
+        let verdict: ModerationVerdict = response
+            .json()
+            .await
+            .map_err(|e| ProxyError::ResponseError(format!("invalid moderation response: {}", e)))?;
+
+        if verdict.flagged {
+            return Err(ProxyError::ContentRejected(
+                verdict.reason.unwrap_or_else(|| "flagged by moderation".to_string()),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Build the filter named by `proxy.content_filter`
+pub fn content_filter_from_config(config: &ProxyConfig) -> Result<Arc<dyn ContentFilter>, AppError> {
+    match config.content_filter.as_str() {
+        "none" => Ok(Arc::new(NoopFilter)),
+        "moderation" => {
+            let url = config.moderation_url.as_deref()
+                .filter(|u| !u.is_empty())
+                .ok_or_else(|| AppError::ConfigError(
+                    "proxy.moderation_url is required when proxy.content_filter is \"moderation\"".into(),
+                ))?;
+            Ok(Arc::new(ModerationEndpointFilter::new(
+                url,
+                Duration::from_millis(config.request_timeout_ms),
+            )))
+        }
+        other => Err(AppError::ConfigError(format!("Unknown proxy content filter '{}'", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_json, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_moderation_endpoint() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_json(serde_json::json!({ "input": "bad words" })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(
+                serde_json::json!({ "flagged": true, "reason": "profanity" }),
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "flagged": false })))
+            .mount(&server)
+            .await;
+
+        let filter = ModerationEndpointFilter::new(server.uri(), Duration::from_secs(1));
+        assert!(filter.check("kind words").await.is_ok());
+        match filter.check("bad words").await {
+            Err(ProxyError::ContentRejected(reason)) => assert_eq!(reason, "profanity"),
+            other => panic!("expected rejection, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_filter_from_config() {
+        assert!(content_filter_from_config(&ProxyConfig::default()).is_ok());
+
+        let missing_url = ProxyConfig { content_filter: "moderation".to_string(), ..ProxyConfig::default() };
+        assert!(matches!(content_filter_from_config(&missing_url), Err(AppError::ConfigError(_))));
+
+        let unknown = ProxyConfig { content_filter: "regex".to_string(), ..ProxyConfig::default() };
+        assert!(matches!(content_filter_from_config(&unknown), Err(AppError::ConfigError(_))));
+    }
+}
@@ -0,0 +1,192 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use crate::error::ProxyError;
+use crate::proxy::limits::{check_response_size, read_body_limited};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    User,
+    Assistant,
+}
+
+impl ChatRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatRole::User => "user",
+            ChatRole::Assistant => "assistant",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "user" => Some(ChatRole::User),
+            "assistant" => Some(ChatRole::Assistant),
+            _ => None,
+        }
+    }
+}
+
+/// One message in a conversation sent to the LLM
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatTurn {
+    pub role: ChatRole,
+    pub content: String,
+}
+
+impl ChatTurn {
+    pub fn user(content: impl Into<String>) -> Self {
+        Self { role: ChatRole::User, content: content.into() }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self { role: ChatRole::Assistant, content: content.into() }
+    }
+}
+
+/// An upstream LLM that can turn a conversation into a completion
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Short identifier used in logs and metrics
+    fn name(&self) -> &str;
+
+    /// Complete the conversation; the last message is the pending user turn
+    async fn complete(&self, messages: &[ChatTurn]) -> Result<String, ProxyError>;
+
+    /// Complete the conversation authenticated with `api_key`, the caller's own
+    /// key or the server default. Providers that need no key ignore it.
+    async fn complete_with_key(&self, messages: &[ChatTurn], api_key: Option<&str>) -> Result<String, ProxyError> {
+        let _ = api_key;
+        self.complete(messages).await
+    }
+
+    /// Complete the conversation on `model`, or the provider's own default
+    /// when `None`. Providers that serve a single model ignore it.
+    async fn complete_with_model(
+        &self,
+        messages: &[ChatTurn],
+        api_key: Option<&str>,
+        model: Option<&str>,
+    ) -> Result<String, ProxyError> {
+        let _ = model;
+        self.complete_with_key(messages, api_key).await
+    }
+
+    /// Like [`complete_with_model`](Self::complete_with_model), failing when
+    /// the completion is over `max_bytes`. By default this is not limited
+    /// while reading: the completion is produced in full and checked after.
+    /// Providers reached over HTTP implement [`HttpLlmProvider`] instead, which
+    /// stops reading the body once it is over the limit.
+    async fn complete_limited(
+        &self,
+        messages: &[ChatTurn],
+        api_key: Option<&str>,
+        model: Option<&str>,
+        max_bytes: usize,
+    ) -> Result<String, ProxyError> {
+        let response = self.complete_with_model(messages, api_key, model).await?;
+        check_response_size(response.len() as u64, max_bytes)?;
+        Ok(response)
+    }
+
+    /// Check the upstream is reachable without running a completion, e.g. by
+    /// listing models. Providers with nothing to reach are always healthy.
+    async fn health(&self) -> Result<(), ProxyError> {
+        Ok(())
+    }
+}
+
+/// An upstream reached over HTTP. Implementing this rather than
+/// [`LlmProvider`] directly routes every completion body through
+/// [`read_body_limited`], so an oversized response is cut off as it is read.
+#[async_trait]
+pub trait HttpLlmProvider: Send + Sync {
+    /// Short identifier used in logs and metrics
+    fn name(&self) -> &str;
+
+    /// Send the completion request on `model`, or the provider's default,
+    /// returning the response before its body is read
+    async fn send(
+        &self,
+        messages: &[ChatTurn],
+        api_key: Option<&str>,
+        model: Option<&str>,
+    ) -> Result<reqwest::Response, ProxyError>;
+
+    /// The completion text in a successful response body
+    fn parse(&self, body: &[u8]) -> Result<String, ProxyError>;
+
+    /// Check the upstream is reachable without running a completion
+    async fn health(&self) -> Result<(), ProxyError> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: HttpLlmProvider> LlmProvider for T {
+    fn name(&self) -> &str {
+        HttpLlmProvider::name(self)
+    }
+
+    async fn complete(&self, messages: &[ChatTurn]) -> Result<String, ProxyError> {
+        self.complete_with_model(messages, None, None).await
+    }
+
+    async fn complete_with_key(&self, messages: &[ChatTurn], api_key: Option<&str>) -> Result<String, ProxyError> {
+        self.complete_with_model(messages, api_key, None).await
+    }
+
+    async fn complete_with_model(
+        &self,
+        messages: &[ChatTurn],
+        api_key: Option<&str>,
+        model: Option<&str>,
+    ) -> Result<String, ProxyError> {
+        self.complete_limited(messages, api_key, model, usize::MAX).await
+    }
+
+    async fn complete_limited(
+        &self,
+        messages: &[ChatTurn],
+        api_key: Option<&str>,
+        model: Option<&str>,
+        max_bytes: usize,
+    ) -> Result<String, ProxyError> {
+        let response = self.send(messages, api_key, model).await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(ProxyError::UpstreamStatus(status.as_u16()));
+        }
+        let body = read_body_limited(response, max_bytes).await?;
+        self.parse(&body)
+    }
+
+    async fn health(&self) -> Result<(), ProxyError> {
+        HttpLlmProvider::health(self).await
+    }
+}
+
+/// Provider that echoes the prompt back, used until a real upstream is configured
+#[derive(Debug, Default)]
+pub struct EchoProvider;
+
+#[async_trait]
+impl LlmProvider for EchoProvider {
+    fn name(&self) -> &str {
+        "echo"
+    }
+
+    async fn complete(&self, messages: &[ChatTurn]) -> Result<String, ProxyError> {
+        let prompt = messages.last().map(|m| m.content.as_str()).unwrap_or_default();
+        Ok(format!("Query received: {}", prompt))
+    }
+}
+
+/// Look up a built-in provider by the name used in `proxy.providers`
+pub fn provider_by_name(name: &str) -> Option<Arc<dyn LlmProvider>> {
+    match name {
+        "echo" => Some(Arc::new(EchoProvider)),
+        _ => None,
+    }
+}
@@ -0,0 +1,991 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, field, info, info_span, warn, Instrument};
+
+use crate::config::{ProxyConfig, TierRoute};
+use crate::error::{AppError, ProxyError};
+use crate::proxy::limits::check_prompt_size;
+use crate::proxy::{
+    content_filter_from_config, provider_by_name, ApiKeyManager, ChatTurn, ConcurrencyLimit, ContentFilter, EncryptedApiKey,
+    LlmProvider, NoopFilter, ProxyMetrics, ResponseCache,
+};
+
+/// Per-request choices for [`ProxyService::query_with_options`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct QueryOptions<'a> {
+    /// The user's stored provider key; the server default is used without one
+    pub api_key: Option<&'a EncryptedApiKey>,
+    /// Model the client asked for, checked against the allowlist
+    pub model: Option<&'a str>,
+    /// The user's `rate_limit_tier`, which selects a `proxy.tier_routes` entry
+    pub tier: Option<&'a str>,
+    /// The answer is streamed to the client, so it is neither served from nor
+    /// added to the response cache
+    pub stream: bool,
+}
+
+/// A provider together with the number of requests it has served
+struct ProviderSlot {
+    provider: Arc<dyn LlmProvider>,
+    served: AtomicU64,
+}
+
+/// Front door for LLM requests, applying the configured request policy
+/// before handing off to the providers in priority order.
+pub struct ProxyService {
+    providers: Vec<ProviderSlot>,
+    request_timeout: Duration,
+    max_prompt_bytes: usize,
+    max_response_bytes: usize,
+    /// Earlier turns kept per request; 0 keeps them all
+    max_context_turns: usize,
+    filter: Arc<dyn ContentFilter>,
+    cache: Option<ResponseCache>,
+    metrics: ProxyMetrics,
+    enabled: bool,
+    /// Decrypts keys users have stored for themselves
+    api_keys: Option<Arc<ApiKeyManager>>,
+    default_api_key: Option<String>,
+    require_api_key: bool,
+    /// Upstream requests allowed in flight at once; unlimited when unset
+    concurrency: Option<ConcurrencyLimit>,
+    /// Model for queries that don't name one
+    default_model: Option<String>,
+    /// Models a query may name besides the default
+    allowed_models: Vec<String>,
+    /// Provider and model overrides by user tier
+    tier_routes: HashMap<String, TierRoute>,
+}
+
+impl ProxyService {
+    pub fn new(provider: Arc<dyn LlmProvider>, config: &ProxyConfig) -> Self {
+        Self::build(vec![provider], config)
+    }
+
+    /// Build a proxy that tries `providers` in order, falling over to the next
+    /// one when a provider fails with a retryable error
+    pub fn with_providers(providers: Vec<Arc<dyn LlmProvider>>, config: &ProxyConfig) -> Result<Self, AppError> {
+        if providers.is_empty() {
+            return Err(AppError::ConfigError("The proxy needs at least one provider".into()));
+        }
+        Ok(Self::build(providers, config))
+    }
+
+    /// The proxy for a non-empty `providers`
+    fn build(providers: Vec<Arc<dyn LlmProvider>>, config: &ProxyConfig) -> Self {
+        Self {
+            providers: providers
+                .into_iter()
+                .map(|provider| ProviderSlot { provider, served: AtomicU64::new(0) })
+                .collect(),
+            request_timeout: Duration::from_millis(config.request_timeout_ms),
+            max_prompt_bytes: config.max_prompt_bytes,
+            max_response_bytes: config.max_response_bytes,
+            max_context_turns: config.max_context_turns,
+            filter: Arc::new(NoopFilter),
+            cache: ResponseCache::from_config(&config.cache),
+            metrics: ProxyMetrics::default(),
+            enabled: true,
+            api_keys: None,
+            default_api_key: config.default_api_key.clone().filter(|key| !key.trim().is_empty()),
+            require_api_key: config.require_api_key,
+            concurrency: None,
+            default_model: config.default_model.clone().filter(|model| !model.trim().is_empty()),
+            allowed_models: config.allowed_models.clone(),
+            tier_routes: config.tier_routes.clone(),
+        }
+    }
+
+    /// Build the proxy from the provider names in `proxy.providers`
+    pub fn from_config(config: &ProxyConfig) -> Result<Self, AppError> {
+        if config.providers.is_empty() {
+            return Err(AppError::ConfigError("proxy.providers must name at least one provider".into()));
+        }
+
+        let providers = config.providers
+            .iter()
+            .map(|name| {
+                provider_by_name(name)
+                    .ok_or_else(|| AppError::ConfigError(format!("Unknown proxy provider '{}'", name)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (tier, route) in &config.tier_routes {
+            if let Some(provider) = route.provider.as_ref().filter(|name| !config.providers.contains(name)) {
+                return Err(AppError::ConfigError(format!(
+                    "proxy.tier_routes.{} names provider '{}', which is not in proxy.providers",
+                    tier, provider
+                )));
+            }
+        }
+
+        let mut service = Self::with_providers(providers, config)?.with_content_filter(content_filter_from_config(config)?);
+        service.concurrency = ConcurrencyLimit::from_config(config)?;
+        Ok(service)
+    }
+
+    /// Cap how many upstream requests run at once
+    pub fn with_concurrency_limit(mut self, limit: ConcurrencyLimit) -> Self {
+        self.concurrency = Some(limit);
+        self
+    }
+
+    /// Screen prompts and completions through `filter`
+    pub fn with_content_filter(mut self, filter: Arc<dyn ContentFilter>) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Turn the proxy on or off; while off every query fails with `ProxyError::Disabled`
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Decrypt users' stored API keys with `api_keys`
+    pub fn with_api_keys(mut self, api_keys: Arc<ApiKeyManager>) -> Self {
+        self.api_keys = Some(api_keys);
+        self
+    }
+
+    /// Whether users' stored keys can be decrypted, and so are worth loading
+    pub fn uses_stored_keys(&self) -> bool {
+        self.api_keys.is_some()
+    }
+
+    /// Requests served so far by each provider, in priority order
+    pub fn served_by_provider(&self) -> Vec<(String, u64)> {
+        self.providers
+            .iter()
+            .map(|slot| (slot.provider.name().to_string(), slot.served.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Upstream call counts and latencies for `/metrics`
+    pub fn metrics(&self) -> &ProxyMetrics {
+        &self.metrics
+    }
+
+    /// Check that at least one provider is reachable. Providers are probed in
+    /// order, each within the request timeout; the last error is returned if
+    /// none answer.
+    pub async fn health(&self) -> Result<(), ProxyError> {
+        if !self.enabled {
+            return Err(ProxyError::Disabled);
+        }
+
+        let mut last_error = None;
+        for slot in &self.providers {
+            let provider = slot.provider.as_ref();
+            let result = tokio::time::timeout(self.request_timeout, provider.health())
+                .await
+                .unwrap_or(Err(ProxyError::Timeout));
+
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    warn!("Provider {} failed its health check: {}", provider.name(), e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.expect("at least one provider"))
+    }
+
+    /// Send `prompt` as the next user turn after `history`. Providers are tried in
+    /// order; the first success wins, otherwise the last error is returned.
+    pub async fn query(&self, prompt: &str, history: &[ChatTurn]) -> Result<String, ProxyError> {
+        self.query_with_options(prompt, history, QueryOptions::default()).await
+    }
+
+    /// Like [`query`](Self::query), on behalf of a user. Upstream calls are
+    /// authenticated with their stored key, or the server default key when
+    /// they have none. A requested model must be the default or one of
+    /// `proxy.allowed_models`. The user's tier may reorder the providers and
+    /// supply the model when none is requested.
+    pub async fn query_with_options(
+        &self,
+        prompt: &str,
+        history: &[ChatTurn],
+        options: QueryOptions<'_>,
+    ) -> Result<String, ProxyError> {
+        if !self.enabled {
+            return Err(ProxyError::Disabled);
+        }
+        let route = options.tier.and_then(|tier| self.tier_routes.get(tier));
+        let model = self.resolve_model(options.model, route)?;
+        let api_key = self.resolve_api_key(options.api_key)?;
+        let providers = self.provider_order(route);
+
+        let messages = self.build_messages(prompt, history);
+        check_prompt_size(&messages, self.max_prompt_bytes)?;
+        self.filter.check(prompt).await?;
+
+        // Context changes the answer, so only standalone prompts are cached.
+        // Completions made with a user's own key are never shared, and
+        // streamed queries always go upstream.
+        let cache_key = self.cache.as_ref()
+            .filter(|_| history.is_empty() && options.api_key.is_none() && !options.stream)
+            .map(|cache| (cache, ResponseCache::key(&Self::model_key(&providers, model), prompt)));
+        if let Some(hit) = cache_key.as_ref().and_then(|(cache, key)| cache.get(key)) {
+            debug!("Serving cached completion");
+            return Ok(hit);
+        }
+
+        let _slot = match &self.concurrency {
+            Some(limit) => Some(limit.acquire().await?),
+            None => None,
+        };
+        let response = self.complete_with_failover(&providers, &messages, api_key.as_deref(), model).await?;
+        self.filter.check(&response).await?;
+
+        if let Some((cache, key)) = cache_key {
+            cache.insert(key, response.clone());
+        }
+        Ok(response)
+    }
+
+    /// The key sent upstream: the user's own, else the default, else none
+    /// unless `require_api_key` is set
+    fn resolve_api_key(&self, stored: Option<&EncryptedApiKey>) -> Result<Option<String>, ProxyError> {
+        if let Some(stored) = stored {
+            let manager = self.api_keys.as_ref().ok_or(ProxyError::InvalidApiKey)?;
+            return manager.decrypt_api_key(stored).map(Some).map_err(|e| {
+                warn!("Stored API key is unusable: {}", e);
+                ProxyError::InvalidApiKey
+            });
+        }
+
+        match &self.default_api_key {
+            Some(key) => Ok(Some(key.clone())),
+            None if self.require_api_key => Err(ProxyError::MissingApiKey),
+            None => Ok(None),
+        }
+    }
+
+    /// The model sent upstream: the requested one if allowed, else the
+    /// tier's, else the default. The tier's model is allowed for its users
+    /// even when it is not in the allowlist.
+    fn resolve_model<'a>(
+        &'a self,
+        requested: Option<&'a str>,
+        route: Option<&'a TierRoute>,
+    ) -> Result<Option<&'a str>, ProxyError> {
+        let routed = route.and_then(|route| route.model.as_deref());
+        let Some(requested) = requested else {
+            return Ok(routed.or(self.default_model.as_deref()));
+        };
+
+        let allowed = self.default_model.as_deref() == Some(requested)
+            || routed == Some(requested)
+            || self.allowed_models.iter().any(|model| model == requested);
+        if allowed {
+            Ok(Some(requested))
+        } else {
+            Err(ProxyError::ModelNotAllowed(requested.to_string()))
+        }
+    }
+
+    /// Providers in the order they are tried: the tier's provider first when
+    /// it names one, then the rest in configured order
+    fn provider_order(&self, route: Option<&TierRoute>) -> Vec<&ProviderSlot> {
+        let mut order: Vec<&ProviderSlot> = self.providers.iter().collect();
+        let routed = route.and_then(|route| route.provider.as_deref());
+        if let Some(first) = routed.and_then(|name| order.iter().position(|slot| slot.provider.name() == name)) {
+            let slot = order.remove(first);
+            order.insert(0, slot);
+        }
+        order
+    }
+
+    /// Identifies who answers a prompt, so cached completions aren't shared
+    /// across provider orders or models
+    fn model_key(providers: &[&ProviderSlot], model: Option<&str>) -> String {
+        let providers = providers
+            .iter()
+            .map(|slot| slot.provider.name())
+            .collect::<Vec<_>>()
+            .join(",");
+        format!("{}/{}", providers, model.unwrap_or_default())
+    }
+
+    async fn complete_with_failover(
+        &self,
+        providers: &[&ProviderSlot],
+        messages: &[ChatTurn],
+        api_key: Option<&str>,
+        model: Option<&str>,
+    ) -> Result<String, ProxyError> {
+        let (last, preferred) = providers.split_last().expect("at least one provider");
+
+        for slot in preferred {
+            match self.query_provider(slot, messages, api_key, model).await {
+                Err(e) if e.is_retryable() => {
+                    warn!("Provider {} failed ({}), trying next provider", slot.provider.name(), e);
+                }
+                result => return result,
+            }
+        }
+
+        self.query_provider(last, messages, api_key, model).await
+    }
+
+    async fn query_provider(
+        &self,
+        slot: &ProviderSlot,
+        messages: &[ChatTurn],
+        api_key: Option<&str>,
+        model: Option<&str>,
+    ) -> Result<String, ProxyError> {
+        let provider = slot.provider.as_ref();
+        let span = info_span!("proxy.provider", provider = provider.name(), latency_ms = field::Empty);
+        let started = Instant::now();
+
+        let call = tokio::time::timeout(
+            self.request_timeout,
+            provider.complete_limited(messages, api_key, model, self.max_response_bytes),
+        );
+        let result = match call.instrument(span.clone()).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!(
+                    "Provider {} did not respond within {:?}",
+                    provider.name(),
+                    self.request_timeout
+                );
+                Err(ProxyError::Timeout)
+            }
+        };
+        let latency = started.elapsed();
+        span.record("latency_ms", latency.as_millis() as u64);
+        self.metrics.record(provider.name(), &result, latency);
+
+        if result.is_ok() {
+            slot.served.fetch_add(1, Ordering::Relaxed);
+            debug!("Request served by provider {}", provider.name());
+        }
+        result
+    }
+
+    /// `history` followed by `prompt` as the user turn, keeping only the most
+    /// recent `max_context_turns` of history
+    fn build_messages(&self, prompt: &str, history: &[ChatTurn]) -> Vec<ChatTurn> {
+        let history = match self.max_context_turns {
+            max if max > 0 && history.len() > max => {
+                info!("Dropping {} oldest of {} context turns", history.len() - max, history.len());
+                &history[history.len() - max..]
+            }
+            _ => history,
+        };
+
+        let mut messages = Vec::with_capacity(history.len() + 1);
+        messages.extend_from_slice(history);
+        messages.push(ChatTurn::user(prompt));
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    /// Provider that records the messages it was asked to complete
+    #[derive(Default)]
+    struct RecordingProvider {
+        seen: Mutex<Vec<ChatTurn>>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for RecordingProvider {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        async fn complete(&self, messages: &[ChatTurn]) -> Result<String, ProxyError> {
+            *self.seen.lock().unwrap() = messages.to_vec();
+            Ok("ok".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_assembles_history() {
+        let provider = Arc::new(RecordingProvider::default());
+        let service = ProxyService::new(provider.clone(), &ProxyConfig { request_timeout_ms: 1000, ..ProxyConfig::default() });
+
+        let history = vec![
+            ChatTurn::user("What is Rust?"),
+            ChatTurn::assistant("A systems programming language."),
+        ];
+        service.query("Who makes it?", &history).await.unwrap();
+
+        let seen = provider.seen.lock().unwrap().clone();
+        assert_eq!(seen.len(), 3);
+        assert_eq!(seen[..2], history[..]);
+        assert_eq!(seen[2], ChatTurn::user("Who makes it?"));
+    }
+
+    #[tokio::test]
+    async fn test_context_trimmed_to_max_turns() {
+        let provider = Arc::new(RecordingProvider::default());
+        let config = ProxyConfig { max_context_turns: 2, ..ProxyConfig::default() };
+        let service = ProxyService::new(provider.clone(), &config);
+
+        let history = vec![
+            ChatTurn::user("q1"),
+            ChatTurn::assistant("a1"),
+            ChatTurn::user("q2"),
+            ChatTurn::assistant("a2"),
+            ChatTurn::user("q3"),
+        ];
+        service.query("latest", &history).await.unwrap();
+
+        // The two newest turns survive, followed by the new prompt
+        let seen = provider.seen.lock().unwrap().clone();
+        assert_eq!(seen, vec![history[3].clone(), history[4].clone(), ChatTurn::user("latest")]);
+
+        // Short conversations are sent whole
+        service.query("again", &history[..1]).await.unwrap();
+        assert_eq!(provider.seen.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_proxy() {
+        let provider = Arc::new(RecordingProvider::default());
+        let service = ProxyService::new(provider.clone(), &ProxyConfig { request_timeout_ms: 1000, ..ProxyConfig::default() })
+            .with_enabled(false);
+
+        assert!(matches!(service.query("Hello", &[]).await, Err(ProxyError::Disabled)));
+        assert!(provider.seen.lock().unwrap().is_empty());
+    }
+
+    /// Provider that answers with the API key it was called with
+    struct KeyEchoProvider;
+
+    #[async_trait]
+    impl LlmProvider for KeyEchoProvider {
+        fn name(&self) -> &str {
+            "key-echo"
+        }
+
+        async fn complete(&self, _messages: &[ChatTurn]) -> Result<String, ProxyError> {
+            Ok("no key".to_string())
+        }
+
+        async fn complete_with_key(&self, _messages: &[ChatTurn], api_key: Option<&str>) -> Result<String, ProxyError> {
+            Ok(api_key.unwrap_or("no key").to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_api_key_resolution() {
+        let manager = Arc::new(ApiKeyManager::from_base64_key(crate::proxy::DEVELOPMENT_ENCRYPTION_KEY).unwrap());
+        let stored = manager.encrypt_api_key("user-key", None).unwrap();
+
+        let config = ProxyConfig { default_api_key: Some("server-key".to_string()), ..ProxyConfig::default() };
+        let service = ProxyService::new(Arc::new(KeyEchoProvider), &config).with_api_keys(manager.clone());
+        assert_eq!(service.query_with_options("hi", &[], QueryOptions { api_key: Some(&stored), ..QueryOptions::default() }).await.unwrap(), "user-key");
+        assert_eq!(service.query_with_options("hi", &[], QueryOptions::default()).await.unwrap(), "server-key");
+
+        // Keys that can no longer be decrypted are refused rather than skipped
+        let expired = manager.encrypt_api_key("user-key", Some(0)).unwrap();
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert!(matches!(service.query_with_options("hi", &[], QueryOptions { api_key: Some(&expired), ..QueryOptions::default() }).await, Err(ProxyError::InvalidApiKey)));
+
+        let keyless = ProxyService::new(Arc::new(KeyEchoProvider), &ProxyConfig::default());
+        assert_eq!(keyless.query("hi", &[]).await.unwrap(), "no key");
+
+        let required = ProxyConfig { require_api_key: true, ..ProxyConfig::default() };
+        let strict = ProxyService::new(Arc::new(KeyEchoProvider), &required).with_api_keys(manager);
+        assert!(matches!(strict.query("hi", &[]).await, Err(ProxyError::MissingApiKey)));
+        assert_eq!(strict.query_with_options("hi", &[], QueryOptions { api_key: Some(&stored), ..QueryOptions::default() }).await.unwrap(), "user-key");
+    }
+
+    /// Provider that answers with the model it was asked for
+    struct ModelEchoProvider;
+
+    #[async_trait]
+    impl LlmProvider for ModelEchoProvider {
+        fn name(&self) -> &str {
+            "model-echo"
+        }
+
+        async fn complete(&self, _messages: &[ChatTurn]) -> Result<String, ProxyError> {
+            Ok("provider default".to_string())
+        }
+
+        async fn complete_with_model(
+            &self,
+            _messages: &[ChatTurn],
+            _api_key: Option<&str>,
+            model: Option<&str>,
+        ) -> Result<String, ProxyError> {
+            Ok(model.unwrap_or("provider default").to_string())
+        }
+    }
+
+    fn with_model(model: &str) -> QueryOptions<'_> {
+        QueryOptions { model: Some(model), ..QueryOptions::default() }
+    }
+
+    #[tokio::test]
+    async fn test_model_selection() {
+        let config = ProxyConfig {
+            default_model: Some("sonnet".to_string()),
+            allowed_models: vec!["haiku".to_string()],
+            ..ProxyConfig::default()
+        };
+        let service = ProxyService::new(Arc::new(ModelEchoProvider), &config);
+
+        // Unnamed queries get the default, which is always allowed
+        assert_eq!(service.query_with_options("hi", &[], QueryOptions::default()).await.unwrap(), "sonnet");
+        assert_eq!(service.query_with_options("hi", &[], with_model("sonnet")).await.unwrap(), "sonnet");
+        assert_eq!(service.query_with_options("hi", &[], with_model("haiku")).await.unwrap(), "haiku");
+
+        match service.query_with_options("hi", &[], with_model("opus")).await {
+            Err(ProxyError::ModelNotAllowed(model)) => assert_eq!(model, "opus"),
+            other => panic!("expected the model to be refused, got {:?}", other),
+        }
+        assert_eq!(service.served_by_provider()[0].1, 3);
+
+        // Without an allowlist only the default may be named
+        let unlisted = ProxyService::new(Arc::new(ModelEchoProvider), &ProxyConfig::default());
+        assert_eq!(unlisted.query("hi", &[]).await.unwrap(), "provider default");
+        assert!(matches!(
+            unlisted.query_with_options("hi", &[], with_model("haiku")).await,
+            Err(ProxyError::ModelNotAllowed(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cache_keyed_by_model() {
+        let mut config = ProxyConfig { allowed_models: vec!["a".to_string(), "b".to_string()], ..ProxyConfig::default() };
+        config.cache.enabled = true;
+        let service = ProxyService::new(Arc::new(ModelEchoProvider), &config);
+
+        assert_eq!(service.query_with_options("hi", &[], with_model("a")).await.unwrap(), "a");
+        assert_eq!(service.query_with_options("hi", &[], with_model("b")).await.unwrap(), "b");
+    }
+
+    #[tokio::test]
+    async fn test_cache_skipped_for_stored_keys() {
+        let manager = Arc::new(ApiKeyManager::from_base64_key(crate::proxy::DEVELOPMENT_ENCRYPTION_KEY).unwrap());
+        let stored = manager.encrypt_api_key("user-key", None).unwrap();
+        let mut config = ProxyConfig { default_api_key: Some("server-key".to_string()), ..ProxyConfig::default() };
+        config.cache.enabled = true;
+        let service = ProxyService::new(Arc::new(KeyEchoProvider), &config).with_api_keys(manager);
+        let with_key = QueryOptions { api_key: Some(&stored), ..QueryOptions::default() };
+
+        // A completion made with a user's key is not cached for anyone else,
+        // nor is a cached server-key completion served to that user
+        assert_eq!(service.query_with_options("hi", &[], with_key).await.unwrap(), "user-key");
+        assert_eq!(service.query("hi", &[]).await.unwrap(), "server-key");
+        assert_eq!(service.query_with_options("hi", &[], with_key).await.unwrap(), "user-key");
+    }
+
+    /// Provider whose replies are numbered by call
+    #[derive(Default)]
+    struct CountingProvider {
+        calls: AtomicU64,
+    }
+
+    #[async_trait]
+    impl LlmProvider for CountingProvider {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        async fn complete(&self, _messages: &[ChatTurn]) -> Result<String, ProxyError> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(format!("reply {}", call))
+        }
+    }
+
+    /// Provider that fails with a fixed upstream status
+    struct FailingProvider {
+        status: u16,
+    }
+
+    #[async_trait]
+    impl LlmProvider for FailingProvider {
+        fn name(&self) -> &str {
+            "failing"
+        }
+
+        async fn complete(&self, _messages: &[ChatTurn]) -> Result<String, ProxyError> {
+            Err(ProxyError::UpstreamStatus(self.status))
+        }
+    }
+
+    /// Provider whose health check passes or fails as configured
+    struct ProbeProvider {
+        healthy: bool,
+    }
+
+    #[async_trait]
+    impl LlmProvider for ProbeProvider {
+        fn name(&self) -> &str {
+            "probe"
+        }
+
+        async fn complete(&self, _messages: &[ChatTurn]) -> Result<String, ProxyError> {
+            Ok("ok".to_string())
+        }
+
+        async fn health(&self) -> Result<(), ProxyError> {
+            if self.healthy {
+                Ok(())
+            } else {
+                Err(ProxyError::RequestFailed("upstream unreachable".to_string()))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_health() {
+        let config = ProxyConfig { request_timeout_ms: 1000, ..ProxyConfig::default() };
+        let healthy = || Arc::new(ProbeProvider { healthy: true }) as Arc<dyn LlmProvider>;
+        let unhealthy = || Arc::new(ProbeProvider { healthy: false }) as Arc<dyn LlmProvider>;
+
+        assert!(ProxyService::new(healthy(), &config).health().await.is_ok());
+        assert!(matches!(
+            ProxyService::new(unhealthy(), &config).health().await,
+            Err(ProxyError::RequestFailed(_))
+        ));
+
+        // One reachable provider is enough, since queries fail over to it
+        assert!(ProxyService::with_providers(vec![unhealthy(), healthy()], &config).unwrap().health().await.is_ok());
+
+        let disabled = ProxyService::new(healthy(), &config).with_enabled(false);
+        assert!(matches!(disabled.health().await, Err(ProxyError::Disabled)));
+    }
+
+    #[tokio::test]
+    async fn test_failover_to_secondary() {
+        let secondary = Arc::new(RecordingProvider::default());
+        let service = ProxyService::with_providers(
+            vec![Arc::new(FailingProvider { status: 503 }), secondary.clone()],
+            &ProxyConfig { request_timeout_ms: 1000, ..ProxyConfig::default() },
+        ).unwrap();
+
+        assert_eq!(service.query("Hello", &[]).await.unwrap(), "ok");
+        assert_eq!(secondary.seen.lock().unwrap().len(), 1);
+        assert_eq!(
+            service.served_by_provider(),
+            vec![("failing".to_string(), 0), ("recording".to_string(), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failover_returns_last_error() {
+        let service = ProxyService::with_providers(
+            vec![Arc::new(FailingProvider { status: 503 }), Arc::new(FailingProvider { status: 502 })],
+            &ProxyConfig { request_timeout_ms: 1000, ..ProxyConfig::default() },
+        ).unwrap();
+
+        match service.query("Hello", &[]).await {
+            Err(ProxyError::UpstreamStatus(status)) => assert_eq!(status, 502),
+            other => panic!("expected the secondary's error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_error_does_not_fail_over() {
+        let secondary = Arc::new(RecordingProvider::default());
+        let service = ProxyService::with_providers(
+            vec![Arc::new(FailingProvider { status: 400 }), secondary.clone()],
+            &ProxyConfig { request_timeout_ms: 1000, ..ProxyConfig::default() },
+        ).unwrap();
+
+        // The request itself is bad, so another provider would refuse it too
+        assert!(matches!(service.query("Hello", &[]).await, Err(ProxyError::UpstreamStatus(400))));
+        assert!(secondary.seen.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_tier_routing() {
+        let standard = Arc::new(RecordingProvider::default());
+        let premium = Arc::new(CountingProvider::default());
+        let mut config = ProxyConfig::default();
+        config.tier_routes.insert(
+            "premium".to_string(),
+            TierRoute { provider: Some("counting".to_string()), model: None },
+        );
+        let service = ProxyService::with_providers(vec![standard.clone(), premium.clone()], &config).unwrap();
+        let tier = |tier| QueryOptions { tier: Some(tier), ..QueryOptions::default() };
+
+        assert_eq!(service.query_with_options("hi", &[], tier("premium")).await.unwrap(), "reply 1");
+        assert!(standard.seen.lock().unwrap().is_empty());
+
+        // Unmapped tiers, and queries without one, keep the configured order
+        assert_eq!(service.query_with_options("hi", &[], tier("standard")).await.unwrap(), "ok");
+        assert_eq!(service.query("hi", &[]).await.unwrap(), "ok");
+        assert_eq!(premium.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            service.served_by_provider(),
+            vec![("recording".to_string(), 2), ("counting".to_string(), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tier_model() {
+        let mut config = ProxyConfig {
+            default_model: Some("haiku".to_string()),
+            allowed_models: vec!["opus".to_string()],
+            ..ProxyConfig::default()
+        };
+        config.tier_routes.insert(
+            "premium".to_string(),
+            TierRoute { provider: None, model: Some("sonnet".to_string()) },
+        );
+        let service = ProxyService::new(Arc::new(ModelEchoProvider), &config);
+        let premium = QueryOptions { tier: Some("premium"), ..QueryOptions::default() };
+
+        assert_eq!(service.query_with_options("hi", &[], premium).await.unwrap(), "sonnet");
+        assert_eq!(service.query("hi", &[]).await.unwrap(), "haiku");
+
+        // A model the client names still wins, and is still checked
+        let named = QueryOptions { model: Some("opus"), ..premium };
+        assert_eq!(service.query_with_options("hi", &[], named).await.unwrap(), "opus");
+        let unlisted = QueryOptions { model: Some("mystery"), ..premium };
+        assert!(matches!(
+            service.query_with_options("hi", &[], unlisted).await,
+            Err(ProxyError::ModelNotAllowed(_))
+        ));
+
+        // The tier's model may be named by its users, but by no one else
+        let routed = QueryOptions { model: Some("sonnet"), ..premium };
+        assert_eq!(service.query_with_options("hi", &[], routed).await.unwrap(), "sonnet");
+        let other_tier = QueryOptions { model: Some("sonnet"), tier: Some("standard"), ..QueryOptions::default() };
+        assert!(matches!(
+            service.query_with_options("hi", &[], other_tier).await,
+            Err(ProxyError::ModelNotAllowed(_))
+        ));
+    }
+
+    #[test]
+    fn test_providers_from_config() {
+        let config = ProxyConfig {
+            providers: vec!["echo".to_string(), "echo".to_string()],
+            ..ProxyConfig::default()
+        };
+        let service = ProxyService::from_config(&config).unwrap();
+        assert_eq!(service.served_by_provider().len(), 2);
+
+        let unknown = ProxyConfig { providers: vec!["nope".to_string()], ..ProxyConfig::default() };
+        assert!(matches!(ProxyService::from_config(&unknown), Err(AppError::ConfigError(_))));
+
+        let empty = ProxyConfig { providers: Vec::new(), ..ProxyConfig::default() };
+        assert!(matches!(ProxyService::from_config(&empty), Err(AppError::ConfigError(_))));
+        assert!(matches!(ProxyService::with_providers(Vec::new(), &empty), Err(AppError::ConfigError(_))));
+
+        let mut unrouted = ProxyConfig::default();
+        unrouted.tier_routes.insert("premium".to_string(), TierRoute { provider: Some("nope".to_string()), model: None });
+        assert!(matches!(ProxyService::from_config(&unrouted), Err(AppError::ConfigError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_size_limits() {
+        let provider = Arc::new(RecordingProvider::default());
+        let config = ProxyConfig { max_prompt_bytes: 10, max_response_bytes: 1, ..ProxyConfig::default() };
+        let service = ProxyService::new(provider.clone(), &config);
+
+        // Over-limit prompts never reach the provider
+        let history = vec![ChatTurn::user("0123456789")];
+        assert!(matches!(service.query("x", &history).await, Err(ProxyError::ResponseError(_))));
+        assert!(provider.seen.lock().unwrap().is_empty());
+
+        // "ok" is two bytes, one over the response limit
+        assert!(matches!(service.query("x", &[]).await, Err(ProxyError::ResponseError(_))));
+        assert_eq!(service.served_by_provider()[0].1, 0);
+        assert_eq!(service.metrics().requests("recording", "ok"), 0);
+        assert_eq!(service.metrics().errors("recording"), 1);
+    }
+
+    /// Provider that fetches its completion from `url` over HTTP
+    struct HttpBodyProvider {
+        url: String,
+    }
+
+    #[async_trait]
+    impl crate::proxy::HttpLlmProvider for HttpBodyProvider {
+        fn name(&self) -> &str {
+            "http-body"
+        }
+
+        async fn send(
+            &self,
+            _messages: &[ChatTurn],
+            _api_key: Option<&str>,
+            _model: Option<&str>,
+        ) -> Result<reqwest::Response, ProxyError> {
+            reqwest::get(&self.url).await.map_err(|e| ProxyError::RequestFailed(e.to_string()))
+        }
+
+        fn parse(&self, body: &[u8]) -> Result<String, ProxyError> {
+            String::from_utf8(body.to_vec()).map_err(|e| ProxyError::ResponseError(e.to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_response_limit_applied_while_reading() {
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("x".repeat(8 * 1024)))
+            .mount(&server)
+            .await;
+
+        let provider = Arc::new(HttpBodyProvider { url: server.uri() });
+        let config = ProxyConfig { max_response_bytes: 1024, ..ProxyConfig::default() };
+        let service = ProxyService::new(provider, &config);
+
+        assert!(matches!(service.query("x", &[]).await, Err(ProxyError::ResponseError(_))));
+        assert_eq!(service.metrics().requests("http-body", "ok"), 0);
+    }
+
+    /// Filter that rejects any text containing a keyword
+    struct KeywordFilter(&'static str);
+
+    #[async_trait]
+    impl ContentFilter for KeywordFilter {
+        async fn check(&self, text: &str) -> Result<(), ProxyError> {
+            if text.contains(self.0) {
+                return Err(ProxyError::ContentRejected(format!("contains '{}'", self.0)));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_content_filter() {
+        let provider = Arc::new(RecordingProvider::default());
+        let service = ProxyService::new(provider.clone(), &ProxyConfig::default())
+            .with_content_filter(Arc::new(KeywordFilter("forbidden")));
+
+        // Rejected prompts never reach the provider
+        let result = service.query("say something forbidden", &[]).await;
+        assert!(matches!(result, Err(ProxyError::ContentRejected(_))));
+        assert!(provider.seen.lock().unwrap().is_empty());
+
+        assert_eq!(service.query("say something nice", &[]).await.unwrap(), "ok");
+
+        // Completions are screened on the way back too
+        let strict = ProxyService::new(provider, &ProxyConfig::default())
+            .with_content_filter(Arc::new(KeywordFilter("ok")));
+        assert!(matches!(strict.query("hello", &[]).await, Err(ProxyError::ContentRejected(_))));
+    }
+
+    #[tokio::test]
+    async fn test_cached_completions() {
+        let provider = Arc::new(CountingProvider::default());
+        let mut config = ProxyConfig::default();
+        config.cache.enabled = true;
+        let service = ProxyService::new(provider.clone(), &config);
+
+        // Miss, then hit
+        assert_eq!(service.query("hello", &[]).await.unwrap(), "reply 1");
+        assert_eq!(service.query("hello", &[]).await.unwrap(), "reply 1");
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+
+        // Different prompts and prompts with context always reach the provider
+        assert_eq!(service.query("goodbye", &[]).await.unwrap(), "reply 2");
+        let history = vec![ChatTurn::user("hi"), ChatTurn::assistant("hello")];
+        assert_eq!(service.query("hello", &history).await.unwrap(), "reply 3");
+        assert_eq!(service.query("hello", &history).await.unwrap(), "reply 4");
+    }
+
+    #[tokio::test]
+    async fn test_streamed_queries_bypass_cache() {
+        let provider = Arc::new(CountingProvider::default());
+        let mut config = ProxyConfig::default();
+        config.cache.enabled = true;
+        let service = ProxyService::new(provider.clone(), &config);
+        let streamed = QueryOptions { stream: true, ..QueryOptions::default() };
+
+        // A streamed query doesn't fill the cache...
+        assert_eq!(service.query_with_options("hello", &[], streamed).await.unwrap(), "reply 1");
+        assert_eq!(service.query("hello", &[]).await.unwrap(), "reply 2");
+
+        // ...nor is it served from it
+        assert_eq!(service.query("hello", &[]).await.unwrap(), "reply 2");
+        assert_eq!(service.query_with_options("hello", &[], streamed).await.unwrap(), "reply 3");
+    }
+
+    #[tokio::test]
+    async fn test_cache_disabled_by_default() {
+        let provider = Arc::new(CountingProvider::default());
+        let service = ProxyService::new(provider.clone(), &ProxyConfig::default());
+
+        service.query("hello", &[]).await.unwrap();
+        service.query("hello", &[]).await.unwrap();
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cached_completion_expires() {
+        let provider = Arc::new(CountingProvider::default());
+        let mut config = ProxyConfig::default();
+        config.cache.enabled = true;
+        config.cache.ttl_secs = 10;
+        let service = ProxyService::new(provider.clone(), &config);
+
+        service.query("hello", &[]).await.unwrap();
+        tokio::time::advance(Duration::from_secs(11)).await;
+        assert_eq!(service.query("hello", &[]).await.unwrap(), "reply 2");
+    }
+
+    /// Provider that takes `delay` to answer and tracks its busiest moment
+    struct SlowProvider {
+        delay: Duration,
+        running: AtomicU64,
+        peak: AtomicU64,
+    }
+
+    #[async_trait]
+    impl LlmProvider for SlowProvider {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        async fn complete(&self, _messages: &[ChatTurn]) -> Result<String, ProxyError> {
+            let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(running, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok("done".to_string())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrency_limit_queues() {
+        let provider = Arc::new(SlowProvider {
+            delay: Duration::from_secs(1),
+            running: AtomicU64::new(0),
+            peak: AtomicU64::new(0),
+        });
+        let service = ProxyService::new(provider.clone(), &ProxyConfig::default())
+            .with_concurrency_limit(ConcurrencyLimit::new(1, Some(Duration::from_secs(5)), 1000));
+
+        // The second request waits for the first instead of running alongside it
+        let started = tokio::time::Instant::now();
+        let (first, second) = tokio::join!(service.query("one", &[]), service.query("two", &[]));
+        assert_eq!(first.unwrap(), "done");
+        assert_eq!(second.unwrap(), "done");
+        assert_eq!(provider.peak.load(Ordering::SeqCst), 1);
+        assert!(started.elapsed() >= Duration::from_secs(2));
+
+        // With the busy policy the second is refused outright
+        let service = ProxyService::new(provider.clone(), &ProxyConfig::default())
+            .with_concurrency_limit(ConcurrencyLimit::new(1, None, 750));
+        let (first, second) = tokio::join!(service.query("one", &[]), service.query("two", &[]));
+        assert!(first.is_ok());
+        assert!(matches!(second, Err(ProxyError::Busy { retry_after_ms: 750 })));
+    }
+}
@@ -0,0 +1,162 @@
+use async_trait::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::info;
+use uuid::Uuid;
+
+use super::ScalingAction;
+use crate::config::ScalingConfig;
+use crate::error::AppError;
+
+/// Longest a scaling command may run before it is killed and counted as failed
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Carries out scaling decisions, e.g. by asking an orchestrator for more or
+/// fewer instances. Not called while `scaling.dry_run` is set. A failed action
+/// doesn't start the cooldown, so it is retried on the next scaling check.
+/// A scale-down names the drained instance to remove in `instance_id`, and
+/// only runs once that instance's connections have closed.
+#[async_trait]
+pub trait ScalingExecutor: Send + Sync {
+    async fn execute(&self, action: ScalingAction, instance_id: Option<Uuid>) -> Result<(), AppError>;
+}
+
+/// Does nothing; for deployments where an external autoscaler acts on
+/// `/scaling/recommendation` instead
+pub struct NoopExecutor;
+
+#[async_trait]
+impl ScalingExecutor for NoopExecutor {
+    async fn execute(&self, _action: ScalingAction, _instance_id: Option<Uuid>) -> Result<(), AppError> {
+        Ok(())
+    }
+}
+
+/// Logs each action it is asked to take
+pub struct LoggingExecutor;
+
+#[async_trait]
+impl ScalingExecutor for LoggingExecutor {
+    async fn execute(&self, action: ScalingAction, instance_id: Option<Uuid>) -> Result<(), AppError> {
+        match instance_id {
+            Some(id) => info!("Executing scaling action {:?} on instance {}", action, id),
+            None => info!("Executing scaling action {:?}", action),
+        }
+        Ok(())
+    }
+}
+
+/// Runs the configured shell command for each direction, e.g. a script that
+/// calls `kubectl scale`. The command gets `BUDDYBOT_SCALING_ACTION`
+/// (`scale_up`/`scale_down`) and `BUDDYBOT_SCALING_FACTOR` in its environment,
+/// plus `BUDDYBOT_SCALING_INSTANCE_ID` for a scale-down of a drained instance,
+/// and must exit 0 for the action to count as done.
+pub struct ShellCommandExecutor {
+    scale_up_command: Option<String>,
+    scale_down_command: Option<String>,
+}
+
+impl ShellCommandExecutor {
+    pub fn new(scale_up_command: Option<String>, scale_down_command: Option<String>) -> Self {
+        Self { scale_up_command, scale_down_command }
+    }
+}
+
+#[async_trait]
+impl ScalingExecutor for ShellCommandExecutor {
+    async fn execute(&self, action: ScalingAction, instance_id: Option<Uuid>) -> Result<(), AppError> {
+        let (name, factor, command) = match &action {
+            ScalingAction::ScaleUp(factor) => ("scale_up", factor, &self.scale_up_command),
+            ScalingAction::ScaleDown(factor) => ("scale_down", factor, &self.scale_down_command),
+        };
+        let Some(command) = command else {
+            info!("No command configured for {}, skipping", name);
+            return Ok(());
+        };
+
+        let mut run = Command::new("sh");
+        run.arg("-c")
+            .arg(command)
+            .env("BUDDYBOT_SCALING_ACTION", name)
+            .env("BUDDYBOT_SCALING_FACTOR", factor.to_string())
+            .kill_on_drop(true);
+        if let Some(id) = instance_id {
+            run.env("BUDDYBOT_SCALING_INSTANCE_ID", id.to_string());
+        }
+        let run = run.status();
+        let status = tokio::time::timeout(COMMAND_TIMEOUT, run)
+            .await
+            .map_err(|_| AppError::InternalError(format!("{} command timed out after {:?}", name, COMMAND_TIMEOUT)))?
+            .map_err(|e| AppError::InternalError(format!("Failed to run {} command: {}", name, e)))?;
+
+        if !status.success() {
+            return Err(AppError::InternalError(format!("{} command exited with {}", name, status)));
+        }
+        info!("{} command completed", name);
+        Ok(())
+    }
+}
+
+/// Build the executor named by `scaling.executor`: "log", "noop" or "shell"
+pub fn executor_from_config(config: &ScalingConfig) -> Result<Arc<dyn ScalingExecutor>, AppError> {
+    match config.executor.as_str() {
+        "log" => Ok(Arc::new(LoggingExecutor)),
+        "noop" => Ok(Arc::new(NoopExecutor)),
+        "shell" => {
+            if config.scale_up_command.is_none() && config.scale_down_command.is_none() {
+                return Err(AppError::ConfigError(
+                    "scaling.executor 'shell' needs scale_up_command or scale_down_command".into(),
+                ));
+            }
+            Ok(Arc::new(ShellCommandExecutor::new(
+                config.scale_up_command.clone(),
+                config.scale_down_command.clone(),
+            )))
+        }
+        other => Err(AppError::ConfigError(format!("Unknown scaling executor '{}'", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_shell_command_executor() {
+        let executor = ShellCommandExecutor::new(
+            Some(r#"test "$BUDDYBOT_SCALING_ACTION" = scale_up && test "$BUDDYBOT_SCALING_FACTOR" = 1.5"#.to_string()),
+            Some("exit 3".to_string()),
+        );
+        executor.execute(ScalingAction::ScaleUp(1.5), None).await.unwrap();
+        assert!(executor.execute(ScalingAction::ScaleDown(0.5), None).await.is_err());
+
+        // A scale-down is told which drained instance to remove
+        let id = Uuid::new_v4();
+        let executor = ShellCommandExecutor::new(
+            None,
+            Some(format!(r#"test "$BUDDYBOT_SCALING_INSTANCE_ID" = {}"#, id)),
+        );
+        executor.execute(ScalingAction::ScaleDown(0.5), Some(id)).await.unwrap();
+        assert!(executor.execute(ScalingAction::ScaleDown(0.5), None).await.is_err());
+
+        // A direction without a command is a no-op
+        let executor = ShellCommandExecutor::new(None, None);
+        executor.execute(ScalingAction::ScaleDown(0.5), None).await.unwrap();
+    }
+
+    #[test]
+    fn test_executor_from_config() {
+        let config = |executor: &str| ScalingConfig {
+            executor: executor.to_string(),
+            ..ScalingConfig::default()
+        };
+        assert!(executor_from_config(&config("log")).is_ok());
+        assert!(executor_from_config(&config("noop")).is_ok());
+        assert!(executor_from_config(&config("shell")).is_err(), "shell needs a command");
+        assert!(executor_from_config(&config("k8s")).is_err());
+
+        let shell = ScalingConfig { scale_up_command: Some("true".to_string()), ..config("shell") };
+        assert!(executor_from_config(&shell).is_ok());
+    }
+}
@@ -0,0 +1,91 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::auth::middleware::require_admin;
+use crate::error::Error;
+use crate::scaling::SystemMetrics;
+use crate::AppState;
+
+/// One instance's report in a heartbeat batch
+#[derive(Debug, Deserialize)]
+pub struct HeartbeatEntry {
+    pub instance_id: Uuid,
+    pub metrics: SystemMetrics,
+}
+
+/// Why one entry of a heartbeat batch was not applied
+#[derive(Debug, Serialize)]
+pub struct HeartbeatError {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance_id: Option<Uuid>,
+    pub error: String,
+}
+
+/// Cluster-wide load averages for dashboards; `metrics` is null until an instance reports.
+/// `database` is this instance's connection pool, including callers queued for a connection.
+pub async fn scaling_metrics(state: web::Data<AppState>) -> HttpResponse {
+    let metrics = state.scaling.aggregate_metrics().await;
+    let database = state.db.get_pool_status().await.ok();
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "metrics": metrics,
+        "database": database,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    }))
+}
+
+/// Recent scaling decisions with the load that triggered each, oldest first
+pub async fn scaling_history(state: web::Data<AppState>) -> HttpResponse {
+    let events = state.scaling.scaling_history().await;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "events": events,
+    }))
+}
+
+/// Record metrics for many instances at once, for a central node relaying
+/// reports. The body is a JSON array of `{ instance_id, metrics }` and may be
+/// gzip- or brotli-encoded with `Content-Encoding`. Entries are applied
+/// independently: the response counts those applied and lists the rest with
+/// their index and the reason. Requires the admin token.
+pub async fn heartbeat_batch(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    entries: web::Json<Vec<serde_json::Value>>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &state.config.admin)?;
+
+    // Parse entries one by one so a malformed entry only fails itself
+    let mut errors = Vec::new();
+    let mut parsed = Vec::new();
+    for (index, entry) in entries.into_inner().into_iter().enumerate() {
+        match serde_json::from_value::<HeartbeatEntry>(entry) {
+            Ok(entry) => parsed.push((index, entry)),
+            Err(e) => errors.push(HeartbeatError { index, instance_id: None, error: e.to_string() }),
+        }
+    }
+
+    let updates = parsed.iter().map(|(_, entry)| (entry.instance_id, entry.metrics.clone())).collect();
+    let results = state.scaling.update_instances_metrics(updates).await;
+    let mut applied = 0;
+    for ((index, entry), result) in parsed.into_iter().zip(results) {
+        match result {
+            Ok(()) => applied += 1,
+            Err(error) => errors.push(HeartbeatError { index, instance_id: Some(entry.instance_id), error }),
+        }
+    }
+    errors.sort_by_key(|e| e.index);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "applied": applied,
+        "errors": errors,
+    })))
+}
+
+/// Scaling signal for external autoscalers: `{ action, factor }`, where `action`
+/// is `scale_up`, `scale_down` or `hold`
+pub async fn scaling_recommendation(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(state.scaling.recommendation().await)
+}
@@ -10,10 +10,15 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use chrono::{DateTime, Utc};
 use tracing::{info, warn};
 
+pub mod executor;
+pub mod handlers;
+
+pub use executor::{executor_from_config, LoggingExecutor, NoopExecutor, ScalingExecutor, ShellCommandExecutor};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemMetrics {
     pub cpu_usage: f32,
@@ -27,6 +32,22 @@ pub struct SystemMetrics {
     pub timestamp: DateTime<Utc>,
 }
 
+impl SystemMetrics {
+    /// Reject readings that would skew the cluster averages
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0.0..=100.0).contains(&self.cpu_usage) {
+            return Err(format!("cpu_usage must be between 0 and 100, got {}", self.cpu_usage));
+        }
+        if self.memory_total == 0 {
+            return Err("memory_total must be greater than 0".to_string());
+        }
+        if self.memory_used > self.memory_total {
+            return Err("memory_used exceeds memory_total".to_string());
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScalingConfig {
     pub cpu_threshold: f32,
@@ -35,6 +56,57 @@ pub struct ScalingConfig {
     pub scale_up_factor: f32,
     pub scale_down_factor: f32,
     pub cooldown_period: i64,
+    /// Seconds before another scale-up; `cooldown_period` when unset
+    #[serde(default)]
+    pub scale_up_cooldown: Option<i64>,
+    /// Seconds before a scale-down; `cooldown_period` when unset
+    #[serde(default)]
+    pub scale_down_cooldown: Option<i64>,
+    /// How many past scaling decisions to keep
+    #[serde(default = "default_history_size")]
+    pub history_size: usize,
+    /// Record and log scaling decisions without acting on them
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Seconds before retrying after the executor fails, doubling with each
+    /// further failure
+    #[serde(default = "default_executor_retry_secs")]
+    pub executor_retry_secs: i64,
+}
+
+fn default_history_size() -> usize { 100 }
+fn default_executor_retry_secs() -> i64 { 60 }
+
+/// Most times the executor retry delay is doubled
+const MAX_RETRY_DOUBLINGS: u32 = 5;
+
+impl ScalingConfig {
+    /// Seconds that must pass since the last scaling action before `action` may be taken
+    pub fn cooldown_for(&self, action: &ScalingAction) -> i64 {
+        match action {
+            ScalingAction::ScaleUp(_) => self.scale_up_cooldown,
+            ScalingAction::ScaleDown(_) => self.scale_down_cooldown,
+        }
+        .unwrap_or(self.cooldown_period)
+    }
+}
+
+impl From<&crate::config::ScalingConfig> for ScalingConfig {
+    fn from(config: &crate::config::ScalingConfig) -> Self {
+        Self {
+            cpu_threshold: config.cpu_threshold,
+            memory_threshold: config.memory_threshold,
+            connection_threshold: config.connection_threshold,
+            scale_up_factor: config.scale_up_factor,
+            scale_down_factor: config.scale_down_factor,
+            cooldown_period: config.cooldown_period,
+            scale_up_cooldown: config.scale_up_cooldown,
+            scale_down_cooldown: config.scale_down_cooldown,
+            history_size: config.history_size,
+            dry_run: config.dry_run,
+            executor_retry_secs: config.executor_retry_secs,
+        }
+    }
 }
 
 impl Default for ScalingConfig {
@@ -46,10 +118,38 @@ impl Default for ScalingConfig {
             scale_up_factor: 1.5,      // Increase capacity by 50%
             scale_down_factor: 0.5,    // Decrease capacity by 50%
             cooldown_period: 300,      // 5 minutes cooldown
+            scale_up_cooldown: None,
+            scale_down_cooldown: None,
+            history_size: default_history_size(),
+            dry_run: false,
+            executor_retry_secs: default_executor_retry_secs(),
         }
     }
 }
 
+/// Load averaged across every instance that has reported metrics
+#[derive(Debug, Clone, Serialize)]
+pub struct AggregateMetrics {
+    pub avg_cpu: f32,
+    pub avg_memory: f32,
+    pub avg_connections: u64,
+    pub instance_count: u64,
+    /// What the thresholds call for right now, ignoring the cooldown
+    pub recommendation: Option<ScalingAction>,
+}
+
+/// A scaling decision and the load that prompted it
+#[derive(Debug, Clone, Serialize)]
+pub struct ScalingEvent {
+    pub at: DateTime<Utc>,
+    pub action: ScalingAction,
+    pub avg_cpu: f32,
+    pub avg_memory: f32,
+    pub avg_connections: u64,
+    /// Decided in dry-run mode, so never carried out
+    pub dry_run: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InstanceInfo {
     pub id: Uuid,
@@ -58,12 +158,49 @@ pub struct InstanceInfo {
     pub started_at: DateTime<Utc>,
     pub last_heartbeat: DateTime<Utc>,
     pub metrics: Option<SystemMetrics>,
+    /// Set ahead of a scale-down: existing connections finish, new ones go elsewhere
+    #[serde(default)]
+    pub draining: bool,
+}
+
+impl InstanceInfo {
+    fn connection_count(&self) -> u64 {
+        self.metrics.as_ref().map_or(0, |m| m.connection_count)
+    }
 }
 
 pub struct ScalingManager {
     config: Arc<RwLock<ScalingConfig>>,
     instances: Arc<RwLock<HashMap<Uuid, InstanceInfo>>>,
-    last_scaling_action: Arc<RwLock<Option<DateTime<Utc>>>>,
+    last_scaling_action: Arc<RwLock<Option<LastScalingAction>>>,
+    history: Arc<RwLock<VecDeque<ScalingEvent>>>,
+    executor: Arc<dyn ScalingExecutor>,
+    /// Executor failures since the last success, holding off retries
+    executor_failures: Arc<RwLock<Option<ExecutorFailures>>>,
+    /// Scale-down waiting for its drained instance's connections to close
+    pending_scale_down: Arc<RwLock<Option<PendingScaleDown>>>,
+}
+
+/// When the last scaling action was decided, and which way it went
+#[derive(Debug, Clone)]
+struct LastScalingAction {
+    at: DateTime<Utc>,
+    action: ScalingAction,
+}
+
+/// Consecutive executor failures and when the latest one happened
+#[derive(Debug, Clone)]
+struct ExecutorFailures {
+    at: DateTime<Utc>,
+    count: u32,
+}
+
+/// A scale-down whose instance is draining, and the load that prompted it
+#[derive(Debug, Clone)]
+struct PendingScaleDown {
+    instance_id: Uuid,
+    action: ScalingAction,
+    metrics: AggregateMetrics,
 }
 
 impl ScalingManager {
@@ -72,13 +209,36 @@ impl ScalingManager {
             config: Arc::new(RwLock::new(config)),
             instances: Arc::new(RwLock::new(HashMap::new())),
             last_scaling_action: Arc::new(RwLock::new(None)),
+            history: Arc::new(RwLock::new(VecDeque::new())),
+            executor: Arc::new(LoggingExecutor),
+            executor_failures: Arc::new(RwLock::new(None)),
+            pending_scale_down: Arc::new(RwLock::new(None)),
         }
     }
 
+    /// Carry out scaling decisions with `executor` instead of just logging them
+    pub fn with_executor(mut self, executor: Arc<dyn ScalingExecutor>) -> Self {
+        self.executor = executor;
+        self
+    }
+
     pub async fn register_instance(&self, host: String, port: u16) -> Uuid {
-        let instance_id = Uuid::new_v4();
+        self.register_instance_with_id(Uuid::new_v4(), host, port).await
+    }
+
+    /// Register under a known id, such as the configured `server.instance_id`.
+    /// Registering an id that is already known just refreshes its heartbeat.
+    pub async fn register_instance_with_id(&self, instance_id: Uuid, host: String, port: u16) -> Uuid {
         let now = Utc::now();
-        
+        let mut instances = self.instances.write().await;
+
+        if let Some(instance) = instances.get_mut(&instance_id) {
+            instance.host = host;
+            instance.port = port;
+            instance.last_heartbeat = now;
+            return instance_id;
+        }
+
         let instance = InstanceInfo {
             id: instance_id,
             host,
@@ -86,14 +246,25 @@ impl ScalingManager {
             started_at: now,
             last_heartbeat: now,
             metrics: None,
+            draining: false,
         };
 
-        self.instances.write().await.insert(instance_id, instance);
+        instances.insert(instance_id, instance);
         info!("Registered new instance: {}", instance_id);
         
         instance_id
     }
 
+    /// Forget `instance_id`, as an instance does for itself on shutdown.
+    /// Returns whether it was registered.
+    pub async fn deregister_instance(&self, instance_id: Uuid) -> bool {
+        let removed = self.instances.write().await.remove(&instance_id).is_some();
+        if removed {
+            info!("Deregistered instance: {}", instance_id);
+        }
+        removed
+    }
+
     pub async fn update_instance_metrics(&self, instance_id: Uuid, metrics: SystemMetrics) -> Result<(), String> {
         let mut instances = self.instances.write().await;
         
@@ -106,19 +277,270 @@ impl ScalingManager {
         }
     }
 
+    /// Apply metrics reported for several instances under a single lock. Each
+    /// update succeeds or fails on its own; results come back in input order.
+    pub async fn update_instances_metrics(&self, updates: Vec<(Uuid, SystemMetrics)>) -> Vec<Result<(), String>> {
+        let mut instances = self.instances.write().await;
+        let now = Utc::now();
+
+        updates
+            .into_iter()
+            .map(|(instance_id, metrics)| {
+                metrics.validate()?;
+                let instance = instances.get_mut(&instance_id).ok_or_else(|| "Instance not found".to_string())?;
+                instance.metrics = Some(metrics);
+                instance.last_heartbeat = now;
+                Ok(())
+            })
+            .collect()
+    }
+
+    /// Pick the least-loaded instance for a new connection, skipping draining ones
+    pub async fn select_instance(&self) -> Option<InstanceInfo> {
+        self.instances.read().await
+            .values()
+            .filter(|instance| !instance.draining)
+            .min_by_key(|instance| instance.connection_count())
+            .cloned()
+    }
+
+    pub async fn mark_draining(&self, instance_id: Uuid) -> Result<(), String> {
+        let mut instances = self.instances.write().await;
+
+        if let Some(instance) = instances.get_mut(&instance_id) {
+            instance.draining = true;
+            info!("Instance {} is draining", instance_id);
+            Ok(())
+        } else {
+            Err("Instance not found".to_string())
+        }
+    }
+
+    /// Put a draining instance back into service, e.g. after its scale-down failed
+    pub async fn undrain(&self, instance_id: Uuid) -> Result<(), String> {
+        let mut instances = self.instances.write().await;
+
+        if let Some(instance) = instances.get_mut(&instance_id) {
+            instance.draining = false;
+            info!("Instance {} is serving again", instance_id);
+            Ok(())
+        } else {
+            Err("Instance not found".to_string())
+        }
+    }
+
+    /// Start a scale-down by draining the least-loaded instance.
+    /// The last instance still taking connections is never drained.
+    pub async fn drain_least_loaded(&self) -> Option<Uuid> {
+        let candidate = {
+            let instances = self.instances.read().await;
+            let serving: Vec<&InstanceInfo> = instances.values().filter(|i| !i.draining).collect();
+            if serving.len() < 2 {
+                return None;
+            }
+            serving.into_iter().min_by_key(|i| i.connection_count())?.id
+        };
+
+        self.mark_draining(candidate).await.ok()?;
+        Some(candidate)
+    }
+
+    /// Drop draining instances whose connections have all closed. An instance
+    /// drained for a scale-down is only dropped once the executor has removed
+    /// it; if the executor fails, the instance goes back into service.
+    pub async fn remove_drained_instances(&self) -> Vec<Uuid> {
+        let (drained, pending) = {
+            let instances = self.instances.read().await;
+            let mut pending = self.pending_scale_down.write().await;
+            // Forget a scale-down whose instance went away or was undrained
+            if pending.as_ref().is_some_and(|p| !instances.get(&p.instance_id).is_some_and(|i| i.draining)) {
+                *pending = None;
+            }
+
+            let drained: Vec<Uuid> = instances.values()
+                .filter(|i| i.draining && i.connection_count() == 0)
+                .map(|i| i.id)
+                .collect();
+            let pending = pending.take_if(|p| drained.contains(&p.instance_id));
+            (drained, pending)
+        };
+
+        let mut removed = drained;
+        if let Some(pending) = pending {
+            let id = pending.instance_id;
+            if let Err(e) = self.executor.execute(pending.action.clone(), Some(id)).await {
+                warn!("Failed to execute {:?} on instance {}: {}", pending.action, id, e);
+                let _ = self.undrain(id).await;
+                self.record_executor_failure().await;
+                removed.retain(|removed| *removed != id);
+            } else {
+                *self.executor_failures.write().await = None;
+                self.record_action(&pending.action, &pending.metrics).await;
+            }
+        }
+
+        let mut instances = self.instances.write().await;
+        removed.retain(|id| instances.remove(id).is_some());
+        for id in &removed {
+            info!("Removed drained instance: {}", id);
+        }
+        removed
+    }
+
     pub async fn check_scaling_needs(&self) -> Option<ScalingAction> {
+        let (action, metrics) = self.pending_action().await?;
+        self.record_action(&action, &metrics).await;
+        Some(action)
+    }
+
+    /// Decide whether to scale and act on it. In dry-run mode the decision is
+    /// only recorded and logged. Otherwise a scale-down drains the
+    /// least-loaded instance, so new connections go elsewhere, and leaves the
+    /// executor to [`remove_drained_instances`](Self::remove_drained_instances)
+    /// once that instance has no connections left; no further scale-down
+    /// starts meanwhile. Only once the executor succeeds is the action
+    /// recorded, starting the cooldown. A failure puts the drained instance
+    /// back into service and holds off the retry for `executor_retry_secs`,
+    /// doubling with each failure in a row.
+    pub async fn apply_scaling(&self) -> Option<ScalingAction> {
+        let (action, metrics) = self.pending_action().await?;
+
+        if self.config.read().await.dry_run {
+            info!("Dry run: would take scaling action {:?}", action);
+            self.record_action(&action, &metrics).await;
+            return Some(action);
+        }
+
+        if let Some(wait) = self.executor_retry_wait().await {
+            info!("Deferring {:?}: executor failed, retrying in {}s", action, wait);
+            return None;
+        }
+
+        if let ScalingAction::ScaleDown(_) = action {
+            if let Some(pending) = self.pending_scale_down.read().await.as_ref() {
+                info!("Deferring {:?}: instance {} is still draining", action, pending.instance_id);
+                return None;
+            }
+        }
+
+        info!("Scaling action required: {:?}", action);
+        if let ScalingAction::ScaleDown(_) = action {
+            if let Some(id) = self.drain_least_loaded().await {
+                info!("Draining instance {} for scale-down", id);
+                *self.pending_scale_down.write().await = Some(PendingScaleDown {
+                    instance_id: id,
+                    action: action.clone(),
+                    metrics,
+                });
+                return Some(action);
+            }
+        }
+
+        if let Err(e) = self.executor.execute(action.clone(), None).await {
+            warn!("Failed to execute {:?}: {}", action, e);
+            self.record_executor_failure().await;
+            return None;
+        }
+
+        *self.executor_failures.write().await = None;
+        self.record_action(&action, &metrics).await;
+        Some(action)
+    }
+
+    /// Count one more executor failure in a row, restarting the retry delay
+    async fn record_executor_failure(&self) {
+        let mut failures = self.executor_failures.write().await;
+        let count = failures.as_ref().map_or(0, |f| f.count) + 1;
+        *failures = Some(ExecutorFailures { at: Utc::now(), count });
+    }
+
+    /// Seconds left before the executor may be retried after failing, if any
+    async fn executor_retry_wait(&self) -> Option<i64> {
+        let failures = self.executor_failures.read().await;
+        let failures = failures.as_ref()?;
+        let base = self.config.read().await.executor_retry_secs;
+        let delay = base.saturating_mul(1 << (failures.count - 1).min(MAX_RETRY_DOUBLINGS));
+        let wait = delay - (Utc::now() - failures.at).num_seconds();
+        (wait > 0).then_some(wait)
+    }
+
+    /// The action the thresholds call for, unless still in cooldown
+    async fn pending_action(&self) -> Option<(ScalingAction, AggregateMetrics)> {
         let config = self.config.read().await;
         let instances = self.instances.read().await;
         let last_action = self.last_scaling_action.read().await;
 
-        // Check cooldown period
-        if let Some(last_time) = *last_action {
-            if (Utc::now() - last_time).num_seconds() < config.cooldown_period {
+        let metrics = Self::average_metrics(&instances, &config)?;
+        let action = metrics.recommendation.clone()?;
+
+        if let Some(last) = last_action.as_ref() {
+            if Self::in_cooldown(last, &action, &config) {
+                let elapsed = (Utc::now() - last.at).num_seconds();
+                info!("Deferring {:?}: {}s since last {:?}", action, elapsed, last.action);
                 return None;
             }
         }
 
-        // Calculate aggregate metrics
+        Some((action, metrics))
+    }
+
+    /// Start the cooldown for `action` and add it to the history
+    async fn record_action(&self, action: &ScalingAction, metrics: &AggregateMetrics) {
+        let config = self.config.read().await;
+        let now = Utc::now();
+        *self.last_scaling_action.write().await = Some(LastScalingAction { at: now, action: action.clone() });
+
+        let mut history = self.history.write().await;
+        history.push_back(ScalingEvent {
+            at: now,
+            action: action.clone(),
+            avg_cpu: metrics.avg_cpu,
+            avg_memory: metrics.avg_memory,
+            avg_connections: metrics.avg_connections,
+            dry_run: config.dry_run,
+        });
+        while history.len() > config.history_size {
+            history.pop_front();
+        }
+    }
+
+    /// Each direction has its own cooldown, measured from the last action of either kind
+    fn in_cooldown(last: &LastScalingAction, action: &ScalingAction, config: &ScalingConfig) -> bool {
+        (Utc::now() - last.at).num_seconds() < config.cooldown_for(action)
+    }
+
+    /// What `check_scaling_needs` would decide right now, without recording it.
+    /// Only takes read locks, so external autoscalers can poll it cheaply.
+    pub async fn recommendation(&self) -> ScalingRecommendation {
+        let config = self.config.read().await;
+        let instances = self.instances.read().await;
+        let last_action = self.last_scaling_action.read().await;
+
+        let action = Self::average_metrics(&instances, &config)
+            .and_then(|metrics| metrics.recommendation)
+            .filter(|action| {
+                last_action.as_ref().is_none_or(|last| !Self::in_cooldown(last, action, &config))
+            });
+        ScalingRecommendation::from(action)
+    }
+
+    /// Past scaling decisions, oldest first
+    pub async fn scaling_history(&self) -> Vec<ScalingEvent> {
+        self.history.read().await.iter().cloned().collect()
+    }
+
+    /// Cluster-wide averages and the scaling recommendation they imply.
+    /// `None` until at least one instance has reported metrics.
+    pub async fn aggregate_metrics(&self) -> Option<AggregateMetrics> {
+        let config = self.config.read().await;
+        let instances = self.instances.read().await;
+        Self::average_metrics(&instances, &config)
+    }
+
+    fn average_metrics(
+        instances: &HashMap<Uuid, InstanceInfo>,
+        config: &ScalingConfig,
+    ) -> Option<AggregateMetrics> {
         let mut total_cpu = 0.0;
         let mut total_memory = 0.0;
         let mut total_connections = 0;
@@ -126,6 +548,11 @@ impl ScalingManager {
 
         for instance in instances.values() {
             if let Some(metrics) = &instance.metrics {
+                // A zero total would turn the memory percentage into NaN/inf and poison the averages
+                if metrics.memory_total == 0 {
+                    warn!("Ignoring metrics from instance {}: memory_total is 0", instance.id);
+                    continue;
+                }
                 total_cpu += metrics.cpu_usage;
                 total_memory += (metrics.memory_used as f32 / metrics.memory_total as f32) * 100.0;
                 total_connections += metrics.connection_count;
@@ -142,17 +569,25 @@ impl ScalingManager {
         let avg_connections = total_connections / active_instances;
 
         // Determine if scaling is needed
-        if avg_cpu > config.cpu_threshold || 
-           avg_memory > config.memory_threshold || 
+        let recommendation = if avg_cpu > config.cpu_threshold ||
+           avg_memory > config.memory_threshold ||
            avg_connections > config.connection_threshold {
             Some(ScalingAction::ScaleUp(config.scale_up_factor))
-        } else if avg_cpu < config.cpu_threshold * 0.5 && 
-                  avg_memory < config.memory_threshold * 0.5 && 
+        } else if avg_cpu < config.cpu_threshold * 0.5 &&
+                  avg_memory < config.memory_threshold * 0.5 &&
                   (avg_connections as f32) < (config.connection_threshold as f32) * 0.5 {
             Some(ScalingAction::ScaleDown(config.scale_down_factor))
         } else {
             None
-        }
+        };
+
+        Some(AggregateMetrics {
+            avg_cpu,
+            avg_memory,
+            avg_connections,
+            instance_count: active_instances,
+            recommendation,
+        })
     }
 
     pub async fn cleanup_inactive_instances(&self) {
@@ -179,12 +614,40 @@ impl ScalingManager {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ScalingAction {
     ScaleUp(f32),
     ScaleDown(f32),
 }
 
+/// Direction of a scaling recommendation, including "leave it alone"
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecommendedAction {
+    ScaleUp,
+    ScaleDown,
+    Hold,
+}
+
+/// Scaling signal for external orchestrators: capacity should be multiplied
+/// by `factor`, which is 1.0 when holding
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScalingRecommendation {
+    pub action: RecommendedAction,
+    pub factor: f32,
+}
+
+impl From<Option<ScalingAction>> for ScalingRecommendation {
+    fn from(action: Option<ScalingAction>) -> Self {
+        match action {
+            Some(ScalingAction::ScaleUp(factor)) => Self { action: RecommendedAction::ScaleUp, factor },
+            Some(ScalingAction::ScaleDown(factor)) => Self { action: RecommendedAction::ScaleDown, factor },
+            None => Self { action: RecommendedAction::Hold, factor: 1.0 },
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -204,8 +667,26 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_scaling_decision() {
+    async fn test_register_with_known_id() {
         let manager = ScalingManager::new(ScalingConfig::default());
+        let id = Uuid::new_v4();
+
+        assert_eq!(manager.register_instance_with_id(id, "localhost".to_string(), 8080).await, id);
+        let started_at = manager.get_active_instances().await[0].started_at;
+
+        // Registering again keeps the original entry
+        manager.register_instance_with_id(id, "localhost".to_string(), 8080).await;
+        let instances = manager.get_active_instances().await;
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].started_at, started_at);
+    }
+
+    #[tokio::test]
+    async fn test_scaling_decision() {
+        let manager = ScalingManager::new(ScalingConfig {
+            cooldown_period: 1,
+            ..ScalingConfig::default()
+        });
         let instance_id = manager.register_instance("localhost".to_string(), 8080).await;
         
         // Test scale up condition
@@ -276,4 +757,358 @@ mod tests {
         // Verify instance was removed
         assert_eq!(manager.get_instance_count().await, 0, "Instance should be removed after cleanup");
     }
+
+    fn metrics(cpu_usage: f32, memory_used: u64, connection_count: u64) -> SystemMetrics {
+        SystemMetrics {
+            cpu_usage,
+            memory_used,
+            memory_total: 10000,
+            connection_count,
+            active_users: 0,
+            request_rate: 0.0,
+            error_rate: 0.0,
+            response_time_p95: 0.0,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_metrics() {
+        let manager = ScalingManager::new(ScalingConfig::default());
+        assert!(manager.aggregate_metrics().await.is_none());
+
+        let first = manager.register_instance("localhost".to_string(), 8080).await;
+        let second = manager.register_instance("localhost".to_string(), 8081).await;
+        // Instances that haven't reported yet don't drag the averages down
+        manager.register_instance("localhost".to_string(), 8082).await;
+
+        manager.update_instance_metrics(first, metrics(90.0, 7000, 1500)).await.unwrap();
+        manager.update_instance_metrics(second, metrics(50.0, 5000, 500)).await.unwrap();
+
+        let aggregate = manager.aggregate_metrics().await.unwrap();
+        assert_eq!(aggregate.instance_count, 2);
+        assert_eq!(aggregate.avg_cpu, 70.0);
+        assert_eq!(aggregate.avg_memory, 60.0);
+        assert_eq!(aggregate.avg_connections, 1000);
+        assert_eq!(aggregate.recommendation, None);
+
+        manager.update_instance_metrics(second, metrics(80.0, 5000, 500)).await.unwrap();
+        let aggregate = manager.aggregate_metrics().await.unwrap();
+        assert_eq!(aggregate.avg_cpu, 85.0);
+        assert_eq!(aggregate.recommendation, Some(ScalingAction::ScaleUp(1.5)));
+    }
+
+    #[tokio::test]
+    async fn test_split_cooldowns() {
+        let manager = ScalingManager::new(ScalingConfig {
+            scale_up_cooldown: Some(600),
+            scale_down_cooldown: Some(0),
+            ..ScalingConfig::default()
+        });
+        let instance_id = manager.register_instance("localhost".to_string(), 8080).await;
+
+        manager.update_instance_metrics(instance_id, metrics(90.0, 5000, 100)).await.unwrap();
+        assert_eq!(manager.check_scaling_needs().await, Some(ScalingAction::ScaleUp(1.5)));
+
+        // Still hot, but another scale-up waits out its cooldown
+        assert_eq!(manager.check_scaling_needs().await, None);
+
+        // Load dropped: scale-down has its own (zero) cooldown and may proceed
+        manager.update_instance_metrics(instance_id, metrics(10.0, 1000, 10)).await.unwrap();
+        assert_eq!(manager.check_scaling_needs().await, Some(ScalingAction::ScaleDown(0.5)));
+    }
+
+    #[tokio::test]
+    async fn test_recommendation() {
+        let manager = ScalingManager::new(ScalingConfig::default());
+        let hold = ScalingRecommendation { action: RecommendedAction::Hold, factor: 1.0 };
+        assert_eq!(manager.recommendation().await, hold);
+
+        let instance_id = manager.register_instance("localhost".to_string(), 8080).await;
+        manager.update_instance_metrics(instance_id, metrics(90.0, 5000, 100)).await.unwrap();
+        let scale_up = ScalingRecommendation { action: RecommendedAction::ScaleUp, factor: 1.5 };
+        assert_eq!(manager.recommendation().await, scale_up);
+
+        // Asking doesn't count as acting, so the recommendation is stable...
+        assert_eq!(manager.recommendation().await, scale_up);
+        assert!(manager.scaling_history().await.is_empty());
+
+        // ...until an action is taken and its cooldown applies
+        manager.check_scaling_needs().await.unwrap();
+        assert_eq!(manager.recommendation().await, hold);
+    }
+
+    #[test]
+    fn test_cooldown_fallback() {
+        let config = ScalingConfig {
+            cooldown_period: 120,
+            scale_up_cooldown: Some(30),
+            ..ScalingConfig::default()
+        };
+        assert_eq!(config.cooldown_for(&ScalingAction::ScaleUp(1.5)), 30);
+        assert_eq!(config.cooldown_for(&ScalingAction::ScaleDown(0.5)), 120);
+    }
+
+    #[tokio::test]
+    async fn test_scaling_history() {
+        let manager = ScalingManager::new(ScalingConfig {
+            cooldown_period: 0,
+            history_size: 2,
+            ..ScalingConfig::default()
+        });
+        let instance_id = manager.register_instance("localhost".to_string(), 8080).await;
+        assert!(manager.scaling_history().await.is_empty());
+
+        manager.update_instance_metrics(instance_id, metrics(90.0, 5000, 1200)).await.unwrap();
+        manager.check_scaling_needs().await.unwrap();
+
+        let history = manager.scaling_history().await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].action, ScalingAction::ScaleUp(1.5));
+        assert_eq!(history[0].avg_cpu, 90.0);
+        assert_eq!(history[0].avg_memory, 50.0);
+        assert_eq!(history[0].avg_connections, 1200);
+
+        // Only the most recent `history_size` events are kept
+        manager.update_instance_metrics(instance_id, metrics(10.0, 1000, 10)).await.unwrap();
+        manager.check_scaling_needs().await.unwrap();
+        manager.check_scaling_needs().await.unwrap();
+
+        let history = manager.scaling_history().await;
+        assert_eq!(history.len(), 2);
+        assert!(history.iter().all(|e| e.action == ScalingAction::ScaleDown(0.5)));
+    }
+
+    /// Records every action it is asked to take, failing the first `failures` of them
+    #[derive(Default)]
+    struct RecordingExecutor {
+        calls: std::sync::Mutex<Vec<(ScalingAction, Option<Uuid>)>>,
+        failures: std::sync::Mutex<usize>,
+    }
+
+    impl RecordingExecutor {
+        fn failing(failures: usize) -> Self {
+            Self { failures: std::sync::Mutex::new(failures), ..Self::default() }
+        }
+
+        fn calls(&self) -> Vec<ScalingAction> {
+            self.calls.lock().unwrap().iter().map(|(action, _)| action.clone()).collect()
+        }
+
+        fn instance_ids(&self) -> Vec<Option<Uuid>> {
+            self.calls.lock().unwrap().iter().map(|(_, id)| *id).collect()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ScalingExecutor for RecordingExecutor {
+        async fn execute(&self, action: ScalingAction, instance_id: Option<Uuid>) -> Result<(), crate::error::AppError> {
+            self.calls.lock().unwrap().push((action, instance_id));
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(crate::error::AppError::InternalError("orchestrator unavailable".into()));
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_records_without_executing() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let manager = ScalingManager::new(ScalingConfig {
+            cooldown_period: 0,
+            dry_run: true,
+            ..ScalingConfig::default()
+        })
+        .with_executor(executor.clone());
+        for port in [8080, 8081] {
+            let instance_id = manager.register_instance("localhost".to_string(), port).await;
+            manager.update_instance_metrics(instance_id, metrics(10.0, 1000, 10)).await.unwrap();
+        }
+        let draining = |instances: Vec<InstanceInfo>| instances.iter().filter(|i| i.draining).count();
+
+        assert_eq!(manager.apply_scaling().await, Some(ScalingAction::ScaleDown(0.5)));
+        let history = manager.scaling_history().await;
+        assert_eq!(history.len(), 1);
+        assert!(history[0].dry_run);
+        assert!(executor.calls().is_empty());
+        // Nor is the scale-down's drain carried out
+        assert_eq!(draining(manager.get_active_instances().await), 0);
+
+        // Outside dry-run mode the executor acts on the decision once the
+        // drained instance is idle
+        manager.config.write().await.dry_run = false;
+        manager.apply_scaling().await.unwrap();
+        assert_eq!(draining(manager.get_active_instances().await), 1);
+        let drained = manager.get_active_instances().await.into_iter().find(|i| i.draining).unwrap().id;
+        manager.update_instance_metrics(drained, metrics(1.0, 1000, 0)).await.unwrap();
+        manager.remove_drained_instances().await;
+        assert!(!manager.scaling_history().await[1].dry_run);
+        assert_eq!(executor.calls(), vec![ScalingAction::ScaleDown(0.5)]);
+    }
+
+    #[tokio::test]
+    async fn test_cooldown_starts_after_successful_execution() {
+        let executor = Arc::new(RecordingExecutor::failing(1));
+        let manager = ScalingManager::new(ScalingConfig {
+            executor_retry_secs: 0,
+            ..ScalingConfig::default()
+        })
+        .with_executor(executor.clone());
+        let instance_id = manager.register_instance("localhost".to_string(), 8080).await;
+        manager.update_instance_metrics(instance_id, metrics(90.0, 5000, 1200)).await.unwrap();
+
+        // The failed attempt is neither recorded nor put in cooldown, so it is
+        // retried once the (here zero) retry delay passes
+        assert_eq!(manager.apply_scaling().await, None);
+        assert!(manager.scaling_history().await.is_empty());
+        assert_eq!(manager.apply_scaling().await, Some(ScalingAction::ScaleUp(1.5)));
+        assert_eq!(manager.scaling_history().await.len(), 1);
+
+        // Once it succeeds the cooldown holds off the next one
+        assert_eq!(manager.apply_scaling().await, None);
+        assert_eq!(executor.calls(), vec![ScalingAction::ScaleUp(1.5); 2]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_scale_down_undrains_and_backs_off() {
+        let executor = Arc::new(RecordingExecutor::failing(1));
+        let manager = ScalingManager::new(ScalingConfig { cooldown_period: 0, ..ScalingConfig::default() })
+            .with_executor(executor.clone());
+        for port in [8080, 8081] {
+            let instance_id = manager.register_instance("localhost".to_string(), port).await;
+            manager.update_instance_metrics(instance_id, metrics(10.0, 1000, 10)).await.unwrap();
+        }
+        let draining = |instances: Vec<InstanceInfo>| instances.iter().filter(|i| i.draining).count();
+
+        // The drained instance goes back into service when the executor fails
+        assert_eq!(manager.apply_scaling().await, Some(ScalingAction::ScaleDown(0.5)));
+        let drained = manager.get_active_instances().await.into_iter().find(|i| i.draining).unwrap().id;
+        manager.update_instance_metrics(drained, metrics(10.0, 1000, 0)).await.unwrap();
+        assert!(manager.remove_drained_instances().await.is_empty());
+        assert_eq!(draining(manager.get_active_instances().await), 0);
+        assert_eq!(manager.get_instance_count().await, 2);
+        assert!(manager.scaling_history().await.is_empty());
+
+        // and the executor isn't called again until the retry delay has passed
+        assert_eq!(manager.apply_scaling().await, None);
+        assert_eq!(executor.calls().len(), 1);
+
+        manager.executor_failures.write().await.as_mut().unwrap().at -= chrono::Duration::seconds(60);
+        assert_eq!(manager.apply_scaling().await, Some(ScalingAction::ScaleDown(0.5)));
+        assert_eq!(draining(manager.get_active_instances().await), 1);
+        assert_eq!(manager.remove_drained_instances().await, vec![drained]);
+        assert!(manager.executor_failures.read().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scale_down_waits_for_drained_connections() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let manager = ScalingManager::new(ScalingConfig { cooldown_period: 0, ..ScalingConfig::default() })
+            .with_executor(executor.clone());
+        for port in [8080, 8081, 8082] {
+            let instance_id = manager.register_instance("localhost".to_string(), port).await;
+            manager.update_instance_metrics(instance_id, metrics(10.0, 1000, 10)).await.unwrap();
+        }
+
+        assert_eq!(manager.apply_scaling().await, Some(ScalingAction::ScaleDown(0.5)));
+        let drained = manager.get_active_instances().await.into_iter().find(|i| i.draining).unwrap().id;
+
+        // Connections are still open: nothing is removed and no other
+        // instance is drained in the meantime
+        assert!(manager.remove_drained_instances().await.is_empty());
+        assert_eq!(manager.apply_scaling().await, None);
+        assert!(executor.calls().is_empty());
+        assert_eq!(manager.get_active_instances().await.iter().filter(|i| i.draining).count(), 1);
+        assert!(manager.scaling_history().await.is_empty());
+
+        // Once idle, the executor is told which instance to remove
+        manager.update_instance_metrics(drained, metrics(1.0, 1000, 0)).await.unwrap();
+        assert_eq!(manager.remove_drained_instances().await, vec![drained]);
+        assert_eq!(executor.calls(), vec![ScalingAction::ScaleDown(0.5)]);
+        assert_eq!(executor.instance_ids(), vec![Some(drained)]);
+        assert_eq!(manager.get_instance_count().await, 2);
+        assert_eq!(manager.scaling_history().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_batch_metrics_update() {
+        let manager = ScalingManager::new(ScalingConfig::default());
+        let known = manager.register_instance("localhost".to_string(), 8080).await;
+        let other = manager.register_instance("localhost".to_string(), 8081).await;
+
+        let results = manager.update_instances_metrics(vec![
+            (known, metrics(50.0, 5000, 10)),
+            (Uuid::new_v4(), metrics(50.0, 5000, 10)),
+            (other, metrics(150.0, 5000, 10)),
+        ]).await;
+        assert!(results[0].is_ok());
+        assert_eq!(results[1], Err("Instance not found".to_string()));
+        assert!(results[2].as_ref().unwrap_err().contains("cpu_usage"));
+
+        let instances = manager.get_active_instances().await;
+        let reported = |id| instances.iter().find(|i| i.id == id).unwrap().metrics.is_some();
+        assert!(reported(known));
+        assert!(!reported(other));
+    }
+
+    #[tokio::test]
+    async fn test_zero_memory_total_ignored() {
+        let manager = ScalingManager::new(ScalingConfig::default());
+        let healthy = manager.register_instance("localhost".to_string(), 8080).await;
+        let broken = manager.register_instance("localhost".to_string(), 8081).await;
+
+        manager.update_instance_metrics(healthy, metrics(40.0, 5000, 600)).await.unwrap();
+        let mut bad = metrics(99.0, 5000, 5000);
+        bad.memory_total = 0;
+        manager.update_instance_metrics(broken, bad).await.unwrap();
+
+        let aggregate = manager.aggregate_metrics().await.unwrap();
+        assert_eq!(aggregate.instance_count, 1);
+        assert_eq!(aggregate.avg_cpu, 40.0);
+        assert_eq!(aggregate.avg_memory, 50.0);
+        assert_eq!(aggregate.avg_connections, 600);
+        assert_eq!(manager.check_scaling_needs().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_draining_instance_not_selected() {
+        let manager = ScalingManager::new(ScalingConfig::default());
+        let idle = manager.register_instance("localhost".to_string(), 8080).await;
+        let busy = manager.register_instance("localhost".to_string(), 8081).await;
+
+        manager.update_instance_metrics(idle, metrics(10.0, 1000, 5)).await.unwrap();
+        manager.update_instance_metrics(busy, metrics(50.0, 5000, 500)).await.unwrap();
+        assert_eq!(manager.select_instance().await.unwrap().id, idle);
+
+        manager.mark_draining(idle).await.unwrap();
+        assert_eq!(manager.select_instance().await.unwrap().id, busy);
+
+        // Still listed until its connections drain
+        let instances = manager.get_active_instances().await;
+        assert_eq!(instances.len(), 2);
+        assert!(instances.iter().any(|i| i.id == idle && i.draining));
+        assert!(manager.mark_draining(Uuid::new_v4()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_drain_and_remove() {
+        let manager = ScalingManager::new(ScalingConfig::default());
+        let idle = manager.register_instance("localhost".to_string(), 8080).await;
+        let busy = manager.register_instance("localhost".to_string(), 8081).await;
+        manager.update_instance_metrics(idle, metrics(10.0, 1000, 5)).await.unwrap();
+        manager.update_instance_metrics(busy, metrics(50.0, 5000, 500)).await.unwrap();
+
+        assert_eq!(manager.drain_least_loaded().await, Some(idle));
+        // The remaining instance is the last one serving, so it is never drained
+        assert_eq!(manager.drain_least_loaded().await, None);
+
+        // Connections are still open, so the instance stays
+        assert!(manager.remove_drained_instances().await.is_empty());
+
+        manager.update_instance_metrics(idle, metrics(1.0, 1000, 0)).await.unwrap();
+        assert_eq!(manager.remove_drained_instances().await, vec![idle]);
+        assert_eq!(manager.get_instance_count().await, 1);
+    }
 }
@@ -0,0 +1,145 @@
+//! Tracing setup for BuddyBot server
+//!
+//! Logs always go to stdout. When `telemetry.enabled` is set, spans are also
+//! exported over OTLP/HTTP so requests can be followed across services.
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::TracerProvider;
+use opentelemetry_sdk::{runtime, Resource};
+use tracing::{field, Instrument, Level};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::FmtSubscriber;
+
+use crate::config::TelemetryConfig;
+use crate::error::AppError;
+
+/// Build a tracer provider that batches spans to the configured OTLP endpoint
+pub fn otlp_tracer_provider(config: &TelemetryConfig) -> Result<TracerProvider, AppError> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&config.endpoint)
+        .build()
+        .map_err(|e| AppError::ConfigError(format!("Invalid telemetry exporter: {}", e)))?;
+
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            config.service_name.clone(),
+        )]))
+        .build())
+}
+
+/// Install the global subscriber. Returns the tracer provider when spans are
+/// being exported, so the caller can flush it on shutdown.
+pub fn init_tracing(config: &TelemetryConfig) -> Result<Option<TracerProvider>, AppError> {
+    if !config.enabled {
+        FmtSubscriber::builder()
+            .with_max_level(Level::INFO)
+            .with_target(false)
+            .with_thread_ids(true)
+            .with_file(true)
+            .with_line_number(true)
+            .pretty()
+            .init();
+        return Ok(None);
+    }
+
+    let provider = otlp_tracer_provider(config)?;
+    let tracer = provider.tracer(config.service_name.clone());
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::filter::LevelFilter::INFO)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_thread_ids(true)
+                .with_file(true)
+                .with_line_number(true)
+                .pretty(),
+        )
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .init();
+
+    Ok(Some(provider))
+}
+
+/// Middleware that wraps each HTTP request in a server span
+pub async fn trace_request(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let route = req.match_pattern().unwrap_or_else(|| req.path().to_string());
+    let span = tracing::info_span!(
+        "http.request",
+        otel.name = %format!("{} {}", req.method(), route),
+        otel.kind = "server",
+        http.method = %req.method(),
+        http.route = %route,
+        http.status_code = field::Empty,
+    );
+
+    let response = next.call(req).instrument(span.clone()).await?;
+    span.record("http.status_code", response.status().as_u16());
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, web, App, HttpResponse};
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+    use std::sync::Arc;
+
+    use crate::config::ProxyConfig;
+    use crate::proxy::{EchoProvider, ProxyService};
+
+    async fn ask(proxy: web::Data<ProxyService>) -> HttpResponse {
+        let answer = proxy.query("hello", &[]).await.unwrap();
+        HttpResponse::Ok().body(answer)
+    }
+
+    #[actix_web::test]
+    async fn test_request_spans_exported() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let proxy = ProxyService::new(Arc::new(EchoProvider), &ProxyConfig::default());
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(trace_request))
+                .app_data(web::Data::new(proxy))
+                .route("/ask", web::get().to(ask)),
+        )
+        .await;
+
+        let response = test::TestRequest::get().uri("/ask").send_request(&app).await;
+        assert!(response.status().is_success());
+
+        provider.force_flush();
+        let spans = exporter.get_finished_spans().unwrap();
+
+        let request = spans.iter().find(|s| s.name == "GET /ask").expect("no request span");
+        assert!(request.attributes.iter().any(|kv| kv.key.as_str() == "http.status_code"));
+
+        let call = spans.iter().find(|s| s.name == "proxy.provider").expect("no provider span");
+        assert_eq!(call.parent_span_id, request.span_context.span_id());
+        let provider_attr = call.attributes.iter().find(|kv| kv.key.as_str() == "provider").unwrap();
+        assert_eq!(provider_attr.value.as_str(), "echo");
+        assert!(call.attributes.iter().any(|kv| kv.key.as_str() == "latency_ms"));
+    }
+}
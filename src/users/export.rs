@@ -0,0 +1,140 @@
+use actix_web::web::Bytes;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream, StreamExt};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::db::{AuthAuditEvent, ConversationMessage, DbOperations, User, UserSession, UserSetting};
+use crate::error::Error;
+
+/// Audit events read per query while gathering an export
+const AUDIT_PAGE_SIZE: i64 = 500;
+
+/// A session as exported. The token itself is never included.
+#[derive(Debug, Serialize)]
+pub struct ExportedSession {
+    pub id: Uuid,
+    /// Last four characters of the token, enough to tell sessions apart
+    pub token_hint: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_activity: DateTime<Utc>,
+}
+
+impl From<&UserSession> for ExportedSession {
+    fn from(session: &UserSession) -> Self {
+        let skip = session.token.chars().count().saturating_sub(4);
+        Self {
+            id: session.id,
+            token_hint: format!("…{}", session.token.chars().skip(skip).collect::<String>()),
+            created_at: session.created_at,
+            expires_at: session.expires_at,
+            last_activity: session.last_activity,
+        }
+    }
+}
+
+/// When the user's stored provider key was saved; the key is never included
+#[derive(Debug, Serialize)]
+pub struct ExportedApiKey {
+    /// Unix seconds
+    pub created_at: u64,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportedConversation {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub messages: Vec<ConversationMessage>,
+}
+
+/// Everything in the export except conversations, which follow as a stream
+#[derive(Serialize)]
+struct ExportHead<'a> {
+    exported_at: DateTime<Utc>,
+    user: &'a User,
+    sessions: Vec<ExportedSession>,
+    settings: Vec<UserSetting>,
+    auth_events: Vec<AuthAuditEvent>,
+    api_key: Option<ExportedApiKey>,
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, Error> {
+    serde_json::to_string(value).map_err(|e| Error::External(format!("Failed to serialize export: {}", e)))
+}
+
+async fn all_auth_events(db: &DbOperations, user_id: Uuid) -> Result<Vec<AuthAuditEvent>, Error> {
+    let mut events = Vec::new();
+    loop {
+        let page = db.list_auth_events(Some(user_id), AUDIT_PAGE_SIZE, events.len() as i64).await?;
+        let last_page = (page.len() as i64) < AUDIT_PAGE_SIZE;
+        events.extend(page);
+        if last_page {
+            return Ok(events);
+        }
+    }
+}
+
+/// `user`'s data as one JSON document. The profile, sessions, settings and
+/// audit history are read up front, so failures there surface as an error
+/// response; conversations are then read and sent one at a time, keeping
+/// memory flat however much history the user has.
+pub async fn export_user_data(
+    db: DbOperations,
+    user: User,
+) -> Result<impl Stream<Item = Result<Bytes, Error>> + 'static, Error> {
+    let sessions = db.list_sessions(user.id).await?;
+    let api_key = db.get_user_api_key(user.id).await?;
+    let head = ExportHead {
+        exported_at: Utc::now(),
+        user: &user,
+        sessions: sessions.iter().map(ExportedSession::from).collect(),
+        settings: db.list_settings(user.id).await?,
+        auth_events: all_auth_events(&db, user.id).await?,
+        api_key: api_key.map(|key| ExportedApiKey { created_at: key.created_at, expires_at: key.expires_at }),
+    };
+
+    // Reopen the head object to append the conversations array
+    let mut head = to_json(&head)?;
+    head.pop();
+    head.push_str(r#","conversations":["#);
+
+    let conversations = db.list_conversations(user.id).await?;
+    let user_id = user.id;
+    let body = stream::iter(conversations.into_iter().enumerate()).then(move |(index, conversation)| {
+        let db = db.clone();
+        async move {
+            let messages = db.list_messages(conversation.id, user_id).await?;
+            let exported = ExportedConversation {
+                id: conversation.id,
+                created_at: conversation.created_at,
+                updated_at: conversation.updated_at,
+                messages,
+            };
+            let separator = if index == 0 { "" } else { "," };
+            Ok(Bytes::from(format!("{}{}", separator, to_json(&exported)?)))
+        }
+    });
+
+    Ok(stream::once(async move { Ok(Bytes::from(head)) })
+        .chain(body)
+        .chain(stream::once(async { Ok(Bytes::from_static(b"]}")) })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_token_redacted() {
+        let session = UserSession::new(Uuid::new_v4(), "secret-token-abcd".to_string(), 1);
+        let exported = ExportedSession::from(&session);
+        assert_eq!(exported.token_hint, "…abcd");
+        assert!(!to_json(&exported).unwrap().contains("secret"));
+
+        let short = UserSession::new(Uuid::new_v4(), "ab".to_string(), 1);
+        assert_eq!(ExportedSession::from(&short).token_hint, "…ab");
+    }
+}
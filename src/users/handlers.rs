@@ -0,0 +1,135 @@
+use actix_web::http::header::{self, ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpRequest, HttpResponse};
+
+use crate::auth::middleware::require_user;
+use crate::error::{Error, FieldError};
+use crate::users::export::export_user_data;
+use crate::AppState;
+use tracing::info;
+
+/// Largest serialized setting value accepted, in bytes
+pub const MAX_SETTING_VALUE_BYTES: usize = 4 * 1024;
+/// Most distinct setting keys a single user may store
+pub const MAX_SETTINGS_PER_USER: i64 = 50;
+const MAX_SETTING_KEY_CHARS: usize = 64;
+
+/// Keys are short identifiers: ASCII letters, digits, `_`, `-` and `.`
+fn validate_setting_key(key: &str) -> Result<(), Error> {
+    let valid = !key.is_empty()
+        && key.len() <= MAX_SETTING_KEY_CHARS
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::Validation(vec![FieldError::new(
+            "key",
+            &format!("Setting keys must be 1-{} characters of letters, digits, '_', '-' or '.'", MAX_SETTING_KEY_CHARS),
+        )]))
+    }
+}
+
+/// Fetch one of the caller's settings
+pub async fn get_setting(
+    req: HttpRequest,
+    key: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    let user = require_user(&req, &state).await?;
+    validate_setting_key(&key)?;
+
+    let value = state.db.get_setting(user.id, &key).await?
+        .ok_or_else(|| Error::NotFound(format!("Setting '{}' not found", key)))?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "key": key.into_inner(),
+        "value": value
+    })))
+}
+
+/// Create or replace one of the caller's settings; the request body is the value
+pub async fn put_setting(
+    req: HttpRequest,
+    key: web::Path<String>,
+    value: web::Json<serde_json::Value>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    state.maintenance.ensure_writable()?;
+    let user = require_user(&req, &state).await?;
+    validate_setting_key(&key)?;
+
+    let value = value.into_inner();
+    if value.to_string().len() > MAX_SETTING_VALUE_BYTES {
+        return Err(Error::Validation(vec![FieldError::new(
+            "value",
+            &format!("Setting values must be at most {} bytes", MAX_SETTING_VALUE_BYTES),
+        )]));
+    }
+
+    if !state.db.set_setting(user.id, &key, &value, MAX_SETTINGS_PER_USER).await? {
+        return Err(Error::Validation(vec![FieldError::new(
+            "key",
+            &format!("At most {} settings may be stored per user", MAX_SETTINGS_PER_USER),
+        )]));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "key": key.into_inner(),
+        "value": value
+    })))
+}
+
+/// Download everything stored about the caller as a single JSON document.
+/// Session tokens and the stored provider key are redacted.
+pub async fn export_data(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    let user = require_user(&req, &state).await?;
+    let body = export_user_data(state.db.clone(), user).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(header::ContentType::json())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("buddybot-export.json".to_string())],
+        })
+        .streaming(body))
+}
+
+/// Permanently erase the caller's account and everything stored for it,
+/// then drop any of their live WebSocket connections
+pub async fn purge_account(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    state.maintenance.ensure_writable()?;
+    let user = require_user(&req, &state).await?;
+
+    let summary = state.db.purge_user(user.id).await?;
+    let connections_closed = state.ws_server.pool().disconnect_user(&user.id).await;
+
+    info!(
+        user_id = %user.id,
+        conversations = summary.conversations,
+        messages = summary.messages,
+        sessions = summary.sessions,
+        connections_closed,
+        "Purged user account"
+    );
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "id": user.id,
+        "deleted": summary,
+        "connections_closed": connections_closed,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setting_key_validation() {
+        assert!(validate_setting_key("theme").is_ok());
+        assert!(validate_setting_key("editor.font-size_2").is_ok());
+
+        assert!(validate_setting_key("").is_err());
+        assert!(validate_setting_key("has space").is_err());
+        assert!(validate_setting_key("ünïcode").is_err());
+        assert!(validate_setting_key(&"k".repeat(MAX_SETTING_KEY_CHARS + 1)).is_err());
+    }
+}
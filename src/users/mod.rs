@@ -0,0 +1,10 @@
+//! User-facing account endpoints for BuddyBot server
+//!
+//! Everything here operates on the authenticated caller (`/users/me/...`).
+
+pub mod export;
+pub mod handlers;
+pub mod stats;
+
+pub use handlers::{export_data, get_setting, purge_account, put_setting};
+pub use stats::{PendingQueries, QueryStats, StatsStore};
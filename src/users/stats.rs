@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+use tracing::{debug, instrument};
+use uuid::Uuid;
+
+use crate::db::DbOperations;
+use crate::error::Error;
+
+/// Where batched query counts are written
+#[async_trait]
+pub trait StatsStore: Send + Sync {
+    /// Add `counts[i]` queries, the last at `last_query_at[i]`, to `user_ids[i]`'s total
+    async fn add_query_counts(
+        &self,
+        user_ids: &[Uuid],
+        counts: &[i64],
+        last_query_at: &[DateTime<Utc>],
+    ) -> Result<u64, Error>;
+}
+
+#[async_trait]
+impl StatsStore for DbOperations {
+    async fn add_query_counts(
+        &self,
+        user_ids: &[Uuid],
+        counts: &[i64],
+        last_query_at: &[DateTime<Utc>],
+    ) -> Result<u64, Error> {
+        self.add_user_query_counts(user_ids, counts, last_query_at).await
+    }
+}
+
+/// Queries a user has made since the last flush
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingQueries {
+    pub count: i64,
+    pub last_at: DateTime<Utc>,
+}
+
+/// Counts each user's queries in memory and adds them to `user_stats` in
+/// batches, so answering a query costs no extra write. Stored totals lag by
+/// up to one flush interval; [`QueryStats::pending`] covers the difference.
+pub struct QueryStats {
+    store: Arc<dyn StatsStore>,
+    pending: Mutex<HashMap<Uuid, PendingQueries>>,
+}
+
+impl QueryStats {
+    pub fn new(store: Arc<dyn StatsStore>) -> Self {
+        Self {
+            store,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count one answered query for `user_id`
+    pub async fn record_query(&self, user_id: Uuid) {
+        let now = Utc::now();
+        self.pending.lock().await
+            .entry(user_id)
+            .and_modify(|p| {
+                p.count += 1;
+                p.last_at = now;
+            })
+            .or_insert(PendingQueries { count: 1, last_at: now });
+    }
+
+    /// Queries counted for `user_id` that have not been written yet
+    pub async fn pending(&self, user_id: Uuid) -> Option<PendingQueries> {
+        self.pending.lock().await.get(&user_id).copied()
+    }
+
+    /// Write all pending counts in one statement, returning how many users
+    /// were flushed. On failure the counts are kept for the next flush.
+    #[instrument(skip_all)]
+    pub async fn flush(&self) -> Result<usize, Error> {
+        let batch = std::mem::take(&mut *self.pending.lock().await);
+        if batch.is_empty() {
+            return Ok(0);
+        }
+
+        let mut user_ids = Vec::with_capacity(batch.len());
+        let mut counts = Vec::with_capacity(batch.len());
+        let mut last_query_at = Vec::with_capacity(batch.len());
+        for (user_id, pending) in &batch {
+            user_ids.push(*user_id);
+            counts.push(pending.count);
+            last_query_at.push(pending.last_at);
+        }
+
+        if let Err(e) = self.store.add_query_counts(&user_ids, &counts, &last_query_at).await {
+            // Merge with anything counted since the batch was taken
+            let mut pending = self.pending.lock().await;
+            for (user_id, failed) in batch {
+                pending.entry(user_id)
+                    .and_modify(|p| p.count += failed.count)
+                    .or_insert(failed);
+            }
+            return Err(e);
+        }
+
+        debug!("Flushed query counts for {} users", user_ids.len());
+        Ok(user_ids.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    #[derive(Default)]
+    struct CountingStore {
+        writes: AtomicUsize,
+        fail: AtomicBool,
+    }
+
+    #[async_trait]
+    impl StatsStore for CountingStore {
+        async fn add_query_counts(
+            &self,
+            user_ids: &[Uuid],
+            _counts: &[i64],
+            _last_query_at: &[DateTime<Utc>],
+        ) -> Result<u64, Error> {
+            self.writes.fetch_add(1, Ordering::SeqCst);
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(Error::External("store unavailable".into()));
+            }
+            Ok(user_ids.len() as u64)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_keeps_counts() {
+        let store = Arc::new(CountingStore::default());
+        let stats = QueryStats::new(store.clone());
+        let user_id = Uuid::new_v4();
+
+        stats.record_query(user_id).await;
+        stats.record_query(user_id).await;
+        store.fail.store(true, Ordering::SeqCst);
+        assert!(stats.flush().await.is_err());
+
+        // Queries counted after the failed flush add to the kept ones
+        stats.record_query(user_id).await;
+        assert_eq!(stats.pending(user_id).await.map(|p| p.count), Some(3));
+
+        store.fail.store(false, Ordering::SeqCst);
+        assert_eq!(stats.flush().await.unwrap(), 1);
+        assert_eq!(stats.pending(user_id).await, None);
+        assert_eq!(stats.flush().await.unwrap(), 0);
+        assert_eq!(store.writes.load(Ordering::SeqCst), 2);
+    }
+}
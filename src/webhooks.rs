@@ -0,0 +1,295 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Semaphore};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::config::WebhookConfig;
+use crate::error::AppError;
+
+/// Event names sent in the payload's `event` field and the `X-BuddyBot-Event` header
+pub const EVENT_USER_REGISTERED: &str = "user.registered";
+pub const EVENT_AUTH_LOGIN: &str = "auth.login";
+
+/// `sha256=<hex HMAC of the body>`, keyed with `webhooks.secret`
+pub const SIGNATURE_HEADER: &str = "X-BuddyBot-Signature";
+pub const EVENT_HEADER: &str = "X-BuddyBot-Event";
+
+/// Events waiting for the delivery worker before new ones are dropped
+const QUEUE_CAPACITY: usize = 1024;
+
+/// Deliveries, including their retries, in flight at once. While all are
+/// busy the queue backs up instead of piling on more retrying tasks.
+const MAX_CONCURRENT_DELIVERIES: usize = 8;
+
+/// Body POSTed to the webhook endpoint
+#[derive(Debug, Serialize)]
+pub struct WebhookPayload {
+    /// Unique per event, so receivers can ignore redelivered retries
+    pub id: Uuid,
+    pub event: String,
+    /// Unix seconds
+    pub created_at: i64,
+    pub data: serde_json::Value,
+}
+
+struct Endpoint {
+    client: reqwest::Client,
+    url: String,
+    secret: String,
+    max_attempts: u32,
+    retry_backoff: Duration,
+}
+
+/// Notifies the configured endpoint of user and session events. Events are
+/// queued and delivered by a background task, so a slow or failing endpoint
+/// never holds up the request that triggered them.
+#[derive(Default)]
+pub struct Webhooks {
+    queue: Option<mpsc::Sender<WebhookPayload>>,
+}
+
+impl Webhooks {
+    /// Drops every event
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Start the delivery worker, unless `webhooks.url` is unset. A URL
+    /// without a non-empty `webhooks.secret` is refused, since payloads would
+    /// go out with a signature anyone can compute. Must be called from within
+    /// the Tokio runtime.
+    pub fn from_config(config: &WebhookConfig) -> Result<Self, AppError> {
+        let Some(url) = config.url.clone() else {
+            return Ok(Self::disabled());
+        };
+        let secret = config.secret.clone()
+            .filter(|secret| !secret.is_empty())
+            .ok_or_else(|| AppError::ConfigError("webhooks.secret must be set when webhooks.url is".into()))?;
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()
+            .map_err(|e| AppError::ConfigError(format!("Failed to build the webhook client: {}", e)))?;
+        let endpoint = Arc::new(Endpoint {
+            client,
+            url,
+            secret,
+            max_attempts: config.max_attempts.max(1),
+            retry_backoff: Duration::from_millis(config.retry_backoff_ms),
+        });
+
+        let (tx, mut rx) = mpsc::channel::<WebhookPayload>(QUEUE_CAPACITY);
+        let deliveries = Arc::new(Semaphore::new(MAX_CONCURRENT_DELIVERIES));
+        tokio::spawn(async move {
+            while let Some(payload) = rx.recv().await {
+                // Each delivery retries on its own, so one failing event
+                // doesn't hold back the ones queued behind it
+                let Ok(permit) = deliveries.clone().acquire_owned().await else {
+                    return;
+                };
+                let endpoint = endpoint.clone();
+                tokio::spawn(async move {
+                    endpoint.deliver(payload).await;
+                    drop(permit);
+                });
+            }
+        });
+
+        Ok(Self { queue: Some(tx) })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.queue.is_some()
+    }
+
+    /// Queue `event` for delivery. Never blocks; if the queue is full the event is dropped.
+    pub fn emit(&self, event: &str, data: serde_json::Value) {
+        let Some(queue) = &self.queue else {
+            return;
+        };
+
+        let payload = WebhookPayload {
+            id: Uuid::new_v4(),
+            event: event.to_string(),
+            created_at: Utc::now().timestamp(),
+            data,
+        };
+        if let Err(e) = queue.try_send(payload) {
+            warn!("Dropping {} webhook: {}", event, e);
+        }
+    }
+}
+
+impl Endpoint {
+    async fn deliver(self: Arc<Self>, payload: WebhookPayload) {
+        let body = match serde_json::to_vec(&payload) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("Failed to serialize {} webhook: {}", payload.event, e);
+                return;
+            }
+        };
+        let signature = sign(&self.secret, &body);
+
+        let mut backoff = self.retry_backoff;
+        for attempt in 1..=self.max_attempts {
+            let result = self.client
+                .post(&self.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, &payload.event)
+                .header(SIGNATURE_HEADER, &signature)
+                .body(body.clone())
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+
+            match result {
+                Ok(_) => {
+                    info!("Delivered {} webhook {}", payload.event, payload.id);
+                    return;
+                }
+                Err(e) if attempt < self.max_attempts => {
+                    warn!("{} webhook attempt {} failed, retrying in {:?}: {}", payload.event, attempt, backoff, e);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => {
+                    warn!("Giving up on {} webhook {} after {} attempts: {}", payload.event, payload.id, attempt, e);
+                }
+            }
+        }
+    }
+}
+
+/// Value of the signature header for `body`: `sha256=` and the hex-encoded
+/// HMAC-SHA256 of the raw body under `secret`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+
+    let mut signature = String::from("sha256=");
+    for byte in mac.finalize().into_bytes() {
+        let _ = write!(signature, "{:02x}", byte);
+    }
+    signature
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header_exists, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn config(url: String) -> WebhookConfig {
+        WebhookConfig {
+            url: Some(url),
+            secret: Some("hook_secret".to_string()),
+            max_attempts: 3,
+            retry_backoff_ms: 10,
+            ..WebhookConfig::default()
+        }
+    }
+
+    async fn wait_for_requests(server: &MockServer, count: usize) -> Vec<wiremock::Request> {
+        for _ in 0..100 {
+            let requests = server.received_requests().await.unwrap();
+            if requests.len() >= count {
+                return requests;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        server.received_requests().await.unwrap()
+    }
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_delivered() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(header_exists(SIGNATURE_HEADER))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+
+        let webhooks = Webhooks::from_config(&config(server.uri())).unwrap();
+        webhooks.emit(EVENT_AUTH_LOGIN, serde_json::json!({ "user_id": Uuid::nil() }));
+
+        let requests = wait_for_requests(&server, 3).await;
+        assert_eq!(requests.len(), 3);
+        // Every attempt carries the same event
+        assert!(requests.iter().all(|r| r.body == requests[0].body));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(server.received_requests().await.unwrap().len(), 3, "Retried after success");
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&server)
+            .await;
+
+        let webhooks = Webhooks::from_config(&config(server.uri())).unwrap();
+        webhooks.emit(EVENT_AUTH_LOGIN, serde_json::json!({}));
+
+        wait_for_requests(&server, 3).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_deliveries_capped() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(204).set_delay(Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+
+        // With every delivery stuck on the slow endpoint, the rest wait in the queue
+        let webhooks = Webhooks::from_config(&config(server.uri())).unwrap();
+        for _ in 0..MAX_CONCURRENT_DELIVERIES * 2 {
+            webhooks.emit(EVENT_AUTH_LOGIN, serde_json::json!({}));
+        }
+
+        wait_for_requests(&server, MAX_CONCURRENT_DELIVERIES).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(server.received_requests().await.unwrap().len(), MAX_CONCURRENT_DELIVERIES);
+    }
+
+    #[test]
+    fn test_disabled_without_url() {
+        let webhooks = Webhooks::from_config(&WebhookConfig::default()).unwrap();
+        assert!(!webhooks.is_enabled());
+        webhooks.emit(EVENT_USER_REGISTERED, serde_json::json!({}));
+    }
+
+    #[test]
+    fn test_secret_required_with_url() {
+        for secret in [None, Some(String::new())] {
+            let config = WebhookConfig { secret, ..config("http://localhost:9".to_string()) };
+            assert!(matches!(Webhooks::from_config(&config), Err(AppError::ConfigError(_))));
+        }
+    }
+}
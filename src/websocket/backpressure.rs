@@ -0,0 +1,136 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::config::WebSocketConfig;
+use crate::scaling::AggregateMetrics;
+
+/// Decides when queries should be turned away with `ServerMessage::Busy`
+/// instead of being queued behind work the server can't keep up with.
+/// Load is shed while the cluster's average CPU is over the threshold, or
+/// while too many queries are already in flight on this instance.
+pub struct Backpressure {
+    cpu_threshold: f32,
+    max_in_flight: usize,
+    retry_after_ms: u64,
+    in_flight: AtomicUsize,
+    overloaded: AtomicBool,
+}
+
+impl Backpressure {
+    pub fn new(config: &WebSocketConfig) -> Self {
+        Self {
+            cpu_threshold: config.busy_cpu_threshold,
+            max_in_flight: config.max_in_flight_queries,
+            retry_after_ms: config.busy_retry_after_ms,
+            in_flight: AtomicUsize::new(0),
+            overloaded: AtomicBool::new(false),
+        }
+    }
+
+    /// Never sheds load
+    pub fn disabled() -> Self {
+        Self {
+            cpu_threshold: f32::INFINITY,
+            max_in_flight: 0,
+            retry_after_ms: 0,
+            in_flight: AtomicUsize::new(0),
+            overloaded: AtomicBool::new(false),
+        }
+    }
+
+    /// Update the overload flag from the latest cluster metrics
+    pub fn observe(&self, metrics: Option<&AggregateMetrics>) {
+        let overloaded = metrics.is_some_and(|m| m.avg_cpu >= self.cpu_threshold);
+        if self.overloaded.swap(overloaded, Ordering::Relaxed) != overloaded {
+            if overloaded {
+                warn!("Cluster CPU is over {}%, deferring new queries", self.cpu_threshold);
+            } else {
+                info!("Cluster load is back under threshold, accepting queries");
+            }
+        }
+    }
+
+    /// Reserve a slot for a query, or return how many milliseconds the client
+    /// should wait before retrying. The slot is released when the permit drops.
+    pub fn try_admit(self: &Arc<Self>) -> Result<QueryPermit, u64> {
+        if self.overloaded.load(Ordering::Relaxed) {
+            return Err(self.retry_after_ms);
+        }
+
+        let previous = self.in_flight.fetch_add(1, Ordering::AcqRel);
+        if self.max_in_flight > 0 && previous >= self.max_in_flight {
+            self.in_flight.fetch_sub(1, Ordering::AcqRel);
+            return Err(self.retry_after_ms);
+        }
+
+        Ok(QueryPermit(self.clone()))
+    }
+
+    /// Queries currently holding a permit
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+}
+
+/// A query admitted by [`Backpressure::try_admit`]
+pub struct QueryPermit(Arc<Backpressure>);
+
+impl Drop for QueryPermit {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(avg_cpu: f32) -> AggregateMetrics {
+        AggregateMetrics {
+            avg_cpu,
+            avg_memory: 10.0,
+            avg_connections: 1,
+            instance_count: 1,
+            recommendation: None,
+        }
+    }
+
+    #[test]
+    fn test_in_flight_limit() {
+        let config = WebSocketConfig { max_in_flight_queries: 2, busy_retry_after_ms: 250, ..WebSocketConfig::default() };
+        let backpressure = Arc::new(Backpressure::new(&config));
+
+        let first = backpressure.try_admit().unwrap();
+        let _second = backpressure.try_admit().unwrap();
+        assert_eq!(backpressure.try_admit().err(), Some(250));
+        assert_eq!(backpressure.in_flight(), 2);
+
+        drop(first);
+        assert!(backpressure.try_admit().is_ok());
+    }
+
+    #[test]
+    fn test_sheds_while_cluster_overloaded() {
+        let config = WebSocketConfig { busy_cpu_threshold: 90.0, ..WebSocketConfig::default() };
+        let backpressure = Arc::new(Backpressure::new(&config));
+
+        backpressure.observe(Some(&metrics(95.0)));
+        assert!(backpressure.try_admit().is_err());
+
+        backpressure.observe(Some(&metrics(50.0)));
+        assert!(backpressure.try_admit().is_ok());
+
+        // No metrics reported yet is not a reason to refuse work
+        backpressure.observe(None);
+        assert!(backpressure.try_admit().is_ok());
+    }
+
+    #[test]
+    fn test_disabled_never_sheds() {
+        let backpressure = Arc::new(Backpressure::disabled());
+        backpressure.observe(Some(&metrics(100.0)));
+        let permits: Vec<_> = (0..1000).map(|_| backpressure.try_admit().unwrap()).collect();
+        assert_eq!(backpressure.in_flight(), permits.len());
+    }
+}
@@ -0,0 +1,12 @@
+use uuid::Uuid;
+
+/// How many events a slow subscriber may fall behind before it starts missing them
+pub const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// Lifecycle events for WebSocket connections, published on the server's event bus
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    Connected { connection_id: Uuid },
+    Authenticated { connection_id: Uuid, user_id: Uuid },
+    Disconnected { connection_id: Uuid },
+}
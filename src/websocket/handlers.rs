@@ -0,0 +1,27 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::auth::middleware::require_admin;
+use crate::error::Error;
+use crate::AppState;
+
+/// Admin endpoint to force-disconnect a WebSocket connection
+pub async fn disconnect_connection(
+    req: HttpRequest,
+    path: web::Path<Uuid>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, Error> {
+    require_admin(&req, &state.config.admin)?;
+
+    let id = path.into_inner();
+    if !state.ws_server.pool().disconnect(&id).await {
+        return Err(Error::NotFound(format!("Connection {} not found", id)));
+    }
+
+    info!("Admin disconnected connection {}", id);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Connection disconnected",
+        "id": id
+    })))
+}
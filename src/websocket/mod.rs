@@ -6,10 +6,18 @@
 // Re-export public interfaces
 // Will be implemented in Phase 2
 
+mod backpressure;
 mod connection;
+mod events;
+mod outbox;
 mod pool;
 mod server;
+mod session;
+pub mod handlers;
 
-pub use connection::{Connection, ClientMessage, ServerMessage};
-pub use pool::ConnectionPool;
+pub use backpressure::{Backpressure, QueryPermit};
+pub use connection::{error_codes, process_query, response_chunks, Connection, ClientMessage, ServerMessage, STREAM_CHUNK_BYTES};
+pub use events::ConnectionEvent;
+pub use pool::{normalize_client_type, BroadcastResult, ConnectionPool, IpSlot};
 pub use server::WebSocketServer;
+pub use session::websocket_route;
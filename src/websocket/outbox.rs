@@ -0,0 +1,83 @@
+use chrono::{DateTime, Utc};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::db::DbOperations;
+use crate::error::Error;
+use crate::websocket::{ConnectionPool, ServerMessage};
+
+/// Most messages kept for an offline user; older ones are dropped first
+pub const MAX_OUTBOX_PER_USER: i64 = 100;
+/// How long a queued message waits for its user to reconnect
+pub const OUTBOX_TTL_DAYS: i64 = 7;
+/// How long a replay may hold messages before another connection may claim them
+pub const OUTBOX_CLAIM_TIMEOUT_SECS: i64 = 60;
+
+/// Queued messages created before this are considered expired
+pub fn outbox_cutoff() -> DateTime<Utc> {
+    Utc::now() - chrono::Duration::days(OUTBOX_TTL_DAYS)
+}
+
+/// Deliver `msg` to every live connection of `user_id`, or queue it for their next
+/// authenticated connection. Returns whether it was delivered immediately.
+pub async fn send_to_user(
+    pool: &ConnectionPool,
+    db: &DbOperations,
+    user_id: Uuid,
+    msg: &ServerMessage,
+) -> Result<bool, Error> {
+    let payload = serde_json::to_value(msg)
+        .map_err(|e| Error::External(format!("Failed to serialize message: {}", e)))?;
+
+    if pool.send_to_user(&user_id, &payload.to_string()).await > 0 {
+        return Ok(true);
+    }
+
+    db.enqueue_outbox(user_id, &payload, MAX_OUTBOX_PER_USER).await?;
+    info!("User {} is offline, queued message for later delivery", user_id);
+    Ok(false)
+}
+
+/// Tie an authenticated connection to its user and replay anything queued while
+/// they were offline. Messages are only removed from the outbox once sent, so
+/// delivery is at-least-once; any that cannot be sent are released for the next
+/// connection.
+pub async fn attach_user(pool: &ConnectionPool, db: &DbOperations, connection_id: Uuid, user_id: Uuid) {
+    pool.bind_user(connection_id, user_id).await;
+
+    let reclaim_before = Utc::now() - chrono::Duration::seconds(OUTBOX_CLAIM_TIMEOUT_SECS);
+    let queued = match db.drain_outbox_for_user(user_id, outbox_cutoff(), reclaim_before).await {
+        Ok(queued) => queued,
+        Err(e) => {
+            error!("Failed to load outbox for user {}: {}", user_id, e);
+            return;
+        }
+    };
+    if queued.is_empty() {
+        return;
+    }
+
+    info!("Replaying {} queued messages to connection {}", queued.len(), connection_id);
+    let mut delivered = Vec::with_capacity(queued.len());
+    let mut failed = Vec::new();
+    for (sent, message) in queued.iter().enumerate() {
+        if let Err(e) = pool.send_to(&connection_id, &message.payload.to_string()).await {
+            error!("Failed to replay outbox to connection {}: {}", connection_id, e);
+            failed.extend(queued[sent..].iter().map(|m| m.id));
+            break;
+        }
+        delivered.push(message.id);
+    }
+
+    // If acknowledging fails the claims lapse and the messages are sent again
+    if !delivered.is_empty() {
+        if let Err(e) = db.ack_outbox(&delivered).await {
+            error!("Failed to acknowledge outbox for user {}: {}", user_id, e);
+        }
+    }
+    if !failed.is_empty() {
+        if let Err(e) = db.release_outbox(&failed).await {
+            error!("Failed to release outbox for user {}: {}", user_id, e);
+        }
+    }
+}
@@ -1,21 +1,68 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
 use tokio::sync::{mpsc, RwLock};
 use tokio_tungstenite::tungstenite::Message;
+use serde::Serialize;
 use uuid::Uuid;
 use crate::error::Error;
 use tracing::{error, info};
 
+/// Longest client type a connection may declare
+const MAX_CLIENT_TYPE_CHARS: usize = 32;
+
+/// Client types are short identifiers like "mobile" or "cli", compared
+/// case-insensitively. `None` if `raw` isn't one.
+pub fn normalize_client_type(raw: &str) -> Option<String> {
+    let client_type = raw.trim().to_ascii_lowercase();
+    let valid = !client_type.is_empty()
+        && client_type.len() <= MAX_CLIENT_TYPE_CHARS
+        && client_type.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'));
+    valid.then_some(client_type)
+}
+
 #[derive(Debug)]
 pub struct ConnectionPool {
     connections: Arc<RwLock<HashMap<Uuid, mpsc::UnboundedSender<Message>>>>,
+    /// User each authenticated connection belongs to
+    users: Arc<RwLock<HashMap<Uuid, Uuid>>>,
+    /// Kind of client each connection declared itself as, e.g. "mobile"
+    client_types: Arc<RwLock<HashMap<Uuid, String>>>,
+    /// Open connections per client address, counted by [`IpSlot`]s
+    per_ip: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl Default for ConnectionPool {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ConnectionPool {
     pub fn new() -> Self {
         Self {
             connections: Arc::new(RwLock::new(HashMap::new())),
+            users: Arc::new(RwLock::new(HashMap::new())),
+            client_types: Arc::new(RwLock::new(HashMap::new())),
+            per_ip: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Reserve a connection slot for `ip`, or `None` if it already holds `cap`
+    /// connections. The slot is released when dropped.
+    pub fn try_reserve_ip(&self, ip: IpAddr, cap: usize) -> Option<IpSlot> {
+        let mut per_ip = self.per_ip.lock().unwrap();
+        let count = per_ip.entry(ip).or_default();
+        if *count >= cap {
+            return None;
         }
+        *count += 1;
+        Some(IpSlot { per_ip: self.per_ip.clone(), ip })
+    }
+
+    /// Connections currently holding a slot for `ip`
+    pub fn connections_from(&self, ip: IpAddr) -> usize {
+        self.per_ip.lock().unwrap().get(&ip).copied().unwrap_or(0)
     }
 
     pub async fn add(&self, id: Uuid, sender: mpsc::UnboundedSender<Message>) {
@@ -23,7 +70,24 @@ impl ConnectionPool {
         info!("Added connection {} to pool", id);
     }
 
+    /// Record that connection `id` has authenticated as `user_id`
+    pub async fn bind_user(&self, id: Uuid, user_id: Uuid) {
+        self.users.write().await.insert(id, user_id);
+    }
+
+    /// Tag connection `id` with the kind of client it declared. Returns false,
+    /// leaving any earlier tag, if `client_type` isn't a valid client type.
+    pub async fn set_client_type(&self, id: Uuid, client_type: &str) -> bool {
+        let Some(client_type) = normalize_client_type(client_type) else {
+            return false;
+        };
+        self.client_types.write().await.insert(id, client_type);
+        true
+    }
+
     pub async fn remove(&self, id: &Uuid) -> bool {
+        self.users.write().await.remove(id);
+        self.client_types.write().await.remove(id);
         let removed = self.connections.write().await.remove(id).is_some();
         if removed {
             info!("Removed connection {} from pool", id);
@@ -31,23 +95,95 @@ impl ConnectionPool {
         removed
     }
 
-    pub async fn broadcast(&self, msg: &str, exclude_id: Option<Uuid>) -> Result<(), Error> {
-        let connections = self.connections.read().await;
-        let message = Message::Text(msg.to_string());
-
-        for (id, sender) in connections.iter() {
-            if let Some(exclude) = exclude_id {
-                if *id == exclude {
-                    continue;
+    /// Force-close a connection: queue a close frame, then drop our sender so the
+    /// connection's forwarding task ends once the frame is flushed.
+    pub async fn disconnect(&self, id: &Uuid) -> bool {
+        self.users.write().await.remove(id);
+        self.client_types.write().await.remove(id);
+        match self.connections.write().await.remove(id) {
+            Some(sender) => {
+                if let Err(e) = sender.send(Message::Close(None)) {
+                    error!("Failed to send close frame to connection {}: {}", id, e);
                 }
+                info!("Disconnected connection {}", id);
+                true
             }
+            None => false,
+        }
+    }
 
-            if let Err(e) = sender.send(message.clone()) {
-                error!("Failed to broadcast to connection {}: {}", id, e);
+    /// Force-close every connection, returning how many were closed
+    pub async fn disconnect_all(&self) -> usize {
+        let ids = self.get_all_connection_ids().await;
+        let mut closed = 0;
+        for id in ids {
+            if self.disconnect(&id).await {
+                closed += 1;
             }
         }
+        closed
+    }
 
-        Ok(())
+    /// Force-close every connection of `user_id`, returning how many were closed
+    pub async fn disconnect_user(&self, user_id: &Uuid) -> usize {
+        let ids: Vec<Uuid> = self.users.read().await
+            .iter()
+            .filter(|(_, owner)| *owner == user_id)
+            .map(|(id, _)| *id)
+            .collect();
+
+        let mut closed = 0;
+        for id in ids {
+            if self.disconnect(&id).await {
+                closed += 1;
+            }
+        }
+        closed
+    }
+
+    /// Send `msg` to every connection except `exclude_id`. Connections whose
+    /// receiving end is gone are reported in `failed`, and removed from the
+    /// pool when `evict_failed` is set.
+    pub async fn broadcast(&self, msg: &str, exclude_id: Option<Uuid>, evict_failed: bool) -> BroadcastResult {
+        let result = self.send_matching(msg, |id| exclude_id != Some(*id)).await;
+
+        if evict_failed {
+            for id in &result.failed {
+                self.remove(id).await;
+            }
+        }
+        result
+    }
+
+    /// Send `msg` only to connections tagged with `client_type`
+    pub async fn broadcast_to_type(&self, client_type: &str, msg: &str) -> BroadcastResult {
+        let Some(client_type) = normalize_client_type(client_type) else {
+            return BroadcastResult::default();
+        };
+        let targets: HashSet<Uuid> = self.client_types.read().await
+            .iter()
+            .filter(|(_, tagged)| **tagged == client_type)
+            .map(|(id, _)| *id)
+            .collect();
+
+        self.send_matching(msg, |id| targets.contains(id)).await
+    }
+
+    async fn send_matching(&self, msg: &str, include: impl Fn(&Uuid) -> bool) -> BroadcastResult {
+        let mut result = BroadcastResult::default();
+        let connections = self.connections.read().await;
+        let message = Message::Text(msg.to_string());
+
+        for (id, sender) in connections.iter().filter(|(id, _)| include(id)) {
+            match sender.send(message.clone()) {
+                Ok(()) => result.delivered += 1,
+                Err(e) => {
+                    error!("Failed to broadcast to connection {}: {}", id, e);
+                    result.failed.push(*id);
+                }
+            }
+        }
+        result
     }
 
     pub async fn send_to(&self, id: &Uuid, msg: &str) -> Result<(), Error> {
@@ -76,16 +212,53 @@ impl ConnectionPool {
         Ok(())
     }
 
+    /// Send to every live connection of `user_id`, returning how many accepted it
+    pub async fn send_to_user(&self, user_id: &Uuid, msg: &str) -> usize {
+        let users = self.users.read().await;
+        let connections = self.connections.read().await;
+        let message = Message::Text(msg.to_string());
+
+        users.iter()
+            .filter(|(_, owner)| *owner == user_id)
+            .filter_map(|(id, _)| connections.get(id).map(|sender| (id, sender)))
+            .filter(|(id, sender)| match sender.send(message.clone()) {
+                Ok(()) => true,
+                Err(e) => {
+                    error!("Failed to send to connection {}: {}", id, e);
+                    false
+                }
+            })
+            .count()
+    }
+
+    /// Fan `msg` out to every live connection of each user in `user_ids`,
+    /// returning how many connections accepted it per user. Users without a
+    /// live connection are reported with a count of 0.
+    pub async fn send_to_users(&self, user_ids: &[Uuid], msg: &str) -> HashMap<Uuid, usize> {
+        let users = self.users.read().await;
+        let connections = self.connections.read().await;
+        let message = Message::Text(msg.to_string());
+
+        let mut delivered: HashMap<Uuid, usize> = user_ids.iter().map(|id| (*id, 0)).collect();
+        for (id, owner) in users.iter() {
+            let Some(count) = delivered.get_mut(owner) else { continue };
+            let Some(sender) = connections.get(id) else { continue };
+            match sender.send(message.clone()) {
+                Ok(()) => *count += 1,
+                Err(e) => error!("Failed to send to connection {}: {}", id, e),
+            }
+        }
+
+        delivered
+    }
+
     pub async fn connection_count(&self) -> usize {
         self.connections.read().await.len()
     }
 
-    pub async fn cleanup_inactive(&self, inactive_connections: &[Uuid]) {
-        let mut connections = self.connections.write().await;
-        for id in inactive_connections {
-            connections.remove(id);
-            info!("Removed inactive connection {}", id);
-        }
+    /// Authenticated connections `user_id` has open to this instance
+    pub async fn user_connection_count(&self, user_id: &Uuid) -> usize {
+        self.users.read().await.values().filter(|owner| *owner == user_id).count()
     }
 
     pub async fn get_all_connection_ids(&self) -> Vec<Uuid> {
@@ -93,11 +266,57 @@ impl ConnectionPool {
     }
 }
 
+/// Outcome of [`ConnectionPool::broadcast`]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct BroadcastResult {
+    /// Connections that accepted the message
+    pub delivered: usize,
+    /// Connections whose receiving end had already gone away
+    pub failed: Vec<Uuid>,
+}
+
+/// A connection counted against its client address by [`ConnectionPool::try_reserve_ip`]
+#[derive(Debug)]
+pub struct IpSlot {
+    per_ip: Arc<Mutex<HashMap<IpAddr, usize>>>,
+    ip: IpAddr,
+}
+
+impl Drop for IpSlot {
+    fn drop(&mut self) {
+        let mut per_ip = self.per_ip.lock().unwrap();
+        if let Some(count) = per_ip.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                per_ip.remove(&self.ip);
+            }
+        }
+    }
+}
+
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use tokio::sync::mpsc;
 
+    #[test]
+    fn test_ip_slots() {
+        let pool = ConnectionPool::new();
+        let ip: IpAddr = "203.0.113.7".parse().unwrap();
+
+        let first = pool.try_reserve_ip(ip, 2).unwrap();
+        let _second = pool.try_reserve_ip(ip, 2).unwrap();
+        assert!(pool.try_reserve_ip(ip, 2).is_none());
+        assert_eq!(pool.connections_from(ip), 2);
+        // Other addresses have their own count
+        assert!(pool.try_reserve_ip("203.0.113.8".parse().unwrap(), 2).is_some());
+
+        drop(first);
+        assert_eq!(pool.connections_from(ip), 1);
+        assert!(pool.try_reserve_ip(ip, 2).is_some());
+    }
+
     #[tokio::test]
     async fn test_connection_pool() {
         let pool = ConnectionPool::new();
@@ -113,15 +332,16 @@ mod tests {
         assert_eq!(pool.connection_count().await, 2);
 
         // Test broadcasting
-        pool.broadcast("test message", None).await.unwrap();
+        let result = pool.broadcast("test message", None, false).await;
+        assert_eq!(result, BroadcastResult { delivered: 2, failed: Vec::new() });
         
-        if let Some(Message::Text(msg)) = rx1.try_recv().ok() {
+        if let Ok(Message::Text(msg)) = rx1.try_recv() {
             assert_eq!(msg, "test message");
         } else {
             panic!("Failed to receive broadcast message on connection 1");
         }
 
-        if let Some(Message::Text(msg)) = rx2.try_recv().ok() {
+        if let Ok(Message::Text(msg)) = rx2.try_recv() {
             assert_eq!(msg, "test message");
         } else {
             panic!("Failed to receive broadcast message on connection 2");
@@ -133,10 +353,155 @@ mod tests {
 
         // Test sending to specific connection
         pool.send_to(&id2, "direct message").await.unwrap();
-        if let Some(Message::Text(msg)) = rx2.try_recv().ok() {
+        if let Ok(Message::Text(msg)) = rx2.try_recv() {
             assert_eq!(msg, "direct message");
         } else {
             panic!("Failed to receive direct message");
         }
     }
+
+    #[tokio::test]
+    async fn test_broadcast_reports_dead_connections() {
+        let pool = ConnectionPool::new();
+        let (live_tx, mut live_rx) = mpsc::unbounded_channel();
+        let (dead_tx, dead_rx) = mpsc::unbounded_channel();
+        let (excluded_tx, mut excluded_rx) = mpsc::unbounded_channel();
+        let (live, dead, excluded) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        pool.add(live, live_tx).await;
+        pool.add(dead, dead_tx).await;
+        pool.add(excluded, excluded_tx).await;
+        pool.bind_user(dead, Uuid::new_v4()).await;
+        drop(dead_rx);
+
+        // Without eviction the dead connection is reported but kept
+        let result = pool.broadcast("hello", Some(excluded), false).await;
+        assert_eq!(result, BroadcastResult { delivered: 1, failed: vec![dead] });
+        assert_eq!(pool.connection_count().await, 3);
+
+        let result = pool.broadcast("again", Some(excluded), true).await;
+        assert_eq!(result, BroadcastResult { delivered: 1, failed: vec![dead] });
+        assert_eq!(pool.connection_count().await, 2);
+        assert!(!pool.get_all_connection_ids().await.contains(&dead));
+
+        assert_eq!(std::iter::from_fn(|| live_rx.try_recv().ok()).count(), 2);
+        assert!(excluded_rx.try_recv().is_err());
+
+        // Once evicted it is no longer tried
+        let result = pool.broadcast("third", None, true).await;
+        assert_eq!(result, BroadcastResult { delivered: 2, failed: Vec::new() });
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_to_type() {
+        let pool = ConnectionPool::new();
+        let mut receivers = Vec::new();
+        for client_type in [Some("mobile"), Some("Mobile"), Some("web"), Some("cli"), None] {
+            let (tx, rx) = mpsc::unbounded_channel();
+            let id = Uuid::new_v4();
+            pool.add(id, tx).await;
+            if let Some(client_type) = client_type {
+                assert!(pool.set_client_type(id, client_type).await);
+            }
+            receivers.push((id, rx));
+        }
+        assert!(!pool.set_client_type(receivers[4].0, "not a type!").await);
+
+        let result = pool.broadcast_to_type("mobile", "update your app").await;
+        assert_eq!(result.delivered, 2);
+        let received: Vec<usize> = receivers.iter_mut()
+            .map(|(_, rx)| std::iter::from_fn(|| rx.try_recv().ok()).count())
+            .collect();
+        assert_eq!(received, vec![1, 1, 0, 0, 0]);
+
+        // Tags go with the connection
+        pool.remove(&receivers[0].0).await;
+        assert_eq!(pool.broadcast_to_type("MOBILE", "again").await.delivered, 1);
+        assert_eq!(pool.broadcast_to_type("tablet", "anyone?").await.delivered, 0);
+    }
+
+    #[tokio::test]
+    async fn test_send_to_user() {
+        let pool = ConnectionPool::new();
+        let user_id = Uuid::new_v4();
+        let (tx1, mut rx1) = mpsc::unbounded_channel();
+        let (tx2, mut rx2) = mpsc::unbounded_channel();
+        let (tx3, mut rx3) = mpsc::unbounded_channel();
+        let (id1, id2, id3) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        pool.add(id1, tx1).await;
+        pool.add(id2, tx2).await;
+        pool.add(id3, tx3).await;
+        pool.bind_user(id1, user_id).await;
+        pool.bind_user(id2, user_id).await;
+        pool.bind_user(id3, Uuid::new_v4()).await;
+        assert_eq!(pool.user_connection_count(&user_id).await, 2);
+
+        assert_eq!(pool.send_to_user(&user_id, "for you").await, 2);
+        assert!(matches!(rx1.try_recv(), Ok(Message::Text(msg)) if msg == "for you"));
+        assert!(matches!(rx2.try_recv(), Ok(Message::Text(msg)) if msg == "for you"));
+        assert!(rx3.try_recv().is_err());
+
+        // Connections stop receiving once they leave the pool
+        pool.remove(&id1).await;
+        pool.remove(&id2).await;
+        assert_eq!(pool.send_to_user(&user_id, "anyone?").await, 0);
+        assert_eq!(pool.user_connection_count(&user_id).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_send_to_users() {
+        let pool = ConnectionPool::new();
+        let (alice, bob, carol, offline) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let mut receivers = Vec::new();
+
+        for owner in [alice, alice, bob, carol] {
+            let (tx, rx) = mpsc::unbounded_channel();
+            let id = Uuid::new_v4();
+            pool.add(id, tx).await;
+            pool.bind_user(id, owner).await;
+            receivers.push(rx);
+        }
+
+        // Repeated user ids still get each connection only once
+        let delivered = pool.send_to_users(&[alice, bob, alice, offline], "fan out").await;
+        assert_eq!(delivered.len(), 3);
+        assert_eq!(delivered[&alice], 2);
+        assert_eq!(delivered[&bob], 1);
+        assert_eq!(delivered[&offline], 0);
+
+        let received: Vec<usize> = receivers.iter_mut()
+            .map(|rx| std::iter::from_fn(|| rx.try_recv().ok()).count())
+            .collect();
+        assert_eq!(received, vec![1, 1, 1, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect() {
+        let pool = ConnectionPool::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let id = Uuid::new_v4();
+        pool.add(id, tx).await;
+
+        // Stand-in for a connection's forwarding task
+        let receiver = tokio::spawn(async move {
+            let mut received = Vec::new();
+            while let Some(msg) = rx.recv().await {
+                received.push(msg);
+            }
+            received
+        });
+
+        assert!(pool.disconnect(&id).await);
+        assert_eq!(pool.connection_count().await, 0);
+
+        let received = tokio::time::timeout(std::time::Duration::from_secs(1), receiver)
+            .await
+            .expect("Receiver task should end after disconnect")
+            .unwrap();
+        assert!(matches!(received.last(), Some(Message::Close(_))));
+
+        // Unknown ids are reported as missing
+        assert!(!pool.disconnect(&id).await);
+    }
 } 
\ No newline at end of file
@@ -1,14 +1,14 @@
 use buddybot_server::{
-    auth::{AuthService, RateLimiter, RateLimitConfig},
-    db::DbOperations,
-    error::Error,
+    auth::{ActivityStore, AuthService, RateLimiter, RateLimitConfig, SessionLimitPolicy, SessionTokenType, AUDIT_LOGIN, AUDIT_REGISTER},
+    auth::password::{needs_rehash, PasswordParams},
+    db::{DbOperations, UserSession},
+    error::{AuthError, Error},
 };
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use uuid::Uuid;
-use wiremock::{
-    matchers::{method, path},
-    Mock, MockServer, ResponseTemplate,
-};
 
 async fn setup_test_db() -> PgPool {
     let database_url = std::env::var("DATABASE_URL")
@@ -31,12 +31,16 @@ async fn test_auth_flow() {
         "test_secret".to_string(),
     );
 
+    // Register a fresh user so the test can be re-run against the same database
+    let email = format!("test-{}@example.com", Uuid::new_v4());
+    auth_service.register(&email, "password123", None, "127.0.0.1").await.unwrap();
+
     // Test authentication flow
-    let token = auth_service.authenticate("test@example.com", "password123").await.unwrap();
+    let token = auth_service.authenticate(&email, "password123", "127.0.0.1").await.unwrap();
     
     // Validate token
     let user = auth_service.validate_token(&token).await.unwrap();
-    assert_eq!(user.email, "test@example.com");
+    assert_eq!(user.email, email);
 }
 
 #[tokio::test]
@@ -70,7 +74,205 @@ async fn test_invalid_token() {
     );
 
     match auth_service.validate_token("invalid_token").await {
-        Err(Error::Unauthorized(_)) => (),
-        _ => panic!("Expected unauthorized error"),
+        Err(Error::Auth(AuthError::InvalidToken)) => (),
+        _ => panic!("Expected invalid token error"),
+    }
+}
+
+#[tokio::test]
+async fn test_login_audit_events() {
+    let pool = setup_test_db().await;
+    let db = DbOperations::new(std::sync::Arc::new(pool));
+    let auth_service = AuthService::new(db.clone(), "test_secret".to_string());
+
+    let email = format!("audit-{}@example.com", Uuid::new_v4());
+    let user = auth_service.register(&email, "password123", None, "10.0.0.1").await.unwrap();
+
+    // Wrong password for a known user is a failed login attributed to that user
+    assert!(auth_service.authenticate(&email, "", "10.0.0.2").await.is_err());
+    auth_service.authenticate(&email, "password123", "10.0.0.3").await.unwrap();
+
+    let events = db.list_auth_events(Some(user.id), 10, 0).await.unwrap();
+    let logins: Vec<_> = events.iter().filter(|e| e.event == AUDIT_LOGIN).collect();
+    assert_eq!(logins.len(), 2);
+
+    let success = logins.iter().find(|e| e.success).expect("success row");
+    assert_eq!(success.ip, "10.0.0.3");
+    let failure = logins.iter().find(|e| !e.success).expect("failure row");
+    assert_eq!(failure.ip, "10.0.0.2");
+
+    assert!(events.iter().any(|e| e.event == AUDIT_REGISTER && e.success && e.ip == "10.0.0.1"));
+}
+
+#[tokio::test]
+async fn test_unknown_user_login_audited() {
+    let pool = setup_test_db().await;
+    let db = DbOperations::new(std::sync::Arc::new(pool));
+    let auth_service = AuthService::new(db.clone(), "test_secret".to_string());
+
+    let ip = format!("203.0.113.{}", rand::random::<u8>());
+    let email = format!("nobody-{}@example.com", Uuid::new_v4());
+    assert!(auth_service.authenticate(&email, "password123", &ip).await.is_err());
+
+    let events = db.list_auth_events(None, 100, 0).await.unwrap();
+    assert!(events.iter().any(|e| e.event == AUDIT_LOGIN && !e.success && e.user_id.is_none() && e.ip == ip));
+}
+
+/// Passes activity writes through to the database, counting the UPDATEs issued
+struct CountingActivityStore {
+    db: DbOperations,
+    updates: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl ActivityStore for CountingActivityStore {
+    async fn touch_sessions(&self, tokens: &[String], seen_at: &[DateTime<Utc>]) -> Result<u64, Error> {
+        self.updates.fetch_add(1, Ordering::SeqCst);
+        self.db.update_sessions_activity(tokens, seen_at).await
+    }
+}
+
+#[tokio::test]
+async fn test_session_activity_is_batched() {
+    let pool = setup_test_db().await;
+    let db = DbOperations::new(Arc::new(pool));
+    let store = Arc::new(CountingActivityStore { db: db.clone(), updates: AtomicUsize::new(0) });
+    let auth_service = AuthService::new(db.clone(), "test_secret".to_string())
+        .with_activity_store(store.clone());
+
+    let email = format!("activity-{}@example.com", Uuid::new_v4());
+    auth_service.register(&email, "password123", None, "127.0.0.1").await.unwrap();
+    let token = auth_service.authenticate(&email, "password123", "127.0.0.1").await.unwrap();
+    let before = db.get_session_by_token(&token).await.unwrap().unwrap().last_activity;
+
+    for _ in 0..50 {
+        auth_service.validate_token(&token).await.unwrap();
+    }
+    assert_eq!(store.updates.load(Ordering::SeqCst), 0);
+
+    assert_eq!(auth_service.flush_session_activity().await.unwrap(), 1);
+    assert_eq!(store.updates.load(Ordering::SeqCst), 1);
+
+    let after = db.get_session_by_token(&token).await.unwrap().unwrap().last_activity;
+    assert!(after > before);
+}
+
+#[tokio::test]
+async fn test_session_limit() {
+    let pool = setup_test_db().await;
+    let db = DbOperations::new(Arc::new(pool));
+
+    let evicting = AuthService::new(db.clone(), "test_secret".to_string())
+        .with_session_limit(2, SessionLimitPolicy::EvictOldest);
+    let email = format!("sessions-{}@example.com", Uuid::new_v4());
+    evicting.register(&email, "password123", None, "127.0.0.1").await.unwrap();
+
+    let oldest = evicting.authenticate(&email, "password123", "127.0.0.1").await.unwrap();
+    let second = evicting.authenticate(&email, "password123", "127.0.0.1").await.unwrap();
+    let newest = evicting.authenticate(&email, "password123", "127.0.0.1").await.unwrap();
+
+    // The third login pushed out the oldest session
+    assert!(matches!(evicting.validate_token(&oldest).await, Err(Error::Auth(AuthError::InvalidToken))));
+    evicting.validate_token(&second).await.unwrap();
+    evicting.validate_token(&newest).await.unwrap();
+
+    // Under "reject" a login past the limit fails and existing sessions survive
+    let rejecting = AuthService::new(db.clone(), "test_secret".to_string())
+        .with_session_limit(2, SessionLimitPolicy::Reject);
+    assert!(matches!(
+        rejecting.authenticate(&email, "password123", "127.0.0.1").await,
+        Err(Error::Forbidden(_))
+    ));
+    rejecting.validate_token(&second).await.unwrap();
+    rejecting.validate_token(&newest).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_session_token_types() {
+    let pool = Arc::new(setup_test_db().await);
+    let jwt_service = AuthService::new(DbOperations::new(pool.clone()), "test_secret".to_string());
+    let opaque_service = AuthService::new(DbOperations::new(pool), "test_secret".to_string())
+        .with_session_token_type(SessionTokenType::Opaque);
+
+    let email = format!("test-{}@example.com", Uuid::new_v4());
+    let user = opaque_service.register(&email, "password123", None, "127.0.0.1").await.unwrap();
+
+    let jwt = jwt_service.authenticate(&email, "password123", "127.0.0.1").await.unwrap();
+    let opaque = opaque_service.authenticate(&email, "password123", "127.0.0.1").await.unwrap();
+    assert_eq!(jwt.split('.').count(), 3);
+    // 256 bits, base64url without padding
+    assert_eq!(opaque.len(), 43);
+    assert!(!opaque.contains('.'));
+
+    // Either kind validates whichever type the service now issues
+    for service in [&jwt_service, &opaque_service] {
+        assert_eq!(service.validate_token(&jwt).await.unwrap().id, user.id);
+        assert_eq!(service.validate_token(&opaque).await.unwrap().id, user.id);
     }
-} 
\ No newline at end of file
+
+    opaque_service.invalidate_token(&opaque, "127.0.0.1").await.unwrap();
+    assert!(matches!(opaque_service.validate_token(&opaque).await, Err(Error::Auth(AuthError::InvalidToken))));
+    assert!(matches!(opaque_service.invalidate_token(&opaque, "127.0.0.1").await, Err(Error::Auth(AuthError::InvalidToken))));
+
+    jwt_service.invalidate_token(&jwt, "127.0.0.1").await.unwrap();
+    assert!(matches!(jwt_service.validate_token(&jwt).await, Err(Error::Auth(AuthError::InvalidToken))));
+
+    // A made-up opaque token matches no session
+    let forged = "A".repeat(43);
+    assert!(matches!(opaque_service.validate_token(&forged).await, Err(Error::Auth(AuthError::InvalidToken))));
+}
+
+#[tokio::test]
+async fn test_session_expiry_leeway() {
+    let pool = Arc::new(setup_test_db().await);
+    let db = DbOperations::new(pool);
+    let service = AuthService::new(db.clone(), "test_secret".to_string());
+
+    let email = format!("test-{}@example.com", Uuid::new_v4());
+    let user = service.register(&email, "password123", None, "127.0.0.1").await.unwrap();
+
+    // Expired ten seconds ago by this node's clock
+    let token = Uuid::new_v4().simple().to_string();
+    db.create_session(&UserSession::expiring_in(user.id, token.clone(), chrono::Duration::seconds(-10)))
+        .await
+        .unwrap();
+
+    // Within the default leeway it still validates
+    assert_eq!(service.validate_token(&token).await.unwrap().id, user.id);
+
+    let strict = AuthService::new(db, "test_secret".to_string()).with_token_validation(5, true);
+    assert!(matches!(strict.validate_token(&token).await, Err(Error::Auth(AuthError::TokenExpired))));
+}
+
+#[tokio::test]
+async fn test_password_hash_upgraded_on_login() {
+    let pool = Arc::new(setup_test_db().await);
+    let db = DbOperations::new(pool.clone());
+    let weak = PasswordParams { memory_kib: 1024, iterations: 1, parallelism: 1 };
+    let strong = PasswordParams { memory_kib: 8192, iterations: 3, parallelism: 1 };
+    let weak_service = AuthService::new(DbOperations::new(pool.clone()), "test_secret".to_string())
+        .with_password_params(weak, true);
+    let strong_service = AuthService::new(DbOperations::new(pool.clone()), "test_secret".to_string())
+        .with_password_params(strong, true);
+    let no_rehash_service = AuthService::new(DbOperations::new(pool), "test_secret".to_string())
+        .with_password_params(strong, false);
+
+    let email = format!("test-{}@example.com", Uuid::new_v4());
+    let user = weak_service.register(&email, "password123", None, "127.0.0.1").await.unwrap();
+    let weak_hash = db.get_password_hash(user.id).await.unwrap().unwrap();
+    assert!(!needs_rehash(&weak_hash, &weak));
+
+    // Upgrading is opt-in, and a failed login never touches the hash
+    no_rehash_service.authenticate(&email, "password123", "127.0.0.1").await.unwrap();
+    assert!(strong_service.authenticate(&email, "wrong-password1", "127.0.0.1").await.is_err());
+    assert_eq!(db.get_password_hash(user.id).await.unwrap().unwrap(), weak_hash);
+
+    strong_service.authenticate(&email, "password123", "127.0.0.1").await.unwrap();
+    let strong_hash = db.get_password_hash(user.id).await.unwrap().unwrap();
+    assert_ne!(strong_hash, weak_hash);
+    assert!(!needs_rehash(&strong_hash, &strong));
+
+    // The upgraded hash still accepts the same password, and isn't redone
+    strong_service.authenticate(&email, "password123", "127.0.0.1").await.unwrap();
+    assert_eq!(db.get_password_hash(user.id).await.unwrap().unwrap(), strong_hash);
+}
@@ -1,6 +1,21 @@
-use actix_web::{test, web, App};
-use buddybot_server::{AppState, Settings, auth::handlers::{login, register, logout}};
+use actix_web::{test, web, App, HttpRequest, HttpResponse};
+use actix_web::middleware::{from_fn, Compress, Condition};
+use buddybot_server::{AppState, Settings, RateLimiter, RateLimitConfig, auth::handlers::{change_password, guest, list_audit_events, login, register, logout, validate}};
+use buddybot_server::admin::handlers::{activate_user, admin_stats, deactivate_user, reset_user_password, set_maintenance};
+use buddybot_server::auth::middleware::{rate_limit, request_token, require_user};
+use buddybot_server::error::Error;
+use buddybot_server::middleware::json_config;
+use buddybot_server::users::handlers::{export_data, get_setting, purge_account, put_setting};
+use buddybot_server::proxy::EncryptedApiKey;
+use buddybot_server::db::{ConversationMessage, DbOperations, User, UserSession};
 use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+fn unique_email() -> String {
+    format!("test-{}@example.com", Uuid::new_v4())
+}
 
 #[actix_web::test]
 async fn test_register_and_login() {
@@ -12,12 +27,13 @@ async fn test_register_and_login() {
             .route("/auth/login", web::post().to(login))
             .route("/auth/register", web::post().to(register))
     ).await;
+    let email = unique_email();
 
     // Test registration
     let register_response = test::TestRequest::post()
         .uri("/auth/register")
         .set_json(json!({
-            "email": "test@example.com",
+            "email": email,
             "password": "password123",
             "display_name": "Test User"
         }))
@@ -32,7 +48,7 @@ async fn test_register_and_login() {
     let login_response = test::TestRequest::post()
         .uri("/auth/login")
         .set_json(json!({
-            "email": "test@example.com",
+            "email": email,
             "password": "password123"
         }))
         .send_request(&app)
@@ -86,7 +102,86 @@ async fn test_invalid_registration() {
         .send_request(&app)
         .await;
     
-    assert_eq!(response.status(), 401);
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    let fields = body["error"]["fields"].as_array().unwrap();
+    assert_eq!(fields.len(), 1);
+    assert_eq!(fields[0]["field"], "password");
+
+    // Both fields invalid should report both
+    let response = test::TestRequest::post()
+        .uri("/auth/register")
+        .set_json(json!({
+            "email": "not-an-email",
+            "password": ""
+        }))
+        .send_request(&app)
+        .await;
+
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    let fields: Vec<&str> = body["error"]["fields"].as_array().unwrap()
+        .iter()
+        .map(|f| f["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, vec!["email", "password"]);
+}
+
+#[actix_web::test]
+async fn test_register_weak_password() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/register", web::post().to(register))
+    ).await;
+
+    for (password, message) in [
+        ("short1", "Password must be at least 8 characters"),
+        ("onlyletters", "Password must mix letters with numbers or symbols"),
+    ] {
+        let email = unique_email();
+        let response = test::TestRequest::post()
+            .uri("/auth/register")
+            .set_json(json!({ "email": email, "password": password }))
+            .send_request(&app)
+            .await;
+
+        assert_eq!(response.status(), 400);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["error"]["code"], "invalid_request");
+        assert_eq!(body["error"]["fields"][0]["field"], "password");
+        assert_eq!(body["error"]["fields"][0]["message"], message);
+
+        // Rejected before the handler ran, so no account was created
+        assert!(state.db.get_user_by_email(&email).await.unwrap().is_none());
+    }
+}
+
+#[actix_web::test]
+async fn test_invalid_login_email() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/login", web::post().to(login))
+    ).await;
+
+    let response = test::TestRequest::post()
+        .uri("/auth/login")
+        .set_json(json!({
+            "email": "user@localhost",
+            "password": "password123"
+        }))
+        .send_request(&app)
+        .await;
+
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["error"]["fields"][0]["field"], "email");
+    assert_eq!(body["error"]["fields"][0]["message"], "Invalid email address");
 }
 
 #[actix_web::test]
@@ -100,12 +195,13 @@ async fn test_logout() {
             .route("/auth/register", web::post().to(register))
             .route("/auth/logout", web::post().to(logout))
     ).await;
+    let email = unique_email();
 
     // First register and get a token
     let register_response = test::TestRequest::post()
         .uri("/auth/register")
         .set_json(json!({
-            "email": "test@example.com",
+            "email": email,
             "password": "password123",
             "display_name": "Test User"
         }))
@@ -125,11 +221,1042 @@ async fn test_logout() {
     assert_eq!(logout_response.status(), 200);
 
     // Verify token is invalidated by trying to use it
-    let protected_response = test::TestRequest::get()
-        .uri("/protected")
+    assert!(state.auth_service.validate_token(token).await.is_err());
+
+    // A second logout with the same token is rejected
+    let repeat_response = test::TestRequest::post()
+        .uri("/auth/logout")
         .insert_header(("Authorization", format!("Bearer {}", token)))
         .send_request(&app)
         .await;
-    
-    assert_eq!(protected_response.status(), 401);
-} 
\ No newline at end of file
+    assert_eq!(repeat_response.status(), 401);
+}
+
+#[actix_web::test]
+async fn test_logout_unknown_token() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/logout", web::post().to(logout))
+    ).await;
+
+    let response = test::TestRequest::post()
+        .uri("/auth/logout")
+        .insert_header(("Authorization", "Bearer not-a-real-token"))
+        .send_request(&app)
+        .await;
+
+    assert_eq!(response.status(), 401);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["error"]["code"], "invalid_token");
+}
+
+#[actix_web::test]
+async fn test_expired_session_reports_token_expired() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/validate", web::get().to(validate))
+    ).await;
+
+    // A session that ran out well beyond the clock-skew leeway
+    let user = state.db.create_user(&User::new(unique_email(), None)).await.unwrap();
+    let token = Uuid::new_v4().simple().to_string();
+    state.db.create_session(&UserSession::expiring_in(user.id, token.clone(), chrono::Duration::hours(-1)))
+        .await
+        .unwrap();
+
+    let response = test::TestRequest::get()
+        .uri("/auth/validate")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 401);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["error"]["code"], "token_expired");
+}
+
+#[actix_web::test]
+async fn test_validate_for_gateway() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/register", web::post().to(register))
+            .route("/auth/validate", web::get().to(validate))
+    ).await;
+    let email = unique_email();
+
+    let register_response = test::TestRequest::post()
+        .uri("/auth/register")
+        .set_json(json!({ "email": email, "password": "password123" }))
+        .send_request(&app)
+        .await;
+    let register_body: serde_json::Value = test::read_body_json(register_response).await;
+    let token = register_body["token"].as_str().unwrap();
+    let user = state.auth_service.validate_token(token).await.unwrap();
+
+    let response = test::TestRequest::get()
+        .uri("/auth/validate")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 200);
+    let header = |name: &str| response.headers().get(name).unwrap().to_str().unwrap().to_string();
+    assert_eq!(header("X-User-Id"), user.id.to_string());
+    assert_eq!(header("X-User-Email"), email);
+    assert_eq!(header("X-User-Role"), "user");
+    assert!(test::read_body(response).await.is_empty());
+
+    for authorization in [None, Some("Bearer not-a-real-token")] {
+        let mut request = test::TestRequest::get().uri("/auth/validate");
+        if let Some(authorization) = authorization {
+            request = request.insert_header(("Authorization", authorization));
+        }
+        let response = request.send_request(&app).await;
+        assert_eq!(response.status(), 401);
+        assert!(response.headers().get("X-User-Id").is_none());
+    }
+}
+
+/// Ends the caller's session, then resolves them with `require_user`
+async fn whoami_after_logout(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse, Error> {
+    let token = request_token(&req, &state.config.auth)?;
+    state.db.delete_session(token).await?;
+    let user = require_user(&req, &state).await?;
+    Ok(HttpResponse::Ok().body(user.id.to_string()))
+}
+
+#[actix_web::test]
+async fn test_rate_limit_shares_session_lookup_with_handlers() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config).await.unwrap();
+    let whoami = |token: &str| test::TestRequest::get()
+        .uri("/whoami")
+        .insert_header(("Authorization", format!("Bearer {}", token)));
+
+    // The handler can only find the user if it reuses the middleware's lookup,
+    // made before the session was deleted
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(rate_limit))
+            .app_data(web::Data::new(state.clone()))
+            .route("/whoami", web::get().to(whoami_after_logout))
+    ).await;
+    let token = session_for(&state, &unique_email()).await;
+    assert_eq!(whoami(&token).send_request(&app).await.status(), 200);
+
+    // Without the middleware the handler does its own lookup
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/whoami", web::get().to(whoami_after_logout))
+    ).await;
+    let token = session_for(&state, &unique_email()).await;
+    assert_eq!(whoami(&token).send_request(&app).await.status(), 401);
+}
+
+#[actix_web::test]
+async fn test_rate_limit_headers() {
+    let config = Settings::new().unwrap();
+    let mut state = AppState::new(config.clone()).await.unwrap();
+
+    // Allow a single request per window so the second one trips the limiter
+    let mut limits = HashMap::new();
+    limits.insert("standard".to_string(), 1);
+    state.rate_limiter = Arc::new(RateLimiter::new(RateLimitConfig {
+        window_size: chrono::Duration::minutes(1),
+        limits,
+    }));
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(rate_limit))
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/register", web::post().to(register))
+            .route("/limited", web::get().to(HttpResponse::Ok))
+    ).await;
+
+    let register_response = test::TestRequest::post()
+        .uri("/auth/register")
+        .set_json(json!({
+            "email": unique_email(),
+            "password": "password123"
+        }))
+        .send_request(&app)
+        .await;
+
+    let register_body: serde_json::Value = test::read_body_json(register_response).await;
+    let token = register_body.get("token").unwrap().as_str().unwrap();
+
+    let first = test::TestRequest::get()
+        .uri("/limited")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .send_request(&app)
+        .await;
+    assert_eq!(first.status(), 200);
+
+    let second = test::TestRequest::get()
+        .uri("/limited")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .send_request(&app)
+        .await;
+    assert_eq!(second.status(), 429);
+
+    let headers = second.headers();
+    assert_eq!(headers.get("X-RateLimit-Limit").unwrap(), "1");
+    assert_eq!(headers.get("X-RateLimit-Remaining").unwrap(), "0");
+    let reset: i64 = headers.get("X-RateLimit-Reset").unwrap().to_str().unwrap().parse().unwrap();
+    assert!(reset > chrono::Utc::now().timestamp());
+}
+
+#[actix_web::test]
+async fn test_audit_listing_compressed() {
+    let mut config = Settings::new().unwrap();
+    config.admin.token = Some("test-admin-token".to_string());
+    config.server.compression = true;
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .wrap(Condition::new(config.server.compression, Compress::default()))
+            .app_data(web::Data::new(state.clone()))
+            .route("/admin/audit", web::get().to(list_audit_events))
+    ).await;
+
+    let response = test::TestRequest::get()
+        .uri("/admin/audit?limit=5")
+        .insert_header(("X-Admin-Token", "test-admin-token"))
+        .insert_header(("Accept-Encoding", "gzip"))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers().get("content-encoding").unwrap(), "gzip");
+
+    // Clients that don't ask for compression get plain JSON
+    let response = test::TestRequest::get()
+        .uri("/admin/audit?limit=5")
+        .insert_header(("X-Admin-Token", "test-admin-token"))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get("content-encoding").is_none());
+}
+
+#[actix_web::test]
+async fn test_admin_stats() {
+    let mut config = Settings::new().unwrap();
+    config.admin.token = Some("test-admin-token".to_string());
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/admin/stats", web::get().to(admin_stats))
+    ).await;
+
+    let response = test::TestRequest::get().uri("/admin/stats").send_request(&app).await;
+    assert_eq!(response.status(), 401);
+
+    let response = test::TestRequest::get()
+        .uri("/admin/stats")
+        .insert_header(("X-Admin-Token", "test-admin-token"))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert!(body["active_users"].as_i64().is_some());
+    assert!(body["active_sessions"].as_i64().is_some());
+}
+
+#[actix_web::test]
+async fn test_registration_disabled() {
+    let mut config = Settings::new().unwrap();
+    config.features.registration_enabled = false;
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/register", web::post().to(register))
+    ).await;
+    let email = unique_email();
+
+    let response = test::TestRequest::post()
+        .uri("/auth/register")
+        .set_json(json!({
+            "email": email,
+            "password": "password123"
+        }))
+        .send_request(&app)
+        .await;
+
+    assert_eq!(response.status(), 403);
+    assert!(state.auth_service.authenticate(&email, "password123", "127.0.0.1").await.is_err());
+}
+
+#[actix_web::test]
+async fn test_login_lockout_retry_after() {
+    let mut config = Settings::new().unwrap();
+    config.auth.lockout.max_failures = 2;
+    config.auth.lockout.lock_secs = 300;
+    let state = AppState::new(config).await.unwrap();
+    let lock_duration = chrono::Duration::minutes(5);
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/login", web::post().to(login))
+    ).await;
+    let credentials = json!({
+        "email": unique_email(),
+        "password": "password123"
+    });
+
+    // Unknown account: each attempt fails until the lock kicks in
+    for _ in 0..2 {
+        let response = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(&credentials)
+            .send_request(&app)
+            .await;
+        assert_eq!(response.status(), 401);
+    }
+
+    let response = test::TestRequest::post()
+        .uri("/auth/login")
+        .set_json(&credentials)
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 429);
+
+    let retry_after: i64 = response.headers().get("retry-after").unwrap()
+        .to_str().unwrap().parse().unwrap();
+    assert!(retry_after > 0 && retry_after <= lock_duration.num_seconds());
+
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["error"]["retry_after_seconds"], retry_after);
+}
+
+#[actix_web::test]
+async fn test_register_display_name_validation() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/register", web::post().to(register))
+    ).await;
+
+    let response = test::TestRequest::post()
+        .uri("/auth/register")
+        .set_json(json!({
+            "email": unique_email(),
+            "password": "password123",
+            "display_name": "x".repeat(101)
+        }))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["error"]["fields"][0]["field"], "display_name");
+
+    // Surrounding whitespace is stripped before storing
+    let email = unique_email();
+    let response = test::TestRequest::post()
+        .uri("/auth/register")
+        .set_json(json!({
+            "email": email,
+            "password": "password123",
+            "display_name": "  Test User  "
+        }))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 201);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    let user = state.auth_service.validate_token(body["token"].as_str().unwrap()).await.unwrap();
+    assert_eq!(user.display_name.as_deref(), Some("Test User"));
+}
+
+#[actix_web::test]
+async fn test_register_rejects_oversized_body() {
+    let config = Settings::new().unwrap();
+    let max_bytes = config.server.max_json_bytes;
+    let state = AppState::new(config).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .app_data(json_config(max_bytes))
+            .route("/auth/register", web::post().to(register))
+    ).await;
+
+    let response = test::TestRequest::post()
+        .uri("/auth/register")
+        .set_json(json!({
+            "email": unique_email(),
+            "password": "x".repeat(max_bytes),
+        }))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 413);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["error"]["status"], 413);
+}
+
+#[actix_web::test]
+async fn test_login_rejects_malformed_json() {
+    let config = Settings::new().unwrap();
+    let max_bytes = config.server.max_json_bytes;
+    let state = AppState::new(config).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .app_data(json_config(max_bytes))
+            .route("/auth/login", web::post().to(login))
+    ).await;
+
+    let response = test::TestRequest::post()
+        .uri("/auth/login")
+        .insert_header(("Content-Type", "application/json"))
+        .set_payload(r#"{"email": "user@example.com", "password": "#)
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["error"]["code"], "invalid_request");
+    assert_eq!(body["error"]["status"], 400);
+    assert_eq!(body["error"]["fields"][0]["field"], "body");
+    assert_eq!(body["error"]["fields"][0]["message"], "Request body is not valid JSON");
+
+    // Well-formed JSON of the wrong shape names the problem
+    let response = test::TestRequest::post()
+        .uri("/auth/login")
+        .set_json(json!({ "email": "user@example.com" }))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["error"]["code"], "invalid_request");
+    assert!(body["error"]["fields"][0]["message"].as_str().unwrap().contains("password"));
+}
+
+#[actix_web::test]
+async fn test_guest_session() {
+    let mut config = Settings::new().unwrap();
+    let disabled = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(disabled))
+            .route("/auth/guest", web::post().to(guest))
+    ).await;
+    let response = test::TestRequest::post().uri("/auth/guest").send_request(&app).await;
+    assert_eq!(response.status(), 403);
+
+    config.features.guest_sessions_enabled = true;
+    let state = AppState::new(config).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/guest", web::post().to(guest))
+            .route("/auth/logout", web::post().to(logout))
+            .route("/users/me/settings/{key}", web::get().to(get_setting))
+    ).await;
+
+    let response = test::TestRequest::post().uri("/auth/guest").send_request(&app).await;
+    assert_eq!(response.status(), 201);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    let token = body["token"].as_str().unwrap();
+
+    let user = state.auth_service.validate_token(token).await.unwrap();
+    assert!(user.is_guest);
+    assert_eq!(user.rate_limit_tier, "guest");
+
+    // Guests can chat but nothing else
+    let response = test::TestRequest::get()
+        .uri("/users/me/settings/theme")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 403);
+
+    // Logging out removes the guest entirely
+    let response = test::TestRequest::post()
+        .uri("/auth/logout")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 200);
+    assert!(state.db.get_user_by_id(user.id).await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn test_guest_rate_limit() {
+    let mut config = Settings::new().unwrap();
+    config.features.guest_sessions_enabled = true;
+    let state = AppState::new(config).await.unwrap();
+    let guest_limit = RateLimitConfig::default().limits["guest"];
+    assert!(guest_limit < RateLimitConfig::default().limits["standard"]);
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(rate_limit))
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/guest", web::post().to(guest))
+            .route("/limited", web::get().to(HttpResponse::Ok))
+    ).await;
+
+    let response = test::TestRequest::post().uri("/auth/guest").send_request(&app).await;
+    let body: serde_json::Value = test::read_body_json(response).await;
+    let auth = format!("Bearer {}", body["token"].as_str().unwrap());
+
+    for _ in 0..guest_limit {
+        let response = test::TestRequest::get()
+            .uri("/limited")
+            .insert_header(("Authorization", auth.clone()))
+            .send_request(&app)
+            .await;
+        assert_eq!(response.status(), 200);
+    }
+
+    let response = test::TestRequest::get()
+        .uri("/limited")
+        .insert_header(("Authorization", auth))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers().get("X-RateLimit-Limit").unwrap(), guest_limit.to_string().as_str());
+}
+
+/// Register `email` with "password123" and return a session token for it
+async fn session_for(state: &AppState, email: &str) -> String {
+    state.auth_service.register(email, "password123", None, "127.0.0.1").await.unwrap();
+    state.auth_service.authenticate(email, "password123", "127.0.0.1").await.unwrap()
+}
+
+#[actix_web::test]
+async fn test_logout_with_custom_token_header() {
+    let mut config = Settings::new().unwrap();
+    config.auth.header_name = "X-Api-Token".to_string();
+    config.auth.token_prefix = "Token ".to_string();
+    let state = AppState::new(config).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/logout", web::post().to(logout))
+    ).await;
+    let token = session_for(&state, &unique_email()).await;
+
+    // The standard header is no longer read
+    let response = test::TestRequest::post()
+        .uri("/auth/logout")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 401);
+
+    let response = test::TestRequest::post()
+        .uri("/auth/logout")
+        .insert_header(("X-Api-Token", format!("token {}", token)))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 200);
+    assert!(state.auth_service.validate_token(&token).await.is_err());
+}
+
+#[actix_web::test]
+async fn test_change_password() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/login", web::post().to(login))
+            .route("/auth/change-password", web::post().to(change_password))
+    ).await;
+    let email = unique_email();
+    let token = session_for(&state, &email).await;
+    let other = state.auth_service.authenticate(&email, "password123", "127.0.0.1").await.unwrap();
+
+    let response = test::TestRequest::post()
+        .uri("/auth/change-password")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({ "current_password": "password123", "new_password": "n3w-passphrase" }))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["sessions_ended"], 1);
+
+    // The session that made the change survives; the other one is logged out
+    assert!(state.auth_service.validate_token(&token).await.is_ok());
+    assert!(state.auth_service.validate_token(&other).await.is_err());
+
+    // Only the new password logs in from now on
+    for (password, status) in [("password123", 401), ("n3w-passphrase", 200)] {
+        let response = test::TestRequest::post()
+            .uri("/auth/login")
+            .set_json(json!({ "email": email, "password": password }))
+            .send_request(&app)
+            .await;
+        assert_eq!(response.status(), status);
+    }
+}
+
+#[actix_web::test]
+async fn test_change_password_wrong_current() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/change-password", web::post().to(change_password))
+    ).await;
+    let email = unique_email();
+    let token = session_for(&state, &email).await;
+    let other = state.auth_service.authenticate(&email, "password123", "127.0.0.1").await.unwrap();
+
+    let response = test::TestRequest::post()
+        .uri("/auth/change-password")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .set_json(json!({ "current_password": "not-my-password1", "new_password": "n3w-passphrase" }))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 401);
+
+    // Nothing changed: the old password still works and no session was ended
+    assert!(state.auth_service.validate_token(&other).await.is_ok());
+    assert!(state.auth_service.authenticate(&email, "password123", "127.0.0.1").await.is_ok());
+}
+
+#[actix_web::test]
+async fn test_change_password_weak_new_password() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/change-password", web::post().to(change_password))
+    ).await;
+    let email = unique_email();
+    let token = session_for(&state, &email).await;
+
+    for weak in ["short1", "onlyletters", "password123"] {
+        let response = test::TestRequest::post()
+            .uri("/auth/change-password")
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .set_json(json!({ "current_password": "password123", "new_password": weak }))
+            .send_request(&app)
+            .await;
+        assert_eq!(response.status(), 400, "Expected {:?} to be rejected", weak);
+        let body: serde_json::Value = test::read_body_json(response).await;
+        assert_eq!(body["error"]["fields"][0]["field"], "new_password");
+    }
+
+    assert!(state.auth_service.authenticate(&email, "password123", "127.0.0.1").await.is_ok());
+}
+
+#[actix_web::test]
+async fn test_export_contains_only_own_data() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/users/me/export", web::get().to(export_data))
+    ).await;
+    let db = DbOperations::new(state.db_pool.clone());
+
+    let mut conversations = Vec::new();
+    let mut tokens = Vec::new();
+    for _ in 0..2 {
+        let email = unique_email();
+        let token = session_for(&state, &email).await;
+        let user = db.get_user_by_email(&email).await.unwrap().unwrap();
+        let conversation = db.upsert_conversation(Uuid::new_v4(), user.id).await.unwrap().unwrap();
+        db.append_message(user.id, &ConversationMessage::new(conversation.id, "user", format!("hello from {}", email)))
+            .await
+            .unwrap();
+        conversations.push(conversation.id);
+        tokens.push(token);
+    }
+
+    let response = test::TestRequest::get()
+        .uri("/users/me/export")
+        .insert_header(("Authorization", format!("Bearer {}", tokens[0])))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 200);
+    let body = test::read_body(response).await;
+    let raw = std::str::from_utf8(&body).unwrap();
+    assert!(!raw.contains(&tokens[0]), "Export leaked the session token");
+
+    let export: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let exported = export["conversations"].as_array().unwrap();
+    assert_eq!(exported.len(), 1);
+    assert_eq!(exported[0]["id"], conversations[0].to_string());
+    assert_eq!(exported[0]["messages"].as_array().unwrap().len(), 1);
+    assert!(!raw.contains(&conversations[1].to_string()));
+    assert_eq!(export["sessions"].as_array().unwrap().len(), 1);
+
+    let response = test::TestRequest::get().uri("/users/me/export").send_request(&app).await;
+    assert_eq!(response.status(), 401);
+}
+
+#[actix_web::test]
+async fn test_purge_account() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/users/me/purge", web::delete().to(purge_account))
+    ).await;
+    let db = DbOperations::new(state.db_pool.clone());
+
+    let email = unique_email();
+    let token = session_for(&state, &email).await;
+    let user = db.get_user_by_email(&email).await.unwrap().unwrap();
+    let conversation = db.upsert_conversation(Uuid::new_v4(), user.id).await.unwrap().unwrap();
+    db.append_message(user.id, &ConversationMessage::new(conversation.id, "user", "hello".to_string()))
+        .await
+        .unwrap();
+    db.set_setting(user.id, "theme", &json!("dark"), 50).await.unwrap();
+    db.set_user_api_key(user.id, &EncryptedApiKey {
+        encrypted_data: "ciphertext".to_string(),
+        nonce: "nonce".to_string(),
+        created_at: 0,
+        expires_at: None,
+    }).await.unwrap();
+    db.enqueue_outbox(user.id, &json!({ "type": "notice" }), 10).await.unwrap();
+    db.add_user_query_counts(&[user.id], &[3], &[chrono::Utc::now()]).await.unwrap();
+
+    let response = test::TestRequest::delete()
+        .uri("/users/me/purge")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["deleted"]["user_deleted"], true);
+    assert_eq!(body["deleted"]["messages"], 1);
+    assert_eq!(body["deleted"]["stats"], 1);
+
+    let count = |table: &str| {
+        let pool = state.db_pool.clone();
+        let query = format!("SELECT COUNT(*) FROM {} WHERE user_id = $1", table);
+        async move { sqlx::query_scalar::<_, i64>(&query).bind(user.id).fetch_one(&*pool).await.unwrap() }
+    };
+    for table in [
+        "user_sessions",
+        "conversations",
+        "user_settings",
+        "user_api_keys",
+        "outbox_messages",
+        "auth_audit",
+        "user_stats",
+    ] {
+        assert_eq!(count(table).await, 0, "{} rows left after purge", table);
+    }
+    let messages: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages WHERE conversation_id = $1")
+        .bind(conversation.id)
+        .fetch_one(&*state.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(messages, 0);
+    assert!(db.get_user_by_id(user.id).await.unwrap().is_none());
+
+    // Purging again is a no-op, and the old token no longer works
+    let summary = db.purge_user(user.id).await.unwrap();
+    assert!(!summary.user_deleted);
+    let response = test::TestRequest::delete()
+        .uri("/users/me/purge")
+        .insert_header(("Authorization", format!("Bearer {}", token)))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 401);
+}
+
+#[actix_web::test]
+async fn test_maintenance_mode() {
+    let mut config = Settings::new().unwrap();
+    config.admin.token = Some("test-admin-token".to_string());
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/health", web::get().to(buddybot_server::health_check))
+            .route("/auth/register", web::post().to(register))
+            .route("/admin/maintenance", web::post().to(set_maintenance))
+            .route("/users/me/settings/{key}", web::get().to(get_setting))
+            .route("/users/me/settings/{key}", web::put().to(put_setting))
+    ).await;
+    let auth = format!("Bearer {}", session_for(&state, &unique_email()).await);
+
+    let toggle = |enabled: bool| test::TestRequest::post()
+        .uri("/admin/maintenance")
+        .insert_header(("X-Admin-Token", "test-admin-token"))
+        .set_json(json!({ "enabled": enabled }));
+    let register_request = || test::TestRequest::post()
+        .uri("/auth/register")
+        .set_json(json!({ "email": unique_email(), "password": "password123" }));
+
+    let response = toggle(true).send_request(&app).await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["maintenance_mode"], true);
+
+    // Writes are refused with a clear message
+    let response = register_request().send_request(&app).await;
+    assert_eq!(response.status(), 503);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert!(body["error"]["message"].as_str().unwrap().contains("maintenance"));
+
+    let response = test::TestRequest::put()
+        .uri("/users/me/settings/theme")
+        .insert_header(("Authorization", auth.clone()))
+        .set_json(json!("dark"))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 503);
+
+    // Health checks and reads keep working
+    let response = test::TestRequest::get().uri("/health").send_request(&app).await;
+    assert_eq!(response.status(), 200);
+    let response = test::TestRequest::get()
+        .uri("/users/me/settings/theme")
+        .insert_header(("Authorization", auth.clone()))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 404);
+
+    // Only admins may flip the switch
+    let response = test::TestRequest::post()
+        .uri("/admin/maintenance")
+        .set_json(json!({ "enabled": false }))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 401);
+
+    toggle(false).send_request(&app).await;
+    let response = register_request().send_request(&app).await;
+    assert_eq!(response.status(), 201);
+}
+
+#[actix_web::test]
+async fn test_premium_tier_rate_limit() {
+    let config = Settings::new().unwrap();
+    let mut state = AppState::new(config.clone()).await.unwrap();
+
+    let mut limits = HashMap::new();
+    limits.insert("standard".to_string(), 2);
+    limits.insert("premium".to_string(), 5);
+    state.rate_limiter = Arc::new(RateLimiter::new(RateLimitConfig {
+        window_size: chrono::Duration::minutes(1),
+        limits,
+    }));
+
+    let app = test::init_service(
+        App::new()
+            .wrap(from_fn(rate_limit))
+            .app_data(web::Data::new(state.clone()))
+            .route("/limited", web::get().to(HttpResponse::Ok))
+    ).await;
+
+    let standard = session_for(&state, &unique_email()).await;
+    let premium_email = unique_email();
+    let premium = session_for(&state, &premium_email).await;
+    let premium_user = state.db.get_user_by_email(&premium_email).await.unwrap().unwrap();
+    assert!(state.db.set_rate_limit_tier(premium_user.id, "premium").await.unwrap());
+
+    let get = |token: &str| test::TestRequest::get()
+        .uri("/limited")
+        .insert_header(("Authorization", format!("Bearer {}", token)));
+
+    // Past the standard limit, but within the premium one
+    for _ in 0..4 {
+        assert_eq!(get(&premium).send_request(&app).await.status(), 200);
+    }
+
+    for _ in 0..2 {
+        assert_eq!(get(&standard).send_request(&app).await.status(), 200);
+    }
+    let response = get(&standard).send_request(&app).await;
+    assert_eq!(response.status(), 429);
+    assert_eq!(response.headers().get("X-RateLimit-Limit").unwrap(), "2");
+}
+
+#[actix_web::test]
+async fn test_deactivate_and_activate_user() {
+    let mut config = Settings::new().unwrap();
+    config.admin.token = Some("test-admin-token".to_string());
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/login", web::post().to(login))
+            .route("/admin/users/{id}/deactivate", web::post().to(deactivate_user))
+            .route("/admin/users/{id}/activate", web::post().to(activate_user))
+    ).await;
+    let email = unique_email();
+    let token = session_for(&state, &email).await;
+    let user = state.auth_service.validate_token(&token).await.unwrap();
+
+    let admin_post = |action: &str, id: Uuid| test::TestRequest::post()
+        .uri(&format!("/admin/users/{}/{}", id, action))
+        .insert_header(("X-Admin-Token", "test-admin-token"));
+
+    let unauthorized = test::TestRequest::post()
+        .uri(&format!("/admin/users/{}/deactivate", user.id))
+        .send_request(&app)
+        .await;
+    assert_eq!(unauthorized.status(), 401);
+
+    let response = admin_post("deactivate", user.id).send_request(&app).await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["is_active"], false);
+    assert_eq!(body["sessions_ended"], 1);
+
+    assert!(state.auth_service.validate_token(&token).await.is_err());
+    let login_body = json!({ "email": email, "password": "password123" });
+    let response = test::TestRequest::post().uri("/auth/login").set_json(&login_body).send_request(&app).await;
+    assert_eq!(response.status(), 403);
+
+    let response = admin_post("activate", user.id).send_request(&app).await;
+    assert_eq!(response.status(), 200);
+    let response = test::TestRequest::post().uri("/auth/login").set_json(&login_body).send_request(&app).await;
+    assert_eq!(response.status(), 200);
+
+    let response = admin_post("deactivate", Uuid::new_v4()).send_request(&app).await;
+    assert_eq!(response.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_admin_password_reset_for_user_without_password() {
+    let mut config = Settings::new().unwrap();
+    config.admin.token = Some("test-admin-token".to_string());
+    let state = AppState::new(config).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/login", web::post().to(login))
+            .route("/admin/users/{id}/password", web::post().to(reset_user_password))
+    ).await;
+
+    // Users created before passwords were stored have no hash
+    let email = unique_email();
+    let user = state.db.create_user(&User::new(email.clone(), None)).await.unwrap();
+    let login_body = json!({ "email": email, "password": "n3w-passphrase" });
+    let response = test::TestRequest::post().uri("/auth/login").set_json(&login_body).send_request(&app).await;
+    assert_eq!(response.status(), 401);
+
+    let reset = |id: Uuid, password: &str| test::TestRequest::post()
+        .uri(&format!("/admin/users/{}/password", id))
+        .insert_header(("X-Admin-Token", "test-admin-token"))
+        .set_json(json!({ "new_password": password }));
+
+    let response = reset(user.id, "short").send_request(&app).await;
+    assert_eq!(response.status(), 400);
+    let response = reset(Uuid::new_v4(), "n3w-passphrase").send_request(&app).await;
+    assert_eq!(response.status(), 404);
+
+    let response = reset(user.id, "n3w-passphrase").send_request(&app).await;
+    assert_eq!(response.status(), 200);
+    let response = test::TestRequest::post().uri("/auth/login").set_json(&login_body).send_request(&app).await;
+    assert_eq!(response.status(), 200);
+}
+
+#[actix_web::test]
+async fn test_registration_webhook_delivered() {
+    use buddybot_server::webhooks::{sign, EVENT_HEADER, EVENT_USER_REGISTERED, SIGNATURE_HEADER};
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    fn header_value(request: &Request, name: &str) -> Option<String> {
+        request.headers.iter()
+            .find(|(header, _)| header.as_str().eq_ignore_ascii_case(name))
+            .map(|(_, values)| values.last().as_str().to_string())
+    }
+
+    let hook = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&hook)
+        .await;
+
+    let mut config = Settings::new().unwrap();
+    config.webhooks.url = Some(format!("{}/hook", hook.uri()));
+    config.webhooks.secret = Some("hook_secret".to_string());
+    let state = AppState::new(config).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/register", web::post().to(register))
+    ).await;
+
+    let email = unique_email();
+    let response = test::TestRequest::post()
+        .uri("/auth/register")
+        .set_json(json!({ "email": email, "password": "password123" }))
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 201);
+
+    // Delivery happens in the background; registration then logs in, so a
+    // second `auth.login` event follows
+    let mut requests = Vec::new();
+    for _ in 0..100 {
+        requests = hook.received_requests().await.unwrap();
+        if requests.len() >= 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let delivery = requests.iter()
+        .find(|r| header_value(r, EVENT_HEADER).as_deref() == Some(EVENT_USER_REGISTERED))
+        .expect("no user.registered delivery");
+
+    let signature = header_value(delivery, SIGNATURE_HEADER).expect("unsigned delivery");
+    assert_eq!(signature, sign("hook_secret", &delivery.body));
+
+    let payload: serde_json::Value = serde_json::from_slice(&delivery.body).unwrap();
+    assert_eq!(payload["event"], EVENT_USER_REGISTERED);
+    assert_eq!(payload["data"]["email"], email);
+    assert!(payload["id"].is_string());
+    assert!(requests.iter().any(|r| header_value(r, EVENT_HEADER).as_deref() == Some("auth.login")));
+}
+
+#[actix_web::test]
+async fn test_concurrent_registration_conflicts() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/register", web::post().to(register))
+    ).await;
+
+    let email = unique_email();
+    let request = || test::TestRequest::post()
+        .uri("/auth/register")
+        .set_json(json!({ "email": email, "password": "password123" }))
+        .to_request();
+    let (first, second) = futures::join!(
+        test::call_service(&app, request()),
+        test::call_service(&app, request()),
+    );
+
+    let mut statuses = [first.status().as_u16(), second.status().as_u16()];
+    statuses.sort();
+    assert_eq!(statuses, [201, 409]);
+
+    let loser = if first.status() == 409 { first } else { second };
+    let body: serde_json::Value = test::read_body_json(loser).await;
+    assert_eq!(body["error"]["code"], "conflict");
+
+    // Registering again later is the same clean conflict
+    let response = test::call_service(&app, request()).await;
+    assert_eq!(response.status(), 409);
+}
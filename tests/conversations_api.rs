@@ -0,0 +1,93 @@
+use actix_web::{test, web, App};
+use buddybot_server::conversations::handlers::list_messages;
+use buddybot_server::db::ConversationMessage;
+use buddybot_server::{AppState, Settings, auth::handlers::register};
+use serde_json::json;
+use uuid::Uuid;
+
+#[actix_web::test]
+async fn test_message_history_paging_and_ownership() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/register", web::post().to(register))
+            .route("/conversations/{id}/messages", web::get().to(list_messages))
+    ).await;
+
+    let mut auth = Vec::new();
+    for _ in 0..2 {
+        let response = test::TestRequest::post()
+            .uri("/auth/register")
+            .set_json(json!({
+                "email": format!("test-{}@example.com", Uuid::new_v4()),
+                "password": "password123"
+            }))
+            .send_request(&app)
+            .await;
+        let body: serde_json::Value = test::read_body_json(response).await;
+        auth.push(format!("Bearer {}", body["token"].as_str().unwrap()));
+    }
+    let owner = state.auth_service.validate_token(auth[0].strip_prefix("Bearer ").unwrap()).await.unwrap();
+
+    // Five turns a second apart: "turn 0" is the oldest
+    let conversation_id = Uuid::new_v4();
+    state.db.upsert_conversation(conversation_id, owner.id).await.unwrap().unwrap();
+    let start = chrono::Utc::now() - chrono::Duration::minutes(1);
+    for n in 0..5 {
+        let mut message = ConversationMessage::new(conversation_id, "user", format!("turn {}", n));
+        message.created_at = start + chrono::Duration::seconds(n);
+        state.db.append_message(owner.id, &message).await.unwrap();
+    }
+
+    let get_page = |uri: String, auth: String| {
+        let app = &app;
+        async move {
+            let response = test::TestRequest::get()
+                .uri(&uri)
+                .insert_header(("Authorization", auth))
+                .send_request(app)
+                .await;
+            let status = response.status().as_u16();
+            let body: serde_json::Value = test::read_body_json(response).await;
+            (status, body)
+        }
+    };
+    let contents = |body: &serde_json::Value| -> Vec<String> {
+        body["messages"].as_array().unwrap().iter()
+            .map(|m| m["content"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let base = format!("/conversations/{}/messages", conversation_id);
+
+    // Newest first, following the message-id cursor until it runs out
+    let (status, first) = get_page(format!("{}?limit=2", base), auth[0].clone()).await;
+    assert_eq!(status, 200);
+    assert_eq!(contents(&first), vec!["turn 4", "turn 3"]);
+
+    let cursor = first["next_before"].as_str().unwrap();
+    let (_, second) = get_page(format!("{}?limit=2&before={}", base, cursor), auth[0].clone()).await;
+    assert_eq!(contents(&second), vec!["turn 2", "turn 1"]);
+
+    let cursor = second["next_before"].as_str().unwrap();
+    let (_, last) = get_page(format!("{}?limit=2&before={}", base, cursor), auth[0].clone()).await;
+    assert_eq!(contents(&last), vec!["turn 0"]);
+    assert!(last["next_before"].is_null());
+
+    // A timestamp cursor excludes turns at or after that instant
+    let before = (start + chrono::Duration::seconds(2)).to_rfc3339_opts(chrono::SecondsFormat::Micros, true);
+    let (_, by_time) = get_page(format!("{}?before={}", base, before), auth[0].clone()).await;
+    assert_eq!(contents(&by_time), vec!["turn 1", "turn 0"]);
+
+    let (status, _) = get_page(format!("{}?limit=0", base), auth[0].clone()).await;
+    assert_eq!(status, 400);
+    let (status, _) = get_page(format!("{}?before=yesterday", base), auth[0].clone()).await;
+    assert_eq!(status, 400);
+
+    // Someone else's conversation looks the same as one that doesn't exist
+    let (status, _) = get_page(base.clone(), auth[1].clone()).await;
+    assert_eq!(status, 404);
+    let (status, _) = get_page(format!("/conversations/{}/messages", Uuid::new_v4()), auth[0].clone()).await;
+    assert_eq!(status, 404);
+}
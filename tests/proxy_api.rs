@@ -0,0 +1,146 @@
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::{test, web, App};
+use async_trait::async_trait;
+use buddybot_server::config::ProxyConfig;
+use buddybot_server::error::ProxyError;
+use buddybot_server::proxy::handlers::{delete_api_key, put_api_key, query};
+use buddybot_server::proxy::{ChatTurn, LlmProvider, ProxyService, QueryOptions};
+use buddybot_server::websocket::process_query;
+use buddybot_server::{AppState, Settings};
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Provider that answers with the API key it was called with
+struct KeyEchoProvider;
+
+#[async_trait]
+impl LlmProvider for KeyEchoProvider {
+    fn name(&self) -> &str {
+        "key-echo"
+    }
+
+    async fn complete(&self, _messages: &[ChatTurn]) -> Result<String, ProxyError> {
+        Ok("no key".to_string())
+    }
+
+    async fn complete_with_key(&self, _messages: &[ChatTurn], api_key: Option<&str>) -> Result<String, ProxyError> {
+        Ok(api_key.unwrap_or("no key").to_string())
+    }
+}
+
+/// Register a user and return their id and an Authorization header value
+async fn register_user(state: &AppState) -> (Uuid, String) {
+    let email = format!("test-{}@example.com", Uuid::new_v4());
+    state.auth_service.register(&email, "password123", None, "127.0.0.1").await.unwrap();
+    let token = state.auth_service.authenticate(&email, "password123", "127.0.0.1").await.unwrap();
+    let user = state.auth_service.validate_token(&token).await.unwrap();
+    (user.id, format!("Bearer {}", token))
+}
+
+#[actix_web::test]
+async fn test_replace_and_delete_api_key() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/proxy/api-key", web::put().to(put_api_key))
+            .route("/proxy/api-key", web::delete().to(delete_api_key))
+    ).await;
+    let (user_id, auth) = register_user(&state).await;
+
+    let proxy_config = ProxyConfig { default_api_key: Some("server-key".to_string()), ..ProxyConfig::default() };
+    let proxy = ProxyService::new(Arc::new(KeyEchoProvider), &proxy_config).with_api_keys(state.api_keys.clone());
+    let ask = || process_query(&proxy, &state.db, user_id, "hello", None, &[], QueryOptions::default());
+
+    assert_eq!(ask().await.unwrap(), "server-key");
+
+    for key in ["sk-first", "sk-rotated"] {
+        let put = test::TestRequest::put()
+            .uri("/proxy/api-key")
+            .insert_header(("Authorization", auth.clone()))
+            .set_json(json!({ "api_key": key }))
+            .send_request(&app)
+            .await;
+        assert_eq!(put.status(), 200);
+        let body: serde_json::Value = test::read_body_json(put).await;
+        assert!(!body.to_string().contains(key), "The key must not be echoed back");
+
+        assert_eq!(ask().await.unwrap(), key);
+    }
+
+    // Stored encrypted, never in plain text
+    let stored = state.db.get_user_api_key(user_id).await.unwrap().unwrap();
+    assert!(!stored.encrypted_data.contains("sk-rotated"));
+
+    let delete = test::TestRequest::delete()
+        .uri("/proxy/api-key")
+        .insert_header(("Authorization", auth.clone()))
+        .send_request(&app)
+        .await;
+    assert_eq!(delete.status(), 200);
+    assert_eq!(ask().await.unwrap(), "server-key");
+
+    let again = test::TestRequest::delete()
+        .uri("/proxy/api-key")
+        .insert_header(("Authorization", auth.clone()))
+        .send_request(&app)
+        .await;
+    assert_eq!(again.status(), 404);
+
+    // With no default key, configuration decides whether keyless calls fail
+    let strict_config = ProxyConfig { require_api_key: true, ..ProxyConfig::default() };
+    let strict = ProxyService::new(Arc::new(KeyEchoProvider), &strict_config).with_api_keys(state.api_keys.clone());
+    let refused = process_query(&strict, &state.db, user_id, "hello", None, &[], QueryOptions::default()).await.unwrap_err();
+    assert_eq!(refused.code(), "missing_api_key");
+
+    // Models outside the allowlist are refused before reaching the provider
+    let unlisted = QueryOptions { model: Some("unlisted"), ..QueryOptions::default() };
+    let refused = process_query(&proxy, &state.db, user_id, "hello", None, &[], unlisted).await.unwrap_err();
+    assert_eq!(refused.code(), "model_not_allowed");
+
+    let empty = test::TestRequest::put()
+        .uri("/proxy/api-key")
+        .insert_header(("Authorization", auth))
+        .set_json(json!({ "api_key": "  " }))
+        .send_request(&app)
+        .await;
+    assert_eq!(empty.status(), 400);
+}
+
+#[actix_web::test]
+async fn test_query_delivery_mode() {
+    let state = AppState::new(Settings::new().unwrap()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/proxy/query", web::post().to(query))
+    ).await;
+    let (_, auth) = register_user(&state).await;
+    let prompt = "hello ".repeat(100);
+    let expected = format!("Query received: {}", prompt);
+
+    // Streamed as a chunked body unless the client asks otherwise
+    for stream in [None, Some(true)] {
+        let streamed = test::TestRequest::post()
+            .uri("/proxy/query")
+            .insert_header(("Authorization", auth.clone()))
+            .set_json(json!({ "text": prompt, "stream": stream }))
+            .send_request(&app)
+            .await;
+        assert_eq!(streamed.status(), 200);
+        assert_eq!(streamed.response().body().size(), BodySize::Stream, "stream: {:?}", stream);
+        assert_eq!(test::read_body(streamed).await, expected.as_bytes());
+    }
+
+    let whole = test::TestRequest::post()
+        .uri("/proxy/query")
+        .insert_header(("Authorization", auth))
+        .set_json(json!({ "text": prompt, "stream": false }))
+        .send_request(&app)
+        .await;
+    assert_eq!(whole.status(), 200);
+    let body: serde_json::Value = test::read_body_json(whole).await;
+    assert_eq!(body["text"], expected);
+}
@@ -0,0 +1,147 @@
+use actix_web::middleware::from_fn;
+use actix_web::{test, web, App};
+use buddybot_server::middleware::{verify_signature, SIGNATURE_HEADER};
+use buddybot_server::webhooks::sign;
+use buddybot_server::scaling::handlers::{heartbeat_batch, scaling_recommendation};
+use buddybot_server::scaling::SystemMetrics;
+use buddybot_server::{AppState, Settings};
+use chrono::Utc;
+use serde_json::json;
+use uuid::Uuid;
+
+#[actix_web::test]
+async fn test_recommendation_reports_scale_up() {
+    let state = AppState::new(Settings::new().unwrap()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/scaling/recommendation", web::get().to(scaling_recommendation))
+    ).await;
+
+    let req = test::TestRequest::get().uri("/scaling/recommendation").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["action"], "hold");
+    assert_eq!(body["factor"], 1.0);
+
+    let instance_id = state.scaling.register_instance("localhost".to_string(), 8080).await;
+    let high_load = SystemMetrics {
+        cpu_usage: 95.0,
+        memory_used: 9000,
+        memory_total: 10000,
+        connection_count: 5000,
+        active_users: 4000,
+        request_rate: 500.0,
+        error_rate: 0.0,
+        response_time_p95: 1.2,
+        timestamp: Utc::now(),
+    };
+    state.scaling.update_instance_metrics(instance_id, high_load).await.unwrap();
+
+    let req = test::TestRequest::get().uri("/scaling/recommendation").to_request();
+    let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(body["action"], "scale_up");
+    assert_eq!(body["factor"], 1.5);
+}
+
+#[actix_web::test]
+async fn test_heartbeat_batch_applies_valid_entries() {
+    let mut config = Settings::new().unwrap();
+    config.admin.token = Some("test-admin-token".to_string());
+    let state = AppState::new(config).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/scaling/heartbeat/batch", web::post().to(heartbeat_batch))
+    ).await;
+
+    let first = state.scaling.register_instance("localhost".to_string(), 8080).await;
+    let second = state.scaling.register_instance("localhost".to_string(), 8081).await;
+    let metrics = |cpu_usage: f32| json!({
+        "cpu_usage": cpu_usage,
+        "memory_used": 4000,
+        "memory_total": 10000,
+        "connection_count": 10,
+        "active_users": 5,
+        "request_rate": 1.0,
+        "error_rate": 0.0,
+        "response_time_p95": 0.1,
+        "timestamp": Utc::now(),
+    });
+    let batch = json!([
+        { "instance_id": first, "metrics": metrics(40.0) },
+        { "instance_id": second, "metrics": metrics(250.0) },
+        { "instance_id": Uuid::new_v4(), "metrics": metrics(40.0) },
+        { "instance_id": "not-a-uuid" },
+        { "instance_id": second, "metrics": metrics(60.0) },
+    ]);
+
+    let response = test::TestRequest::post()
+        .uri("/scaling/heartbeat/batch")
+        .set_json(&batch)
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 401);
+
+    let response = test::TestRequest::post()
+        .uri("/scaling/heartbeat/batch")
+        .insert_header(("X-Admin-Token", "test-admin-token"))
+        .set_json(&batch)
+        .send_request(&app)
+        .await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["applied"], 2);
+    let failed: Vec<u64> = body["errors"].as_array().unwrap().iter().map(|e| e["index"].as_u64().unwrap()).collect();
+    assert_eq!(failed, vec![1, 2, 3]);
+    assert_eq!(body["errors"][1]["error"], "Instance not found");
+
+    // Both valid reports landed; the later one for `second` is the one kept
+    let aggregate = state.scaling.aggregate_metrics().await.unwrap();
+    assert_eq!(aggregate.instance_count, 2);
+    assert_eq!(aggregate.avg_cpu, 50.0);
+}
+
+#[actix_web::test]
+async fn test_heartbeat_batch_requires_signature() {
+    let mut config = Settings::new().unwrap();
+    config.admin.token = Some("test-admin-token".to_string());
+    config.admin.signing_secret = Some("test-signing-secret".to_string());
+    let state = AppState::new(config).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .service(
+                web::resource("/scaling/heartbeat/batch")
+                    .wrap(from_fn(verify_signature))
+                    .route(web::post().to(heartbeat_batch)),
+            )
+    ).await;
+
+    let body = json!([{ "instance_id": Uuid::new_v4() }]).to_string();
+    let request = |body: &str, signature: Option<String>| {
+        let mut request = test::TestRequest::post()
+            .uri("/scaling/heartbeat/batch")
+            .insert_header(("X-Admin-Token", "test-admin-token"))
+            .insert_header(("Content-Type", "application/json"))
+            .set_payload(body.to_string());
+        if let Some(signature) = signature {
+            request = request.insert_header((SIGNATURE_HEADER, signature));
+        }
+        request
+    };
+
+    // The handler still sees the body after it was checked
+    let response = request(&body, Some(sign("test-signing-secret", body.as_bytes()))).send_request(&app).await;
+    assert_eq!(response.status(), 200);
+    let response: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(response["errors"][0]["index"], 0);
+
+    let tampered = json!([{ "instance_id": Uuid::new_v4() }]).to_string();
+    let response = request(&tampered, Some(sign("test-signing-secret", body.as_bytes()))).send_request(&app).await;
+    assert_eq!(response.status(), 401);
+
+    let response = request(&body, None).send_request(&app).await;
+    assert_eq!(response.status(), 401);
+    let response: serde_json::Value = test::read_body_json(response).await;
+    assert!(response["error"]["message"].as_str().unwrap().contains("signature required"));
+}
@@ -0,0 +1,77 @@
+use actix_web::{test, web, App};
+use buddybot_server::{AppState, Settings, auth::handlers::register};
+use buddybot_server::users::handlers::{get_setting, put_setting, MAX_SETTING_VALUE_BYTES};
+use serde_json::json;
+use uuid::Uuid;
+
+#[actix_web::test]
+async fn test_settings_round_trip_and_size_cap() {
+    let config = Settings::new().unwrap();
+    let state = AppState::new(config.clone()).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/auth/register", web::post().to(register))
+            .route("/users/me/settings/{key}", web::get().to(get_setting))
+            .route("/users/me/settings/{key}", web::put().to(put_setting))
+    ).await;
+
+    let register_response = test::TestRequest::post()
+        .uri("/auth/register")
+        .set_json(json!({
+            "email": format!("test-{}@example.com", Uuid::new_v4()),
+            "password": "password123"
+        }))
+        .send_request(&app)
+        .await;
+    let register_body: serde_json::Value = test::read_body_json(register_response).await;
+    let auth = format!("Bearer {}", register_body["token"].as_str().unwrap());
+
+    // Settings require an authenticated caller
+    let anonymous = test::TestRequest::get()
+        .uri("/users/me/settings/theme")
+        .send_request(&app)
+        .await;
+    assert_eq!(anonymous.status(), 401);
+
+    let missing = test::TestRequest::get()
+        .uri("/users/me/settings/theme")
+        .insert_header(("Authorization", auth.clone()))
+        .send_request(&app)
+        .await;
+    assert_eq!(missing.status(), 404);
+
+    let put = test::TestRequest::put()
+        .uri("/users/me/settings/theme")
+        .insert_header(("Authorization", auth.clone()))
+        .set_json(json!({"mode": "dark", "accent": "blue"}))
+        .send_request(&app)
+        .await;
+    assert_eq!(put.status(), 200);
+
+    let get = test::TestRequest::get()
+        .uri("/users/me/settings/theme")
+        .insert_header(("Authorization", auth.clone()))
+        .send_request(&app)
+        .await;
+    assert_eq!(get.status(), 200);
+    let body: serde_json::Value = test::read_body_json(get).await;
+    assert_eq!(body["value"], json!({"mode": "dark", "accent": "blue"}));
+
+    // Oversized values are rejected and leave the stored value untouched
+    let oversized = test::TestRequest::put()
+        .uri("/users/me/settings/theme")
+        .insert_header(("Authorization", auth.clone()))
+        .set_json(json!("x".repeat(MAX_SETTING_VALUE_BYTES)))
+        .send_request(&app)
+        .await;
+    assert_eq!(oversized.status(), 400);
+
+    let get = test::TestRequest::get()
+        .uri("/users/me/settings/theme")
+        .insert_header(("Authorization", auth))
+        .send_request(&app)
+        .await;
+    let body: serde_json::Value = test::read_body_json(get).await;
+    assert_eq!(body["value"]["mode"], "dark");
+}
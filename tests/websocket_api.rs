@@ -0,0 +1,453 @@
+use actix_web::{test, web, App, HttpServer};
+use buddybot_server::admin::handlers::{send_notification, user_stats};
+use buddybot_server::websocket::{websocket_route, ServerMessage};
+use buddybot_server::scaling::SystemMetrics;
+use buddybot_server::{AppState, RateLimitConfig, RateLimiter, Settings, WebSocketServer};
+use futures::{SinkExt, StreamExt};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+use uuid::Uuid;
+
+/// Serve `/ws` for `state` on an ephemeral port, returning its address
+fn spawn_server(state: AppState) -> std::net::SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/ws", web::get().to(websocket_route))
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_web::rt::spawn(server);
+    addr
+}
+
+async fn session_token(state: &AppState) -> String {
+    let email = format!("ws-{}@example.com", Uuid::new_v4());
+    state.auth_service.register(&email, "password123", None, "127.0.0.1").await.unwrap();
+    state.auth_service.authenticate(&email, "password123", "127.0.0.1").await.unwrap()
+}
+
+async fn next_json<S>(stream: &mut S) -> serde_json::Value
+where
+    S: futures::Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin,
+{
+    loop {
+        let message = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("timed out waiting for a message")
+            .unwrap()
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(message.to_text().unwrap()).unwrap();
+        // Heartbeats start at a random point in the interval, so may arrive at any time
+        if json["type"] != "ping" {
+            return json;
+        }
+    }
+}
+
+#[actix_web::test]
+async fn test_handshake_token_authenticates() {
+    let state = AppState::new(Settings::new().unwrap()).await.unwrap();
+    let token = session_token(&state).await;
+    let addr = spawn_server(state);
+
+    let (mut ws, _) = connect_async(format!("ws://{}/ws?token={}", addr, token)).await.unwrap();
+
+    // The session reports success without an auth message and accepts queries at once
+    let auth = next_json(&mut ws).await;
+    assert_eq!(auth["type"], "auth_result");
+    assert_eq!(auth["payload"]["success"], true);
+
+    let query = json!({ "type": "query", "payload": { "text": "hello" } });
+    ws.send(Message::Text(query.to_string())).await.unwrap();
+    let chunk = next_json(&mut ws).await;
+    assert_eq!(chunk["type"], "response_chunk");
+    assert_eq!(chunk["payload"]["text"], "Query received: hello");
+    assert_eq!(next_json(&mut ws).await["type"], "response_end");
+
+    let query = json!({ "type": "query", "payload": { "text": "hello", "stream": false } });
+    ws.send(Message::Text(query.to_string())).await.unwrap();
+    let response = next_json(&mut ws).await;
+    assert_eq!(response["type"], "response");
+    assert_eq!(response["payload"]["text"], "Query received: hello");
+}
+
+#[actix_web::test]
+async fn test_without_handshake_token_requires_auth_message() {
+    let state = AppState::new(Settings::new().unwrap()).await.unwrap();
+    let token = session_token(&state).await;
+    let addr = spawn_server(state);
+
+    let (mut ws, _) = connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+
+    let query = json!({ "type": "query", "payload": { "text": "hello" } });
+    ws.send(Message::Text(query.to_string())).await.unwrap();
+    let response = next_json(&mut ws).await;
+    assert_eq!(response["type"], "auth_required");
+
+    let auth = json!({ "type": "auth", "payload": { "token": token } });
+    ws.send(Message::Text(auth.to_string())).await.unwrap();
+    assert_eq!(next_json(&mut ws).await["payload"]["success"], true);
+}
+
+#[actix_web::test]
+async fn test_upgrade_refused_without_token_when_required() {
+    let mut config = Settings::new().unwrap();
+    config.websocket.require_auth_on_connect = true;
+    let state = AppState::new(config).await.unwrap();
+    let token = session_token(&state).await;
+    let addr = spawn_server(state);
+
+    for url in [format!("ws://{}/ws", addr), format!("ws://{}/ws?token=not-a-session", addr)] {
+        match connect_async(url).await {
+            Err(tokio_tungstenite::tungstenite::Error::Http(response)) => {
+                assert_eq!(response.status(), 401);
+            }
+            other => panic!("expected the upgrade to be refused, got {:?}", other.map(|(_, r)| r.status())),
+        }
+    }
+
+    // A valid token still connects
+    let (mut ws, _) = connect_async(format!("ws://{}/ws?token={}", addr, token)).await.unwrap();
+    assert_eq!(next_json(&mut ws).await["payload"]["success"], true);
+}
+
+/// Upgrade request for `url` offering `protocols` in `Sec-WebSocket-Protocol`
+fn request_with_protocols(url: String, protocols: &str) -> tokio_tungstenite::tungstenite::handshake::client::Request {
+    let mut request = url.into_client_request().unwrap();
+    request.headers_mut().insert("Sec-WebSocket-Protocol", protocols.parse().unwrap());
+    request
+}
+
+#[actix_web::test]
+async fn test_subprotocol_negotiated() {
+    let mut config = Settings::new().unwrap();
+    config.websocket.require_subprotocol = true;
+    let state = AppState::new(config).await.unwrap();
+    let token = session_token(&state).await;
+    let addr = spawn_server(state);
+
+    let request = request_with_protocols(format!("ws://{}/ws?token={}", addr, token), "chat, buddybot.v1");
+    let (mut ws, response) = connect_async(request).await.unwrap();
+    assert_eq!(response.headers().get("Sec-WebSocket-Protocol").unwrap(), "buddybot.v1");
+    assert_eq!(next_json(&mut ws).await["payload"]["success"], true);
+
+    // A token carried as a subprotocol authenticates, but only the supported
+    // protocol is echoed, never the token
+    let request = request_with_protocols(format!("ws://{}/ws", addr), &format!("bearer.{}, buddybot.v1", token));
+    let (mut ws, response) = connect_async(request).await.unwrap();
+    assert_eq!(response.headers().get("Sec-WebSocket-Protocol").unwrap(), "buddybot.v1");
+    assert_eq!(next_json(&mut ws).await["payload"]["success"], true);
+
+    // Offering only the token, or only unsupported protocols, doesn't satisfy the requirement
+    for offered in [format!("bearer.{}", token), "chat, buddybot.v2".to_string()] {
+        let request = request_with_protocols(format!("ws://{}/ws?token={}", addr, token), &offered);
+        match connect_async(request).await {
+            Err(tokio_tungstenite::tungstenite::Error::Http(response)) => assert_eq!(response.status(), 400),
+            other => panic!("expected the upgrade to be refused, got {:?}", other.map(|(_, r)| r.status())),
+        }
+    }
+}
+
+#[actix_web::test]
+async fn test_idle_connection_closed_despite_pongs() {
+    let mut config = Settings::new().unwrap();
+    config.websocket.idle_query_timeout_secs = 1;
+    let state = AppState::new(config).await.unwrap();
+    let token = session_token(&state).await;
+    let addr = spawn_server(state);
+
+    let (mut ws, _) = connect_async(format!("ws://{}/ws?token={}", addr, token)).await.unwrap();
+    assert_eq!(next_json(&mut ws).await["payload"]["success"], true);
+
+    let pong = json!({ "type": "pong" }).to_string();
+    ws.send(Message::Text(pong.clone())).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    ws.send(Message::Text(pong)).await.unwrap();
+
+    let idle = next_json(&mut ws).await;
+    assert_eq!(idle["type"], "error");
+    assert_eq!(idle["payload"]["code"], "idle_timeout");
+    let closed = tokio::time::timeout(Duration::from_secs(5), ws.next()).await.unwrap();
+    assert!(matches!(closed, Some(Ok(Message::Close(_))) | None));
+}
+
+#[actix_web::test]
+async fn test_offline_message_delivered_on_reconnect() {
+    let state = AppState::new(Settings::new().unwrap()).await.unwrap();
+    let token = session_token(&state).await;
+    let user = state.auth_service.validate_token(&token).await.unwrap();
+
+    let notice = ServerMessage::Response { text: "while you were away".to_string() };
+    assert!(!state.ws_server.send_to_user(user.id, &notice).await.unwrap());
+
+    let addr = spawn_server(state.clone());
+    let (mut ws, _) = connect_async(format!("ws://{}/ws?token={}", addr, token)).await.unwrap();
+    assert_eq!(next_json(&mut ws).await["payload"]["success"], true);
+
+    let replayed = next_json(&mut ws).await;
+    assert_eq!(replayed["type"], "response");
+    assert_eq!(replayed["payload"]["text"], "while you were away");
+
+    // Once connected, messages go straight through instead of being queued
+    let live = ServerMessage::Response { text: "live".to_string() };
+    assert!(state.ws_server.send_to_user(user.id, &live).await.unwrap());
+    assert_eq!(next_json(&mut ws).await["payload"]["text"], "live");
+}
+
+#[actix_web::test]
+async fn test_queries_deferred_while_overloaded() {
+    let mut config = Settings::new().unwrap();
+    config.websocket.busy_cpu_threshold = 90.0;
+    config.websocket.busy_retry_after_ms = 2000;
+    let state = AppState::new(config).await.unwrap();
+    let token = session_token(&state).await;
+
+    // One instance reporting a CPU well over the threshold
+    let instance = state.scaling.register_instance("127.0.0.1".to_string(), 8080).await;
+    state.scaling.update_instance_metrics(instance, SystemMetrics {
+        cpu_usage: 99.0,
+        memory_used: 1,
+        memory_total: 2,
+        connection_count: 10,
+        active_users: 1,
+        request_rate: 0.0,
+        error_rate: 0.0,
+        response_time_p95: 0.0,
+        timestamp: chrono::Utc::now(),
+    }).await.unwrap();
+    let backpressure = state.ws_server.backpressure();
+    backpressure.observe(state.scaling.aggregate_metrics().await.as_ref());
+
+    let addr = spawn_server(state);
+    let (mut ws, _) = connect_async(format!("ws://{}/ws?token={}", addr, token)).await.unwrap();
+    assert_eq!(next_json(&mut ws).await["payload"]["success"], true);
+
+    let query = json!({ "type": "query", "payload": { "text": "hello", "stream": false } });
+    ws.send(Message::Text(query.to_string())).await.unwrap();
+    let busy = next_json(&mut ws).await;
+    assert_eq!(busy["type"], "busy");
+    assert_eq!(busy["payload"]["retry_after_ms"], 2000);
+
+    // Once load drops the same query goes through
+    backpressure.observe(None);
+    ws.send(Message::Text(query.to_string())).await.unwrap();
+    assert_eq!(next_json(&mut ws).await["type"], "response");
+}
+
+#[actix_web::test]
+async fn test_query_rate_limit_follows_user_tier() {
+    let config = Settings::new().unwrap();
+    let mut state = AppState::new(config).await.unwrap();
+
+    let mut limits = HashMap::new();
+    limits.insert("standard".to_string(), 1);
+    limits.insert("premium".to_string(), 3);
+    let limiter = Arc::new(RateLimiter::new(RateLimitConfig {
+        window_size: chrono::Duration::minutes(1),
+        limits,
+    }));
+    state.ws_server = Arc::new(
+        WebSocketServer::new(state.auth_service.clone(), state.ws_server.proxy(), state.db.clone())
+            .with_rate_limiter(limiter),
+    );
+
+    let standard = session_token(&state).await;
+    let premium = session_token(&state).await;
+    let premium_user = state.auth_service.validate_token(&premium).await.unwrap();
+    state.db.set_rate_limit_tier(premium_user.id, "premium").await.unwrap();
+
+    let addr = spawn_server(state);
+    let query = json!({ "type": "query", "payload": { "text": "hello", "stream": false } }).to_string();
+
+    let (mut ws, _) = connect_async(format!("ws://{}/ws?token={}", addr, premium)).await.unwrap();
+    assert_eq!(next_json(&mut ws).await["payload"]["success"], true);
+    for _ in 0..2 {
+        ws.send(Message::Text(query.clone())).await.unwrap();
+        assert_eq!(next_json(&mut ws).await["type"], "response");
+    }
+
+    let (mut ws, _) = connect_async(format!("ws://{}/ws?token={}", addr, standard)).await.unwrap();
+    assert_eq!(next_json(&mut ws).await["payload"]["success"], true);
+    ws.send(Message::Text(query.clone())).await.unwrap();
+    assert_eq!(next_json(&mut ws).await["type"], "response");
+    ws.send(Message::Text(query)).await.unwrap();
+    let refused = next_json(&mut ws).await;
+    assert_eq!(refused["type"], "error");
+    assert_eq!(refused["payload"]["code"], "rate_limited");
+}
+
+#[actix_web::test]
+async fn test_connections_capped_per_ip() {
+    let mut config = Settings::new().unwrap();
+    config.websocket.max_connections_per_ip = 2;
+    config.server.trust_proxy = true;
+    let state = AppState::new(config).await.unwrap();
+    let addr = spawn_server(state);
+
+    let from = |ip: &str| {
+        let mut request = format!("ws://{}/ws", addr).into_client_request().unwrap();
+        request.headers_mut().insert("X-Forwarded-For", ip.parse().unwrap());
+        request
+    };
+
+    let (first, _) = connect_async(from("203.0.113.7")).await.unwrap();
+    let (_second, _) = connect_async(from("203.0.113.7")).await.unwrap();
+    match connect_async(from("203.0.113.7")).await {
+        Err(tokio_tungstenite::tungstenite::Error::Http(response)) => assert_eq!(response.status(), 429),
+        other => panic!("expected the upgrade to be refused, got {:?}", other.map(|(_, r)| r.status())),
+    }
+    // Another address is unaffected
+    connect_async(from("203.0.113.8")).await.unwrap();
+
+    // Closing a connection frees its slot once the server notices
+    drop(first);
+    let mut reconnected = false;
+    for _ in 0..50 {
+        if connect_async(from("203.0.113.7")).await.is_ok() {
+            reconnected = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(reconnected, "slot was not released after disconnect");
+}
+
+#[actix_web::test]
+async fn test_forged_forwarded_hops_share_the_proxy_seen_address() {
+    let mut config = Settings::new().unwrap();
+    config.websocket.max_connections_per_ip = 2;
+    config.server.trust_proxy = true;
+    let state = AppState::new(config).await.unwrap();
+    let addr = spawn_server(state);
+
+    // The client forges a new leftmost hop each time; the proxy appends the
+    // address it actually saw, which is what the cap counts against
+    let forged = |n: usize| {
+        let mut request = format!("ws://{}/ws", addr).into_client_request().unwrap();
+        let hops = format!("198.51.100.{}, 203.0.113.7", n);
+        request.headers_mut().insert("X-Forwarded-For", hops.parse().unwrap());
+        request
+    };
+
+    let (_first, _) = connect_async(forged(1)).await.unwrap();
+    let (_second, _) = connect_async(forged(2)).await.unwrap();
+    match connect_async(forged(3)).await {
+        Err(tokio_tungstenite::tungstenite::Error::Http(response)) => assert_eq!(response.status(), 429),
+        other => panic!("expected the upgrade to be refused, got {:?}", other.map(|(_, r)| r.status())),
+    }
+}
+
+#[actix_web::test]
+async fn test_admin_notification_to_user() {
+    let mut config = Settings::new().unwrap();
+    config.admin.token = Some("test-admin-token".to_string());
+    let state = AppState::new(config).await.unwrap();
+    let (alice, bob) = (session_token(&state).await, session_token(&state).await);
+    let alice_id = state.auth_service.validate_token(&alice).await.unwrap().id;
+    let addr = spawn_server(state.clone());
+    let admin = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/admin/notifications", web::post().to(send_notification))
+    ).await;
+
+    let (mut alice_ws, _) = connect_async(format!("ws://{}/ws?token={}", addr, alice)).await.unwrap();
+    let (mut bob_ws, _) = connect_async(format!("ws://{}/ws?token={}", addr, bob)).await.unwrap();
+    assert_eq!(next_json(&mut alice_ws).await["payload"]["success"], true);
+    assert_eq!(next_json(&mut bob_ws).await["payload"]["success"], true);
+    // Let both sessions register with the pool
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let notify = |body: serde_json::Value| test::TestRequest::post()
+        .uri("/admin/notifications")
+        .insert_header(("X-Admin-Token", "test-admin-token"))
+        .set_json(body);
+
+    let response = notify(json!({
+        "user_id": alice_id,
+        "level": "warning",
+        "title": "Quota",
+        "body": "You have used 90% of this month's queries",
+    }))
+    .send_request(&admin)
+    .await;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = test::read_body_json(response).await;
+    assert_eq!(body["delivered"], true);
+
+    let notification = next_json(&mut alice_ws).await;
+    assert_eq!(notification, json!({
+        "type": "notification",
+        "payload": { "level": "warning", "title": "Quota", "body": "You have used 90% of this month's queries" }
+    }));
+
+    // Bob's next message is the broadcast, so he never saw Alice's
+    let response = notify(json!({ "title": "Maintenance", "body": "Back in five minutes" })).send_request(&admin).await;
+    assert_eq!(response.status(), 200);
+    let broadcast = next_json(&mut bob_ws).await;
+    assert_eq!(broadcast["payload"]["title"], "Maintenance");
+    assert_eq!(broadcast["payload"]["level"], "info");
+
+    let response = notify(json!({ "level": "urgent", "title": "Hi", "body": "there" })).send_request(&admin).await;
+    assert_eq!(response.status(), 400);
+    let response = notify(json!({ "user_id": Uuid::new_v4(), "title": "Hi", "body": "there" })).send_request(&admin).await;
+    assert_eq!(response.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_queries_counted_in_user_stats() {
+    let mut config = Settings::new().unwrap();
+    config.admin.token = Some("test-admin-token".to_string());
+    let state = AppState::new(config).await.unwrap();
+    let token = session_token(&state).await;
+    let user_id = state.auth_service.validate_token(&token).await.unwrap().id;
+    let addr = spawn_server(state.clone());
+    let admin = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state.clone()))
+            .route("/admin/users/{id}/stats", web::get().to(user_stats))
+    ).await;
+    let stats = || async {
+        let response = test::TestRequest::get()
+            .uri(&format!("/admin/users/{}/stats", user_id))
+            .insert_header(("X-Admin-Token", "test-admin-token"))
+            .send_request(&admin)
+            .await;
+        assert_eq!(response.status(), 200);
+        test::read_body_json::<serde_json::Value, _>(response).await
+    };
+
+    let before = stats().await;
+    assert_eq!(before["query_count"], 0);
+    assert!(before["last_query_at"].is_null());
+
+    let (mut ws, _) = connect_async(format!("ws://{}/ws?token={}", addr, token)).await.unwrap();
+    assert_eq!(next_json(&mut ws).await["payload"]["success"], true);
+    let query = json!({ "type": "query", "payload": { "text": "hello", "stream": false } }).to_string();
+    for _ in 0..2 {
+        ws.send(Message::Text(query.clone())).await.unwrap();
+        assert_eq!(next_json(&mut ws).await["type"], "response");
+    }
+
+    let counted = stats().await;
+    assert_eq!(counted["query_count"], 2);
+    assert_eq!(counted["connections"], 1);
+    assert!(counted["last_query_at"].is_string());
+
+    // Flushed counts are read back from the database and new ones add to them
+    assert_eq!(state.ws_server.query_stats().flush().await.unwrap(), 1);
+    assert_eq!(stats().await["query_count"], 2);
+    ws.send(Message::Text(query)).await.unwrap();
+    assert_eq!(next_json(&mut ws).await["type"], "response");
+    assert_eq!(stats().await["query_count"], 3);
+}